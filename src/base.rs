@@ -1,4 +1,4 @@
-use std::sync::mpsc::{Sender, Receiver};
+use std::sync::mpsc::{self, Sender, Receiver};
 use clap::{Parser, ArgAction};
 use regex::bytes::Regex;
 use once_cell::sync::Lazy;
@@ -7,7 +7,8 @@ use std::io::{BufRead, IsTerminal};
 use bstr::{BStr, BString, ByteSlice, ByteVec};
 use std::process::{ExitCode};
 use crate::utils::{Break, MaybeBreak};
-use anyhow::{Result};
+use anyhow::{Result, bail};
+use unicode_width::UnicodeWidthChar;
 use crate::io::{Reader};
 
 const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
@@ -40,17 +41,66 @@ pub enum Message {
     Ofs(Ofs),
     Stderr(Row),
     RawStderr(BString, bool, bool),
+    Alignment(Vec<Option<Alignment>>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, clap::ValueEnum)]
+pub enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+// compare the first row to the ones read ahead after it
+fn decide_header<R>(reader: &mut Reader<R>, base: &mut Base) -> Result<()> {
+    let sample: Vec<_> = reader.lookahead.iter()
+        .skip(1)
+        .take(reader.lookahead.len().saturating_sub(base.opts.skip_trailer + 1))
+        .map(|(row, _, _)| row.as_slice())
+        .collect();
+    let first = &reader.lookahead[0].0;
+    let decision = crate::header::detect(first, &sample);
+    if base.opts.explain_header {
+        let verdict = if decision.is_header { "is a header" } else { "is not a header" };
+        base.log(format!("first row {verdict} ({})\n", decision.reason))?;
+    }
+    base.header_len = decision.is_header.then_some(first.len());
+    reader.lookahead[0].1 = decision.is_header;
+    Ok(())
 }
 
 pub fn no_ansi_colour_len(val: &BStr) -> usize {
-    ANSI.split(val).map(|x| x.len()).sum()
+    // terminal columns, so wide characters count as two and combining marks as none
+    ANSI.split(val).flat_map(|x| x.chars()).map(|c| c.width().unwrap_or(0)).sum()
 }
 
+// how much to pad each column of each row so they line up
+pub fn padding<'a, I: Iterator<Item=&'a [BString]>>(rows: I) -> Vec<Vec<usize>> {
+    let widths: Vec<Vec<_>> = rows
+        .map(|row| row.iter().map(|col| no_ansi_colour_len(col.as_ref())).collect())
+        .collect();
+
+    let max_col = widths.iter().map(|w| w.len()).max().unwrap_or(0);
+    let max_widths: Vec<_> = (0 .. max_col).map(|i|
+        widths.iter().filter_map(|w| w.get(i)).max().copied().unwrap_or(0)
+    ).collect();
+
+    // don't pad the last column
+    widths.iter()
+        .map(|w| w.iter().zip(&max_widths).take(w.len().saturating_sub(1)).map(|(w, m)| m - w).collect())
+        .collect()
+}
+
+/// How the fields of a line are separated.
 #[derive(Debug, Clone)]
 pub enum Ifs {
+    /// Split on matches of a regex.
     Regex(Regex),
+    /// Split on an exact string, e.g. `,` or a tab.
     Plain(BString),
+    /// Split on runs of whitespace.
     Space,
+    /// Split on two or more spaces, as in the pretty output.
     Pretty,
 }
 
@@ -170,11 +220,65 @@ pub struct BaseOptions {
     pub no_quoting: bool,
     #[arg(global = true, long = "no-quote-output", default_value_t = true, action = ArgAction::SetFalse, help = "don't quote output")]
     pub quote_output: bool,
+    #[arg(global = true, long, value_name = "CHAR", value_parser = crate::utils::parse_char, help = "quote character (default: \")")]
+    pub quote_char: Option<u8>,
+    #[arg(global = true, long, value_name = "CHAR", value_parser = crate::utils::parse_char, help = "escape character, e.g. with \\ then \\, is a literal comma and \\n is a newline")]
+    pub escape_char: Option<u8>,
+    #[arg(global = true, long, value_name = "STRING", help = "read this unquoted value as empty and write empty values as it in delimited output (null in json), e.g. \\N")]
+    pub null_string: Option<String>,
+    #[arg(global = true, long, value_name = "N|auto", value_parser = parse_skip_lines, help = "skip this many lines before the header, or auto to find the header")]
+    pub skip_lines: Option<SkipLines>,
+    #[arg(global = true, long, value_name = "PREFIX", help = "skip lines starting with this prefix")]
+    pub comment_prefix: Option<String>,
+    #[arg(global = true, long, value_name = "N", default_value_t = 0, help = "skip this many rows at the end of the input")]
+    pub skip_trailer: usize,
+    #[arg(global = true, long, help = "ignore field names that are not in the header instead of failing")]
+    pub lenient_fields: bool,
+    #[arg(global = true, long, value_enum, default_value_t = crate::header::DedupeHeader::Keep, help = "what to do with repeated header names, suffix and error also name empty ones column_N")]
+    pub dedupe_header: crate::header::DedupeHeader,
+    #[arg(global = true, long, help = "fail on ragged rows and unterminated quotes")]
+    pub strict: bool,
+    #[arg(global = true, long, value_enum, value_name = "ACTION", help = "what to do with rows that have a different number of fields to the header (or the first row)")]
+    pub on_ragged: Option<Ragged>,
+    #[arg(global = true, long, help = "explain on stderr whether the first row was taken as a header and why")]
+    pub explain_header: bool,
+    #[arg(global = true, long, value_enum, help = "compress the output")]
+    pub compress: Option<crate::io::Compression>,
+    #[arg(global = true, long, value_enum, help = "input encoding, auto guesses from the byte order mark and contents")]
+    pub encoding: Option<crate::encoding::Encoding>,
+    #[arg(global = true, long, value_name = "ENCODING", value_parser = crate::encoding::parse_output_encoding, help = "output encoding [possible values: utf-8, utf-16le, utf-16be, latin1, cp1252]")]
+    pub output_encoding: Option<crate::encoding::Encoding>,
 
     #[clap(skip)]
     pub inner: BaseOptionsInner,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SkipLines {
+    Count(usize),
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Ragged {
+    // pad short rows with empty fields
+    Pad,
+    // cut long rows down
+    Truncate,
+    Error,
+    Skip,
+    // write them to stderr instead
+    Stderr,
+}
+
+fn parse_skip_lines(value: &str) -> Result<SkipLines, String> {
+    if value == "auto" {
+        Ok(SkipLines::Auto)
+    } else {
+        value.parse().map(SkipLines::Count).map_err(|_| "must be a number or auto".into())
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct BaseOptionsInner {
     pub trailer: AutoChoices,
@@ -185,11 +289,16 @@ pub struct BaseOptionsInner {
     pub is_stdout_tty: bool,
     pub is_stderr_tty: bool,
     pub stderr_colour: bool,
+    pub redirect: crate::io::Redirect,
 }
 
 impl BaseOptions {
+    pub fn quote_char(&self) -> u8 {
+        self.quote_char.unwrap_or(b'"')
+    }
+
     pub fn post_process(&mut self, is_stdout_tty: Option<bool>) {
-        self.inner.is_stdout_tty = is_stdout_tty.unwrap_or_else(|| std::io::stdout().is_terminal());
+        self.inner.is_stdout_tty = is_stdout_tty.unwrap_or_else(|| !self.inner.redirect.has_output() && std::io::stdout().is_terminal());
         self.inner.is_stderr_tty = std::io::stderr().is_terminal();
 
         if self.no_header {
@@ -197,6 +306,9 @@ impl BaseOptions {
         } else if self.header == Some(false) {
             self.header = None;
         }
+        if self.strict && self.on_ragged.is_none() {
+            self.on_ragged = Some(Ragged::Error);
+        }
         if self.irs.is_none() {
             self.irs = Some("\n".into());
         }
@@ -246,10 +358,17 @@ pub trait Processor<W: Writer + Send + 'static=BaseWriter> {
     fn run(self, base: &mut Base, receiver: Receiver<Message>) -> Result<ExitCode> where Self: Sized {
         self.register_cleanup();
         let mut writer = self.make_writer(base.opts.clone());
-        base.scope.spawn(move || {
+        let writer = base.scope.spawn(move || {
             writer.run(receiver)
         });
-        self.process_file(std::io::stdin().lock(), base, Callbacks::all())
+        let result = self.process_file(base.opts.inner.redirect.stdin(base.opts.encoding), base, Callbacks::all());
+
+        // hang up so the writer can finish, then report e.g. a failure to finish compressing
+        base.sender = mpsc::channel().0;
+        match writer.join() {
+            Ok(Err(e)) if result.is_ok() && e.downcast_ref::<std::io::Error>().is_none_or(|e| e.kind() != std::io::ErrorKind::BrokenPipe) => Err(e),
+            _ => result,
+        }
     }
 
     fn determine_ifs(&self, line: &BStr, opts: &BaseOptions) -> Ifs {
@@ -351,25 +470,36 @@ pub trait Processor<W: Writer + Send + 'static=BaseWriter> {
         (ifs, ofs)
     }
 
-    fn process_one_row<R: BufRead>(
+    // reads the next complete record, spanning lines if needed
+    fn read_record<R: BufRead>(
         &mut self,
         reader: &mut Reader<R>,
         base: &mut Base,
         do_callbacks: Callbacks,
         mut prev_row: Row,
         mut first_read: bool,
-        first_row: bool,
-    ) -> Result<Option<(Row, bool, Row)>> where Self: Sized {
+    ) -> Result<Option<Row>> where Self: Sized {
 
         loop {
             let mut lines = reader.line_reader();
-            while let Some((mut line, last_line)) = lines.get_line(base.irs.as_ref()) {
+            loop {
+                let line_number = lines.line_count() + 1;
+                let Some((mut line, last_line)) = lines.get_line(base.irs.as_ref()) else { break };
 
-                if first_read {
-                    first_read = false;
+                if line_number == 1 {
                     // Remove UTF-8 BOM
                     line = line.strip_prefix(UTF8_BOM).unwrap_or(line).into();
+                }
+                if line_number <= base.skip_lines {
+                    continue
+                }
+                // comments, but not inside a quoted field
+                if prev_row.is_empty() && base.opts.comment_prefix.as_ref().is_some_and(|prefix| line.starts_with(prefix.as_bytes())) {
+                    continue
+                }
 
+                if first_read {
+                    first_read = false;
                     let (ifs, ofs) = self.determine_delimiters(line, &base.opts);
                     base.ifs = ifs;
                     if do_callbacks.contains(Callbacks::ON_OFS) {
@@ -380,20 +510,16 @@ pub trait Processor<W: Writer + Send + 'static=BaseWriter> {
                     }
                 }
 
-                let (row, incomplete) = self.parse_line(base, line, prev_row, b'"')?;
+                if prev_row.is_empty() {
+                    base.line_number = line_number;
+                }
+                let quote = base.opts.quote_char();
+                let (row, incomplete) = self.parse_line(base, line, prev_row, quote)?;
+                if incomplete && lines.is_eof() && last_line {
+                    base.unterminated_quote()?;
+                }
                 if !incomplete || (lines.is_eof() && last_line) {
-
-                    let is_header = if first_row {
-                        // got the first row, is it a header
-                        base.opts.header.unwrap_or_else(|| row.iter().all(|c| matches!(c.first(), Some(b'_' | b'a' ..= b'z' | b'A' ..= b'Z'))))
-                    } else {
-                        false
-                    };
-
-                    if is_header {
-                        base.header_len = Some(row.len());
-                    }
-                    return Ok(Some((row, is_header, vec![])))
+                    return Ok(Some(row))
                 }
 
                 prev_row = row;
@@ -405,12 +531,106 @@ pub trait Processor<W: Writer + Send + 'static=BaseWriter> {
 
             drop(lines);
             if reader.is_eof {
-                return Ok(None)
+                if prev_row.is_empty() {
+                    return Ok(None)
+                }
+                // the input ended inside a quoted field
+                base.unterminated_quote()?;
+                if let Some(last) = prev_row.last_mut() && last.ends_with(&base.irs) {
+                    let len = last.len() - base.irs.len();
+                    last.truncate(len);
+                }
+                return Ok(Some(prev_row))
             }
             reader.read()?;
         }
     }
 
+    // the first line whose field count matches most of the lines after it
+    fn find_header_line<R: BufRead>(&self, reader: &mut Reader<R>, base: &mut Base) -> Result<usize> where Self: Sized {
+        const SAMPLE_LINES: usize = 20;
+        let lines = reader.peek_lines(base.irs.as_ref(), SAMPLE_LINES)?;
+        let lines: Vec<_> = lines.iter()
+            .enumerate()
+            .map(|(i, line)| (i, if i == 0 { line.strip_prefix(UTF8_BOM).unwrap_or(line) } else { line.as_slice() }))
+            .filter(|(_, line)| !base.opts.comment_prefix.as_ref().is_some_and(|prefix| line.starts_with(prefix.as_bytes())))
+            .collect();
+
+        for (i, &(index, line)) in lines.iter().enumerate() {
+            let ifs = self.determine_ifs(line.into(), &base.opts);
+            let counts: Vec<_> = lines[i..].iter().map(|&(_, line)| base.count_fields(line.into(), &ifs)).collect();
+            // the most common count, so a trailer or the odd ragged line doesn't matter
+            let mode = counts[1..].iter().max_by_key(|&&c| (counts[1..].iter().filter(|&&x| x == c).count(), c));
+            if counts[0] > 1 && mode == Some(&counts[0]) {
+                return Ok(index)
+            }
+        }
+        Ok(0)
+    }
+
+    fn process_one_row<R: BufRead>(
+        &mut self,
+        reader: &mut Reader<R>,
+        base: &mut Base,
+        do_callbacks: Callbacks,
+        prev_row: Row,
+        mut first_read: bool,
+        mut first_row: bool,
+    ) -> Result<Option<(Row, bool, Row)>> where Self: Sized {
+
+        if first_read {
+            base.skip_lines = match base.opts.skip_lines {
+                Some(SkipLines::Count(count)) => count,
+                Some(SkipLines::Auto) => self.find_header_line(reader, base)?,
+                None => 0,
+            };
+        }
+
+        let mut prev_row = Some(prev_row);
+        loop {
+            // got the first row, is it a header
+            let mut detect_header = first_row && reader.lookahead.is_empty() && base.opts.header.is_none();
+            // combining columns needs to know the header before parsing the rest, so there is no sample
+            let sample_rows = if detect_header && !base.opts.combine_trailing_columns { crate::header::SAMPLE_ROWS } else { 0 };
+
+            // read far enough ahead to know which rows are the trailer
+            // and to compare the first row to the ones after it
+            while reader.lookahead.len() <= base.opts.skip_trailer.max(sample_rows) {
+                let is_header = first_row && reader.lookahead.is_empty() && base.opts.header == Some(true);
+                let Some(row) = self.read_record(reader, base, do_callbacks, prev_row.take().unwrap_or_default(), first_read)? else { break };
+                first_read = false;
+
+                if is_header {
+                    base.header_len = Some(row.len());
+                }
+                reader.lookahead.push_back((row, is_header, base.line_number));
+
+                if detect_header && reader.lookahead.len() > sample_rows {
+                    detect_header = false;
+                    decide_header(reader, base)?;
+                }
+            }
+            if detect_header && !reader.lookahead.is_empty() {
+                decide_header(reader, base)?;
+            }
+
+            if reader.lookahead.len() <= base.opts.skip_trailer {
+                // only the trailer is left
+                reader.lookahead.clear();
+                return Ok(None)
+            }
+            let (row, is_header, line_number) = reader.lookahead.pop_front().unwrap();
+            base.line_number = line_number;
+            if is_header {
+                return Ok(Some((crate::header::dedupe(row, base.opts.dedupe_header)?, is_header, vec![])))
+            }
+            if let Some(row) = base.fix_ragged(row)? {
+                return Ok(Some((row, is_header, vec![])))
+            }
+            first_row = false;
+        }
+    }
+
     fn process_file<R: BufRead>(mut self, file: R, base: &mut Base, do_callbacks: Callbacks) -> Result<ExitCode> where Self: Sized {
 
         let result = (|| {
@@ -459,6 +679,7 @@ pub trait Processor<W: Writer + Send + 'static=BaseWriter> {
                 },
                 Message::Stderr(row) => Break::when(base.write_stderr(row).is_err()),
                 Message::RawStderr(value, ors, clear) => Break::when(base.write_raw_stderr(value, ors, clear).is_err()),
+                Message::Alignment(alignment) => self.on_alignment(base, alignment),
             };
             match Break::is_break(result) {
                 Ok(true) => break,
@@ -487,6 +708,10 @@ pub trait Processor<W: Writer + Send + 'static=BaseWriter> {
         base.on_header(header)
     }
 
+    fn on_alignment(&mut self, base: &mut Base, alignment: Vec<Option<Alignment>>) -> Result<()> {
+        Ok(base.on_alignment(alignment)?)
+    }
+
     fn on_eof(self, base: &mut Base) -> Result<bool> where Self: Sized {
         base.on_eof()
     }
@@ -510,6 +735,16 @@ pub struct ScopelessBase {
     header_len: Option<usize>,
     pub ifs: Ifs,
     pub irs: BString,
+    // input line on which the last record started
+    pub line_number: usize,
+    // the last line ended in an escaped newline outside of quotes
+    escaped_newline: bool,
+    // number of lines before the header
+    skip_lines: usize,
+    // the file being read, if not stdin
+    pub filename: Option<BString>,
+    // number of fields in the first row, when there is no header
+    row_len: Option<usize>,
 }
 
 #[derive(Clone)]
@@ -531,18 +766,20 @@ impl<'a, 'b> std::ops::DerefMut for Base<'a, 'b> {
     }
 }
 
-impl<'a, 'b> Base<'a, 'b> {
+impl ScopelessBase {
 
-    pub fn new(opts: BaseOptions, sender: Sender<Message>, scope: &'a std::thread::Scope<'a, 'b>) -> Self {
+    pub fn new(opts: BaseOptions, sender: Sender<Message>) -> Self {
         Self {
-            inner: ScopelessBase {
-                sender,
-                header_len: None,
-                ifs: Ifs::Pretty,
-                irs: crate::utils::unescape_str(opts.irs.as_deref().unwrap_or("\n")).into_owned(),
-                opts,
-            },
-            scope,
+            sender,
+            header_len: None,
+            line_number: 0,
+            escaped_newline: false,
+            skip_lines: 0,
+            filename: None,
+            row_len: None,
+            ifs: Ifs::Pretty,
+            irs: crate::utils::unescape_str(opts.irs.as_deref().unwrap_or("\n")).into_owned(),
+            opts,
         }
     }
 
@@ -563,15 +800,17 @@ impl<'a, 'b> Base<'a, 'b> {
         Some((start + m.start(), start + m.end()))
     }
 
-    fn parse_line(&self, line: &BStr, mut row: Row, quote: u8) -> (Row, bool) {
+    pub(crate) fn parse_line(&mut self, line: &BStr, mut row: Row, quote: u8) -> (Row, bool) {
         let allow_quoted = !self.opts.no_quoting;
+        let escape = self.opts.escape_char;
         let maxcols = if self.opts.combine_trailing_columns && let Some(header_len) = self.header_len {
             Some(header_len)
         } else {
             None
         };
+        let escaped_newline = std::mem::take(&mut self.escaped_newline);
 
-        if !allow_quoted || !line.contains(&quote) {
+        if (!allow_quoted || !line.contains(&quote)) && escape.is_none_or(|e| !line.contains(&e)) && !escaped_newline {
             if let Some(last) = row.last_mut() {
                 last.extend_from_slice(line);
                 return (row, true);
@@ -581,26 +820,36 @@ impl<'a, 'b> Base<'a, 'b> {
                 } else {
                     line.split_str(ifs).map(|x| x.into()).collect()
                 };
-                return (row, false);
+                return (self.replace_nulls(row), false);
             } else if let Ifs::Regex(ifs) = &self.ifs {
                 let row = if let Some(maxcols) = maxcols {
                     ifs.splitn(line, maxcols).map(|x| x.into()).collect()
                 } else {
                     ifs.split(line).map(|x| x.into()).collect()
                 };
-                return (row, false);
+                return (self.replace_nulls(row), false);
             }
         }
 
         let mut start = Some(0);
 
         if let Some(last) = row.last_mut() {
-            let (value, i) = Self::extract_column(line, 0, quote);
-            last.extend_from_slice(&value);
-            if let Some(i) = i {
-                start = Self::next_ifs(line, i + 1, &self.ifs).unzip().1;
+            if escaped_newline {
+                let (value, ifs, escaped) = self.extract_unquoted(line, 0);
+                last.extend_from_slice(&value);
+                if escaped {
+                    self.escaped_newline = true;
+                    return (row, true);
+                }
+                start = ifs.map(|(s, e)| e.max(s+1));
             } else {
-                return (row, true);
+                let (value, i) = self.extract_column(line, 0, quote);
+                last.extend_from_slice(&value);
+                if let Some(i) = i {
+                    start = Self::next_ifs(line, i + 1, &self.ifs).unzip().1;
+                } else {
+                    return (row, true);
+                }
             }
         }
 
@@ -609,7 +858,7 @@ impl<'a, 'b> Base<'a, 'b> {
             let row_full = maxcols.is_some_and(|m| row.len() >= m);
 
             if allow_quoted && c == quote {
-                let (value, i) = Self::extract_column(line, s + 1, quote);
+                let (value, i) = self.extract_column(line, s + 1, quote);
                 if row_full {
                     row.last_mut().unwrap().extend_from_slice(&value);
                 } else {
@@ -621,13 +870,24 @@ impl<'a, 'b> Base<'a, 'b> {
                     return (row, true);
                 }
             } else {
-                let se = Self::next_ifs(line, s, &self.ifs).unzip();
+                let (mut value, ifs, escaped) = self.extract_unquoted(line, s);
+                if self.opts.null_string.as_ref().is_some_and(|null| line[s .. ifs.map_or(line.len(), |(s, _)| s)] == null.as_bytes()) {
+                    value.clear();
+                }
                 if row_full {
-                    row.last_mut().unwrap().extend_from_slice(&line[s..se.1.unwrap_or(line.len())]);
+                    let last = row.last_mut().unwrap();
+                    last.extend_from_slice(&value);
+                    if let Some((s, e)) = ifs {
+                        last.extend_from_slice(&line[s..e]);
+                    }
                 } else {
-                    row.push(line[s..se.0.unwrap_or(line.len())].to_owned());
+                    row.push(value);
                 }
-                start = se.0.zip(se.1).map(|(s, e)| e.max(s+1));
+                if escaped {
+                    self.escaped_newline = true;
+                    return (row, true);
+                }
+                start = ifs.map(|(s, e)| e.max(s+1));
             }
         }
 
@@ -638,14 +898,74 @@ impl<'a, 'b> Base<'a, 'b> {
         (row, false)
     }
 
-    fn extract_column(line: &BStr, mut start: usize, quote: u8) -> (BString, Option<usize>) {
+    fn count_fields(&mut self, line: &BStr, ifs: &Ifs) -> usize {
+        let ifs = std::mem::replace(&mut self.ifs, ifs.clone());
+        let quote = self.opts.quote_char();
+        let count = self.parse_line(line, vec![], quote).0.len();
+        self.ifs = ifs;
+        self.escaped_newline = false;
+        count
+    }
+
+    fn replace_nulls(&self, mut row: Row) -> Row {
+        if let Some(null) = &self.opts.null_string {
+            for col in &mut row {
+                if col == null.as_bytes() {
+                    col.clear();
+                }
+            }
+        }
+        row
+    }
+
+    fn unescape(c: u8) -> u8 {
+        match c {
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b'0' => b'\0',
+            c => c,
+        }
+    }
+
+    // returns the value, where the next ifs is and whether it ended with an escaped newline
+    fn extract_unquoted(&self, line: &BStr, mut start: usize) -> (BString, Option<(usize, usize)>, bool) {
         let mut value = BString::new(vec![]);
+        loop {
+            let ifs = Self::next_ifs(line, start, &self.ifs);
+            let end = ifs.map_or(line.len(), |(s, _)| s);
+            let escape = self.opts.escape_char.and_then(|e| line[start..end].find_byte(e)).map(|pos| start + pos);
+
+            let Some(pos) = escape else {
+                value.extend_from_slice(&line[start..end]);
+                return (value, ifs, false)
+            };
+            value.extend_from_slice(&line[start..pos]);
+            if let Some(&c) = line.get(pos + 1) {
+                value.push(Self::unescape(c));
+                start = pos + 2;
+            } else {
+                // the newline is escaped
+                return (value, None, true)
+            }
+        }
+    }
+
+    fn extract_column(&self, line: &BStr, mut start: usize, quote: u8) -> (BString, Option<usize>) {
+        let mut value = BString::new(vec![]);
+        // doubled quotes still work if the escape is the quote
+        let escape = self.opts.escape_char.filter(|&e| e != quote);
 
         // find the next quote
-        while let Some(pos) = line[start..].find_byte(quote).map(|pos| start + pos) {
+        while let Some(pos) = line[start..].iter().position(|&c| c == quote || Some(c) == escape).map(|pos| start + pos) {
             value.extend_from_slice(&line[start..pos]);
+            if line[pos] != quote {
+                if let Some(&c) = line.get(pos + 1) {
+                    value.push(Self::unescape(c));
+                }
+                start = pos + 2;
             // is next char also a quote
-            if let Some(&c) = line.get(pos + 1) && c == quote {
+            } else if let Some(&c) = line.get(pos + 1) && c == quote {
                 value.push(quote);
                 start = pos + 2;
             } else {
@@ -653,9 +973,19 @@ impl<'a, 'b> Base<'a, 'b> {
             }
         }
 
-        value.extend_from_slice(&line[start..]);
+        value.extend_from_slice(line.get(start..).unwrap_or_default());
         (value, None)
     }
+}
+
+impl<'a, 'b> Base<'a, 'b> {
+
+    pub fn new(opts: BaseOptions, sender: Sender<Message>, scope: &'a std::thread::Scope<'a, 'b>) -> Self {
+        Self {
+            inner: ScopelessBase::new(opts, sender),
+            scope,
+        }
+    }
 
     pub fn on_eof(&self) -> Result<bool> {
         Ok(self.sender.send(Message::Eof).is_ok())
@@ -673,6 +1003,10 @@ impl<'a, 'b> Base<'a, 'b> {
         Break::when(self.sender.send(Message::Header(header)).is_err())
     }
 
+    pub fn on_alignment(&self, alignment: Vec<Option<Alignment>>) -> MaybeBreak {
+        Break::when(self.sender.send(Message::Alignment(alignment)).is_err())
+    }
+
     pub fn write_raw(&self, value: BString, ors: bool, clear: bool) -> MaybeBreak {
         Break::when(self.sender.send(Message::Raw(value, ors, clear)).is_err())
     }
@@ -693,6 +1027,56 @@ impl<'a, 'b> Base<'a, 'b> {
         self.write_raw_stderr(line.into(), false, true)
     }
 
+    // where the last record started, for error messages
+    pub fn location(&self) -> String {
+        let filename = self.filename.as_ref().map_or(std::borrow::Cow::Borrowed("<stdin>"), |f| f.to_str_lossy());
+        format!("{filename}:{}", self.line_number)
+    }
+
+    fn unterminated_quote(&self) -> Result<()> {
+        let message = format!("{}: unterminated quote, the field opened on this line runs to the end of the input", self.location());
+        if self.opts.strict {
+            bail!(message);
+        }
+        self.log(message + "\n")?;
+        Ok(())
+    }
+
+    // rows with a different number of fields to the header, or the first row if there isn't one
+    fn fix_ragged(&mut self, mut row: Row) -> Result<Option<Row>> {
+        let Some(action) = self.opts.on_ragged else { return Ok(Some(row)) };
+        let expected = self.header_len.unwrap_or_else(|| *self.row_len.get_or_insert(row.len()));
+        if row.len() == expected {
+            return Ok(Some(row))
+        }
+
+        match action {
+            Ragged::Pad => {
+                if row.len() < expected {
+                    row.resize(expected, b"".into());
+                }
+                Ok(Some(row))
+            },
+            Ragged::Truncate => {
+                row.truncate(expected);
+                Ok(Some(row))
+            },
+            Ragged::Skip => Ok(None),
+            Ragged::Stderr => {
+                self.write_stderr(row)?;
+                Ok(None)
+            },
+            Ragged::Error => {
+                const SNIPPET_LEN: usize = 60;
+                let mut snippet: String = bstr::join(",", &row).to_str_lossy().chars().take(SNIPPET_LEN + 1).collect();
+                if snippet.chars().count() > SNIPPET_LEN {
+                    snippet = snippet.chars().take(SNIPPET_LEN).collect::<String>() + "...";
+                }
+                bail!("{}: expected {expected} fields but found {}: {snippet}", self.location(), row.len())
+            },
+        }
+    }
+
 }
 
 pub struct Output<W: Writer=BaseWriter> {
@@ -709,6 +1093,10 @@ impl<W: Writer> Output<W> {
 
     pub fn new(opts: BaseOptions) -> Self {
         let writer = W::new(&opts);
+        Self::with_writer(opts, writer)
+    }
+
+    pub fn with_writer(opts: BaseOptions, writer: W) -> Self {
         Self {
             opts,
             writer,
@@ -730,22 +1118,7 @@ impl<W: Writer> Output<W> {
         let empty_vec = FormattedRow(vec![]);
         let row_filter = |row| row_filter_fn(row, &empty_vec);
 
-        let widths: Vec<Vec<_>> = header.into_iter()
-            .chain(rows.iter().map(row_filter))
-            .map(|row| row.0.iter().map(|col| no_ansi_colour_len(col.as_ref())).collect())
-            .collect();
-
-        let max_col = rows.iter().map(|row| row_filter(row).0.len()).max().unwrap_or(0);
-        let max_col = max_col.max(header.map_or(0, |h| h.0.len()));
-
-        let max_widths: Vec<_> = (0 .. max_col).map(|i|
-            widths.iter().filter_map(|w| w.get(i)).max().copied().unwrap_or(0)
-        ).collect();
-
-        // don't pad the last column
-        widths.iter()
-            .map(|w| w.iter().zip(&max_widths).take(w.len().saturating_sub(1)).map(|(w, m)| m - w).collect())
-            .collect()
+        padding(header.into_iter().chain(rows.iter().map(row_filter)).map(|row| &row.0[..]))
     }
 
 
@@ -777,7 +1150,7 @@ impl<W: Writer> Output<W> {
         if let Some(trailer) = trailer {
             self.writer.write_header(state, trailer, header_padding.as_ref(), &self.opts, &self.ofs)?;
         }
-        Ok(())
+        self.writer.write_eof(state, &self.opts)
     }
 
     fn on_separator(&mut self, state: &mut WriterState) -> Result<()> {
@@ -799,7 +1172,7 @@ impl<W: Writer> Output<W> {
 
         self.row_count += 1;
 
-        let row = W::format_columns(row, &self.ofs, state.ors.as_ref(), self.opts.quote_output);
+        let row = W::format_columns(row, &self.ofs, state.ors.as_ref(), &self.opts);
 
         match &self.ofs {
             Ofs::Pretty => if is_header {
@@ -812,6 +1185,8 @@ impl<W: Writer> Output<W> {
             Ofs::Plain(_) => if is_header {
                 self.gathered_header = Some(row.clone());
                 self.writer.write_header(state, row, None, &self.opts, &self.ofs)?;
+            } else if stderr {
+                self.writer.write_row(state, GatheredRow::Stderr(row), None, &self.opts, &self.ofs)?;
             } else {
                 self.writer.write_row(state, GatheredRow::Row(row), None, &self.opts, &self.ofs)?;
             },
@@ -874,7 +1249,7 @@ impl<W: Writer> Output<W> {
         for msg in receiver {
             self.handle_message(&mut state, msg)?;
         }
-        Ok(())
+        state.finish()
     }
 
     pub fn handle_message(&mut self, state: &mut WriterState, msg: Message) -> Result<()> {
@@ -887,6 +1262,10 @@ impl<W: Writer> Output<W> {
             Message::Ofs(ofs) => Ok(self.on_ofs(ofs)?),
             Message::Stderr(row) => self.on_stderr(state, row),
             Message::RawStderr(value, ors, clear) => self.on_raw_stderr(state, value, ors, clear),
+            Message::Alignment(alignment) => {
+                self.writer.set_alignment(alignment);
+                Ok(())
+            },
         }
     }
 
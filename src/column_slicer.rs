@@ -1,65 +1,204 @@
 use std::cmp::min;
-use bstr::BString;
+use crate::pipeline::Error;
+use bstr::{BString, ByteSlice};
 use once_cell::sync::Lazy;
 use regex::bytes::Regex;
 use std::collections::HashMap;
 
-static FIELD_REGEX: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"^(\d+)?-(\d+)?$").unwrap());
+static INDEX_REGEX: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"^-?\d+$").unwrap());
+static FIELD_REGEX: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"^(-?\d+)?-(-?\d+)?$").unwrap());
+
+pub const FIELDS_HELP: &str = "\
+Fields are a comma separated list of names, 1-based indices, ranges (2-4, NAME-NAME) and globs ('*_id'), \
+and ! in front of any of them excludes it, e.g. '*,!password'.
+-N is the Nth field from the end (-1 is the last), not 1-N, so use 1-N for the first N fields.
+Names containing commas can't be given this way, use --regex instead.";
 
 pub fn make_header_map(header: &[BString]) -> HashMap<BString, usize> {
-    header.iter().enumerate().map(|(i, k)| (k.clone(), i)).collect()
+    let mut map = HashMap::new();
+    for (i, k) in header.iter().enumerate() {
+        map.entry(k.clone()).or_insert(i);
+    }
+
+    // repeated names can be picked out with name#2 etc
+    let mut counts = HashMap::new();
+    for (i, k) in header.iter().enumerate() {
+        let count = counts.entry(k).or_insert(0);
+        *count += 1;
+        let mut key = k.clone();
+        key.extend_from_slice(format!("#{count}").as_bytes());
+        map.entry(key).or_insert(i);
+    }
+    map
+}
+
+// the error for a name that isn't in the header, with any close matches
+pub fn unknown_field(header: &[BString], name: &[u8]) -> String {
+    let name = name.to_str_lossy();
+    let mut candidates: Vec<_> = header.iter()
+        .map(|h| h.to_str_lossy())
+        .map(|h| (crate::utils::edit_distance(&name.to_lowercase(), &h.to_lowercase()), h))
+        // close enough to be a typo
+        .filter(|(distance, h)| *distance <= 2.max(name.chars().count().max(h.chars().count()) / 3))
+        .collect();
+    candidates.sort();
+    candidates.dedup_by(|a, b| a.1 == b.1);
+
+    let mut message = format!("unknown field {name:?}");
+    if !candidates.is_empty() {
+        let candidates: Vec<_> = candidates.iter().take(3).map(|(_, h)| format!("{h:?}")).collect();
+        message += &format!(", did you mean {}?", candidates.join(" or "));
+    }
+    message + " (use --lenient-fields to ignore unknown fields)"
 }
 
+/// Picks columns out of rows by name, index, range, glob or regex.
+///
+/// ```
+/// let mut slicer = dsv::ColumnSlicer::new(&["b,-1".to_owned()], false).unwrap();
+/// slicer.make_header_map(&["a".into(), "b".into(), "c".into()], false).unwrap();
+/// assert_eq!(slicer.slice(&["1".into(), "2".into(), "3".into()], false, false), ["2", "3"]);
+/// ```
 #[derive(Clone, Debug)]
 pub struct ColumnSlicer {
-    fields: Vec<Field>,
+    fields: Vec<Selector>,
+    header: Vec<BString>,
     headers: HashMap<BString, usize>,
+    // names that aren't in the header but are a range of names, e.g. start_date-end_date
+    name_ranges: HashMap<BString, (Bound, Bound)>,
 }
 
 #[derive(Clone, Debug)]
-enum Field {
-    Range(usize, usize),
+struct Selector {
+    field: Field,
+    exclude: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Bound {
     Index(usize),
+    // counting back from the end, 1 is the last column
+    FromEnd(usize),
+    Name(BString),
+}
+
+#[derive(Clone, Debug)]
+enum Field {
+    Range(Option<Bound>, Option<Bound>),
+    Index(Bound),
     Regex(Regex),
+    // keeps the text in case it is also the exact name of a column
+    Glob(BString, glob::Pattern),
     Name(BString),
 }
 
+impl Bound {
+    fn parse(value: &str) -> Option<Self> {
+        if let Some(value) = value.strip_prefix('-') {
+            value.parse().ok().map(Self::FromEnd)
+        } else {
+            value.parse::<usize>().ok().map(|i| Self::Index(i.saturating_sub(1)))
+        }
+    }
+}
+
 impl ColumnSlicer {
-    pub fn from_names<'a, F: Iterator<Item=&'a BString>>(fields: F) -> Self {
+    fn with_fields(fields: Vec<Selector>) -> Self {
         Self {
-            fields: fields.cloned().map(Field::Name).collect(),
+            fields,
+            header: vec![],
             headers: HashMap::new(),
+            name_ranges: HashMap::new(),
         }
     }
 
-    pub fn new<'a, I: IntoIterator<Item=&'a String>>(fields: I, is_regex: bool) -> Self {
+    pub(crate) fn from_names<'a, F: Iterator<Item=&'a BString>>(fields: F) -> Self {
+        Self::with_fields(fields.cloned().map(|name| Selector{ field: Field::Name(name), exclude: false }).collect())
+    }
+
+    /// Parses the fields, each a comma separated list of `N`, `-N` (from the end),
+    /// `N-M` (either end optional or negative), `NAME`, `NAME-NAME`, a glob,
+    /// or a regex if `is_regex`, any of them prefixed with `!` to exclude it instead.
+    ///
+    /// Fails if `is_regex` and one of them isn't a valid regex.
+    pub fn new<'a, I: IntoIterator<Item=&'a String>>(fields: I, is_regex: bool) -> Result<Self, Error> {
         let mut new_fields = vec![];
 
         for field in fields {
-            if field != "-" && let Some(captures) = FIELD_REGEX.captures(field) {
-                let start = captures.get(1).map_or(1usize, |m| m.as_str().parse().unwrap()).saturating_sub(1);
-                let end = captures.get(2).map_or(usize::MAX, |m| m.as_str().parse().unwrap());
-                new_fields.push(Field::Range(start, end));
-            } else if let Ok(index) = field.parse::<usize>() {
-                new_fields.push(Field::Index(index.saturating_sub(1)));
-            } else if is_regex {
-                new_fields.push(Field::Regex(Regex::new(field).unwrap()));
-            } else {
-                new_fields.push(Field::Name(field.clone().into()));
+            // commas are too common in regexes to split on
+            let parts: Vec<&str> = if is_regex { vec![field] } else { field.split(',').collect() };
+            for part in parts {
+                let (part, exclude) = match part.strip_prefix('!') {
+                    Some(part) if !part.is_empty() => (part, true),
+                    _ => (part, false),
+                };
+
+                let field = if INDEX_REGEX.is_match(part) {
+                    Field::Index(Bound::parse(part).unwrap())
+                } else if part != "-" && let Some(captures) = FIELD_REGEX.captures(part) {
+                    let start = captures.get(1).and_then(|m| Bound::parse(m.as_str()));
+                    let end = captures.get(2).and_then(|m| Bound::parse(m.as_str()));
+                    Field::Range(start, end)
+                } else if is_regex {
+                    Field::Regex(Regex::new(part).map_err(|e| Error::Failed(e.into()))?)
+                } else if part.contains(['*', '?', '[']) && let Ok(pattern) = glob::Pattern::new(part) {
+                    Field::Glob(part.into(), pattern)
+                } else {
+                    Field::Name(part.into())
+                };
+                new_fields.push(Selector{ field, exclude });
             }
         }
 
-        Self {
-            fields: new_fields,
-            headers: HashMap::new(),
-        }
+        Ok(Self::with_fields(new_fields))
     }
 
-    pub fn make_header_map(&mut self, header: &[BString]) {
+    /// Resolves names against the header, which must be done before slicing by name.
+    ///
+    /// Unless `lenient`, names that aren't in the header and globs or regexes that match nothing are an error.
+    pub fn make_header_map(&mut self, header: &[BString], lenient: bool) -> Result<(), Error> {
         self.headers = make_header_map(header);
+        self.header = header.to_owned();
+
+        self.name_ranges.clear();
+        for selector in &self.fields {
+            match &selector.field {
+                Field::Name(name) if !self.headers.contains_key(name) => {
+                    // try each hyphen until both sides make sense
+                    let range = name.find_iter("-")
+                        .map(|i| (&name[..i], &name[i+1..]))
+                        .find_map(|(start, end)| Some((self.parse_bound(start)?, self.parse_bound(end)?)));
+                    if let Some(range) = range {
+                        self.name_ranges.insert(name.clone(), range);
+                    } else if !lenient {
+                        return Err(Error::Failed(self.unknown_field(name).into()));
+                    }
+                },
+                Field::Glob(text, _) if !lenient && self.resolve(&selector.field, header.len()).is_empty() => {
+                    return Err(Error::Failed(format!("no fields match {:?} (use --lenient-fields to ignore unknown fields)", text.to_str_lossy()).into()));
+                },
+                Field::Regex(regex) if !lenient && self.resolve(&selector.field, header.len()).is_empty() => {
+                    return Err(Error::Failed(format!("no fields match /{regex}/ (use --lenient-fields to ignore unknown fields)").into()));
+                },
+                _ => (),
+            }
+        }
+        Ok(())
     }
 
-    pub fn get_single_field_index(&self, field: &str) -> Option<usize> {
+    fn unknown_field(&self, name: &BString) -> String {
+        unknown_field(&self.header, name)
+    }
+
+    fn parse_bound(&self, value: &[u8]) -> Option<Bound> {
+        if self.headers.contains_key(value) {
+            Some(Bound::Name(value.into()))
+        } else {
+            value.to_str().ok().and_then(Bound::parse)
+        }
+    }
+
+    pub(crate) fn get_single_field_index(&self, field: &str) -> Option<usize> {
         if let Ok(i) = field.parse::<usize>() {
             Some(i.saturating_sub(1))
         } else {
@@ -67,47 +206,93 @@ impl ColumnSlicer {
         }
     }
 
-    fn range_for_row(start: usize, end: usize, len: usize) -> std::ops::Range<usize> {
-        min(start, len) .. min(end, len)
+    fn resolve_bound(&self, bound: &Bound, len: usize) -> Option<usize> {
+        match bound {
+            Bound::Index(i) => Some(*i),
+            Bound::FromEnd(i) => len.checked_sub(*i),
+            Bound::Name(name) => self.headers.get(name).copied(),
+        }
     }
 
-    pub fn matches(&self, index: usize) -> bool {
-        self.fields.is_empty() ||
-        self.fields.iter().any(|field| match field {
-            Field::Range(start, end) => (*start .. *end).contains(&index),
-            Field::Index(i) => *i == index,
-            Field::Regex(regex) => self.headers.iter().any(|(k, &v)| v == index && regex.is_match(k)),
-            Field::Name(name) => self.headers.get(name) == Some(&index),
-        })
+    fn range_for_row(&self, start: Option<&Bound>, end: Option<&Bound>, len: usize) -> std::ops::Range<usize> {
+        let start = match start {
+            None => 0,
+            Some(Bound::FromEnd(i)) => len.saturating_sub(*i),
+            Some(start) => self.resolve_bound(start, len).unwrap_or(len),
+        };
+        let end = match end {
+            None => len,
+            Some(end) => self.resolve_bound(end, len).map_or(0, |i| i + 1),
+        };
+        min(start, len) .. min(end, len)
     }
 
-    pub fn indices(&self, len: usize, complement: bool) -> impl Iterator<Item=usize> {
+    // the columns this field refers to, in order
+    fn resolve(&self, field: &Field, len: usize) -> Vec<usize> {
+        match field {
+            Field::Range(start, end) => self.range_for_row(start.as_ref(), end.as_ref(), len).collect(),
+            Field::Index(bound) => self.resolve_bound(bound, len).into_iter().collect(),
+            Field::Name(name) => if let Some(&i) = self.headers.get(name) {
+                vec![i]
+            } else if let Some((start, end)) = self.name_ranges.get(name) {
+                self.range_for_row(Some(start), Some(end), len).collect()
+            } else {
+                vec![]
+            },
+            Field::Regex(regex) => self.header.iter().enumerate().filter(|(_, k)| regex.is_match(k)).map(|(i, _)| i).collect(),
+            Field::Glob(text, pattern) => if let Some(&i) = self.headers.get(text) {
+                vec![i]
+            } else {
+                self.header.iter()
+                    .enumerate()
+                    .filter(|(_, k)| k.to_str().is_ok_and(|k| pattern.matches(k)))
+                    .map(|(i, _)| i)
+                    .collect()
+            },
+        }
+    }
 
-        let iters = if complement {
-            let iter = (0..len).filter(|&index| !self.matches(index));
-            (Some(iter), None, None)
-        } else if self.fields.is_empty() {
-            // actually means you want everything
-            (None, Some(0..len), None)
+    fn selected(&self, len: usize) -> Vec<usize> {
+        // starting with an exclusion means everything else
+        let mut selected: Vec<_> = if self.fields.first().is_none_or(|f| f.exclude) {
+            (0..len).collect()
         } else {
-            let iter = self.fields.iter()
-                .filter_map(move |field| match field {
-                    Field::Range(start, end) => Some(Self::range_for_row(*start, *end, len)),
-                    Field::Index(i) => Some(*i .. i+1),
-                    Field::Name(name) => self.headers.get(name).map(|&i| i .. i+1),
-                    Field::Regex(_) => None,
-                }).chain(self.fields.iter()
-                    .filter_map(|field| if let Field::Regex(regex) = field { Some(regex) } else { None })
-                    .flat_map(|regex| {
-                        self.headers.iter().filter(|(k, _)| regex.is_match(k)).map(|(_, &v)| v .. v+1)
-                    })
-                ).flatten();
-            (None, None, Some(iter))
+            vec![]
         };
 
-        iters.0.into_iter().flatten().chain(iters.1.into_iter().flatten()).chain(iters.2.into_iter().flatten())
+        for selector in &self.fields {
+            let indices = self.resolve(&selector.field, len);
+            if selector.exclude {
+                selected.retain(|i| !indices.contains(i));
+            } else {
+                selected.extend(indices);
+            }
+        }
+        selected
+    }
+
+    /// Whether the column at `index` is selected, in a row of `len` columns.
+    pub fn matches(&self, index: usize, len: usize) -> bool {
+        let mut matched = self.fields.first().is_none_or(|f| f.exclude);
+        for selector in &self.fields {
+            if self.resolve(&selector.field, len).contains(&index) {
+                matched = !selector.exclude;
+            }
+        }
+        matched
+    }
+
+    /// The selected columns in a row of `len` columns, in order, or the rest if `complement`.
+    pub fn indices(&self, len: usize, complement: bool) -> impl Iterator<Item=usize> {
+        let selected = self.selected(len);
+        if complement {
+            (0..len).filter(|i| !selected.contains(i)).collect()
+        } else {
+            selected
+        }.into_iter()
     }
 
+    /// The selected values from a row, with blanks for missing columns if `allow_empty`.
     pub fn slice(
         &self,
         row: &[BString],
@@ -117,6 +302,7 @@ impl ColumnSlicer {
         self.slice_with(row, complement, allow_empty.then_some(|_| b"".into()))
     }
 
+    /// Like [`slice`](Self::slice), but for any values, with `default` giving those for missing columns.
     pub fn slice_with<T: Clone, F: Fn(usize) -> T>(
         &self,
         row: &[T],
@@ -129,3 +315,62 @@ impl ColumnSlicer {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(fields: &[&str], is_regex: bool, header: &str) -> Result<Vec<usize>, Error> {
+        let fields: Vec<String> = fields.iter().map(|&f| f.into()).collect();
+        let header: Vec<BString> = header.split(',').map(BString::from).collect();
+        let mut slicer = ColumnSlicer::new(&fields, is_regex)?;
+        slicer.make_header_map(&header, false)?;
+        Ok(slicer.indices(header.len(), false).collect())
+    }
+
+    #[test]
+    fn indices_and_ranges() {
+        assert_eq!(select(&["2"], false, "a,b,c,d").unwrap(), [1]);
+        assert_eq!(select(&["-1"], false, "a,b,c,d").unwrap(), [3]);
+        assert_eq!(select(&["2-3"], false, "a,b,c,d").unwrap(), [1, 2]);
+        assert_eq!(select(&["-3--2"], false, "a,b,c,d").unwrap(), [1, 2]);
+        assert_eq!(select(&["3-"], false, "a,b,c,d").unwrap(), [2, 3]);
+    }
+
+    #[test]
+    fn names_and_name_ranges() {
+        assert_eq!(select(&["c,a"], false, "a,b,c,d").unwrap(), [2, 0]);
+        assert_eq!(select(&["start-end"], false, "id,start,x,end,y").unwrap(), [1, 2, 3]);
+        // a name with a hyphen in it wins over the range
+        assert_eq!(select(&["a-b"], false, "a,b,a-b").unwrap(), [2]);
+        assert!(select(&["nope"], false, "a,b").is_err());
+    }
+
+    #[test]
+    fn globs_and_exclusions() {
+        assert_eq!(select(&["*_id"], false, "user_id,name,org_id").unwrap(), [0, 2]);
+        assert_eq!(select(&["*,!name"], false, "user_id,name,org_id").unwrap(), [0, 2]);
+        assert_eq!(select(&["!name"], false, "user_id,name,org_id").unwrap(), [0, 2]);
+        // an exact name is used before trying it as a glob
+        assert_eq!(select(&["tags/tag[0]"], false, "id,tags/tag[0],tags/tag[1]").unwrap(), [1]);
+        assert!(select(&["*_id"], false, "a,b").is_err());
+    }
+
+    #[test]
+    fn regexes() {
+        assert_eq!(select(&["^a|c$"], true, "ab,b,bc").unwrap(), [0, 2]);
+        // commas aren't split when they're regexes
+        let mut slicer = ColumnSlicer::new(&["^x,y$".to_owned()], true).unwrap();
+        slicer.make_header_map(&["a".into(), "x,y".into()], false).unwrap();
+        assert_eq!(slicer.indices(2, false).collect::<Vec<_>>(), [1]);
+        assert!(select(&["^z"], true, "a,b").is_err());
+    }
+
+    #[test]
+    fn lenient() {
+        let mut slicer = ColumnSlicer::new(&["*_id".to_owned(), "nope".to_owned()], false).unwrap();
+        let header: Vec<BString> = vec!["a".into(), "b".into()];
+        slicer.make_header_map(&header, true).unwrap();
+        assert_eq!(slicer.indices(2, false).count(), 0);
+    }
+}
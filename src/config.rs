@@ -0,0 +1,382 @@
+use std::ffi::OsString;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use anyhow::{Result, Context, bail};
+use clap::CommandFactory;
+use crate::subcommands::Cli;
+
+// $XDG_CONFIG_HOME/dsv/config looks like
+//
+//   # options for every command
+//   --colour=always --rainbow-columns
+//
+//   [grep]
+//   --ignore-case
+//
+//   [alias]
+//   errors = grep -k level ERROR ! sort ts ! cut ts,msg
+#[derive(Default, Debug)]
+pub struct Config {
+    options: Vec<String>,
+    subcommands: HashMap<String, Vec<String>>,
+    aliases: HashMap<String, Vec<String>>,
+}
+
+fn config_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))?;
+    Some(dir.join("dsv").join("config"))
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let mut config = match config_path() {
+            Some(path) if path.exists() => {
+                let contents = std::fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+                Self::parse(&contents).with_context(|| format!("in {}", path.display()))?
+            },
+            _ => Self::default(),
+        };
+
+        // the environment goes after the file so it wins
+        if let Some(opts) = std::env::var_os("DSV_OPTS") {
+            let opts = opts.to_str().context("DSV_OPTS is not valid utf-8")?;
+            config.options.extend(shell_split(opts).context("in DSV_OPTS")?);
+        }
+        Ok(config)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut config = Self::default();
+        let mut section: Option<&str> = None;
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+
+            let result = (|| {
+                if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                    let name = name.trim();
+                    if name != "alias" && Cli::command().find_subcommand(name).is_none() && crate::subcommands::_plugin::find(name).is_none() {
+                        bail!("unknown subcommand [{name}]");
+                    }
+                    section = Some(name);
+                } else if section == Some("alias") {
+                    let Some((name, value)) = line.split_once('=') else { bail!("expected NAME = COMMAND") };
+                    let value = shell_split(value)?;
+                    if value.is_empty() {
+                        bail!("alias {} is empty", name.trim());
+                    }
+                    config.aliases.insert(name.trim().to_owned(), value);
+                } else if let Some(name) = section {
+                    config.subcommands.entry(name.to_owned()).or_default().extend(shell_split(line)?);
+                } else {
+                    config.options.extend(shell_split(line)?);
+                }
+                Ok(())
+            })();
+            result.with_context(|| format!("line {}", i + 1))?;
+        }
+        Ok(config)
+    }
+
+    // adds the defaults to the command line and expands any aliases
+    pub fn expand_args(&self, args: Vec<OsString>) -> Result<Vec<OsString>> {
+        let mut args = args.into_iter();
+        let arg0 = args.next().unwrap_or_default();
+        let rest: Vec<_> = args.collect();
+
+        let takes_value = value_options();
+        let mut expanded = vec![arg0];
+        expanded.extend(self.options.iter().map(OsString::from));
+
+        match command_index(&rest, &takes_value) {
+            Some(i) if rest[i] == "!" => {
+                expanded.extend_from_slice(&rest[..=i]);
+                let segments: Vec<_> = rest[i+1 ..].split(|a| a == "!").collect();
+                let mut first = true;
+                for segment in segments {
+                    for segment in self.expand_segment(segment, &takes_value, &mut HashSet::new())? {
+                        if !first {
+                            expanded.push("!".into());
+                        }
+                        first = false;
+                        expanded.extend(segment);
+                    }
+                }
+            },
+            Some(_) => {
+                let segments = self.expand_segment(&rest, &takes_value, &mut HashSet::new())?;
+                // an alias that is itself a pipeline
+                if segments.len() > 1 {
+                    expanded.push("!".into());
+                }
+                for (i, segment) in segments.into_iter().enumerate() {
+                    if i > 0 {
+                        expanded.push("!".into());
+                    }
+                    expanded.extend(segment);
+                }
+            },
+            None => expanded.extend(rest),
+        }
+        Ok(expanded)
+    }
+
+    fn expand_segment(&self, segment: &[OsString], takes_value: &HashMap<String, bool>, seen: &mut HashSet<String>) -> Result<Vec<Vec<OsString>>> {
+        let Some(i) = command_index(segment, takes_value) else { return Ok(vec![segment.to_owned()]) };
+        let (flags, name, rest) = (&segment[..i], &segment[i], &segment[i+1 ..]);
+        let name_str = name.to_str().unwrap_or_default();
+
+        // an alias can use a subcommand of the same name without recursing
+        if let Some(alias) = self.aliases.get(name_str) && !seen.contains(name_str) {
+            seen.insert(name_str.to_owned());
+            let parts: Vec<_> = alias.split(|a| a == "!").filter(|p| !p.is_empty()).collect();
+            let mut segments = vec![];
+            for (j, part) in parts.iter().enumerate() {
+                let mut new_segment: Vec<OsString> = flags.to_owned();
+                new_segment.extend(part.iter().map(OsString::from));
+                // extra arguments go to the last command
+                if j == parts.len() - 1 {
+                    new_segment.extend_from_slice(rest);
+                }
+                segments.extend(self.expand_segment(&new_segment, takes_value, seen)?);
+            }
+            seen.remove(name_str);
+            return Ok(segments)
+        }
+
+        let mut new_segment = segment[..=i].to_owned();
+        if let Some(defaults) = self.subcommands.get(name_str) {
+            new_segment.extend(defaults.iter().map(OsString::from));
+        }
+        new_segment.extend_from_slice(rest);
+        Ok(vec![new_segment])
+    }
+}
+
+// which options take a separate value, by --long and -s
+pub fn value_options() -> HashMap<String, bool> {
+    let command = Cli::command();
+    let mut options = HashMap::new();
+    for arg in command.get_arguments() {
+        // --colour=always can't be split over two arguments
+        let takes_value = arg.get_action().takes_values() && !arg.is_require_equals_set();
+        for long in arg.get_long().into_iter().chain(arg.get_all_aliases().into_iter().flatten()) {
+            options.insert(format!("--{long}"), takes_value);
+        }
+        if let Some(short) = arg.get_short() {
+            options.insert(format!("-{short}"), takes_value);
+        }
+    }
+    options
+}
+
+// the position of the subcommand, skipping over options and their values
+pub fn command_index(args: &[OsString], takes_value: &HashMap<String, bool>) -> Option<usize> {
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        let Some(arg) = arg.to_str() else { return Some(i) };
+        if arg == "--" {
+            return None
+        } else if arg.starts_with("--") {
+            i += if !arg.contains('=') && takes_value.get(arg).copied().unwrap_or(false) { 2 } else { 1 };
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            // a cluster of short flags, the first that takes a value eats the rest
+            let mut skip = 1;
+            for (j, c) in arg.char_indices().skip(1) {
+                if takes_value.get(&format!("-{c}")).copied().unwrap_or(false) {
+                    if j + c.len_utf8() == arg.len() {
+                        skip = 2;
+                    }
+                    break
+                }
+            }
+            i += skip;
+        } else {
+            return Some(i)
+        }
+    }
+    None
+}
+
+// split like a shell would, with quotes and backslashes but nothing else
+pub fn shell_split(value: &str) -> Result<Vec<String>> {
+    shell_split_with(value, None)
+}
+
+pub type Lookup<'a> = &'a dyn Fn(&str) -> Result<String>;
+
+// also substitutes $NAME and ${NAME} outside single quotes, without splitting the value again
+pub fn shell_split_with(value: &str, vars: Option<Lookup>) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = value.chars().peekable();
+
+    let substitute = |chars: &mut std::iter::Peekable<std::str::Chars>, word: &mut String| -> Result<bool> {
+        let Some(vars) = vars else { return Ok(false) };
+        let braced = chars.next_if_eq(&'{').is_some();
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            name.push(c);
+        }
+        if braced && chars.next_if_eq(&'}').is_none() {
+            bail!("unterminated ${{ in {value:?}");
+        }
+        if name.is_empty() {
+            if braced {
+                bail!("empty ${{}} in {value:?}");
+            }
+            return Ok(false)
+        }
+        word.push_str(&vars(&name)?);
+        Ok(true)
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            // a comment, but only at the start of a word
+            '#' if word.is_none() => break,
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => bail!("unterminated quote in {value:?}"),
+                    }
+                }
+            },
+            '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => { word.push('\\'); word.push(c); },
+                            None => bail!("unterminated quote in {value:?}"),
+                        },
+                        Some('$') => if !substitute(&mut chars, word)? {
+                            word.push('$');
+                        },
+                        Some(c) => word.push(c),
+                        None => bail!("unterminated quote in {value:?}"),
+                    }
+                }
+            },
+            '\\' => word.get_or_insert_default().extend(chars.next()),
+            '$' => {
+                let word = word.get_or_insert_default();
+                if !substitute(&mut chars, word)? {
+                    word.push('$');
+                }
+            },
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+pub fn args() -> Result<Vec<OsString>> {
+    let args = crate::script::expand_args(std::env::args_os().collect())?;
+    Config::load()?.expand_args(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(value: &str) -> Vec<String> {
+        shell_split(value).unwrap()
+    }
+
+    fn os(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn split_words_and_quotes() {
+        assert_eq!(split("  a  b\tc "), ["a", "b", "c"]);
+        assert_eq!(split(r#"'a b' "c d" e\ f"#), ["a b", "c d", "e f"]);
+        assert_eq!(split(r#"'' "" x"#), ["", "", "x"]);
+        assert_eq!(split(r#""a \"b\" \n""#), [r#"a "b" \n"#]);
+        assert_eq!(split("a'b'\"c\"d"), ["abcd"]);
+    }
+
+    #[test]
+    fn split_comments() {
+        assert_eq!(split("a b # c d"), ["a", "b"]);
+        assert_eq!(split("a#b '#c'"), ["a#b", "#c"]);
+        assert!(split("# all comment").is_empty());
+    }
+
+    #[test]
+    fn split_unterminated_quotes() {
+        assert!(shell_split("'a").unwrap_err().to_string().contains("unterminated quote"));
+        assert!(shell_split("\"a").unwrap_err().to_string().contains("unterminated quote"));
+        assert!(shell_split("\"a\\").unwrap_err().to_string().contains("unterminated quote"));
+    }
+
+    #[test]
+    fn split_variables() {
+        let lookup = |name: &str| match name {
+            "X" => Ok("one two".to_owned()),
+            _ => bail!("undefined ${name}"),
+        };
+        let split = |value| shell_split_with(value, Some(&lookup));
+        // substituted values aren't split again
+        assert_eq!(split("$X ${X}y \"$X\"").unwrap(), ["one two", "one twoy", "one two"]);
+        assert_eq!(split("'$X' \\$X $ $-x").unwrap(), ["$X", "$X", "$", "$-x"]);
+        assert!(split("$Y").unwrap_err().to_string().contains("undefined $Y"));
+        assert!(split("${X").unwrap_err().to_string().contains("unterminated ${"));
+        assert!(split("${}").unwrap_err().to_string().contains("empty ${}"));
+        // without a lookup $ is just a character
+        assert_eq!(shell_split("$X").unwrap(), ["$X"]);
+    }
+
+    #[test]
+    fn command_after_options() {
+        let takes_value = value_options();
+        let index = |args: &[&str]| command_index(&os(args), &takes_value);
+        assert_eq!(index(&["cut", "a"]), Some(0));
+        assert_eq!(index(&["--csv", "cut", "a"]), Some(1));
+        assert_eq!(index(&["--ifs", ";", "cut"]), Some(2));
+        assert_eq!(index(&["--ifs=;", "cut"]), Some(1));
+        assert_eq!(index(&["-d", ";", "cut"]), Some(2));
+        assert_eq!(index(&["-d;", "cut"]), Some(1));
+        assert_eq!(index(&["!", "cut"]), Some(0));
+        assert_eq!(index(&["--csv"]), None);
+        assert_eq!(index(&["--", "cut"]), None);
+    }
+
+    #[test]
+    fn parse_sections() {
+        let config = Config::parse("# comment\n--csv\n\n[grep]\n-i\n[alias]\nerrors = grep level ERROR ! sort ts\n").unwrap();
+        assert_eq!(config.options, ["--csv"]);
+        assert_eq!(config.subcommands["grep"], ["-i"]);
+        assert_eq!(config.aliases["errors"], ["grep", "level", "ERROR", "!", "sort", "ts"]);
+
+        let err = Config::parse("--csv\n[nosuchcommand]\n").unwrap_err();
+        assert_eq!(format!("{err:#}"), "line 2: unknown subcommand [nosuchcommand]");
+        let err = Config::parse("[alias]\nx =\n").unwrap_err();
+        assert_eq!(format!("{err:#}"), "line 2: alias x is empty");
+    }
+
+    #[test]
+    fn expand_defaults_and_aliases() {
+        let config = Config::parse("--csv\n[sort]\n-r\n[alias]\nerrors = grep ERROR ! sort ts\nsort = sort -n\n").unwrap();
+        let expand = |args: &[&str]| config.expand_args(os(args)).unwrap();
+        assert_eq!(expand(&["dsv", "cut", "a"]), os(&["dsv", "--csv", "cut", "a"]));
+        // an alias can use the subcommand it shadows
+        assert_eq!(expand(&["dsv", "sort", "x"]), os(&["dsv", "--csv", "sort", "-r", "-n", "x"]));
+        assert_eq!(expand(&["dsv", "errors", "-k"]), os(&["dsv", "--csv", "!", "grep", "ERROR", "!", "sort", "-r", "-n", "ts", "-k"]));
+        assert_eq!(expand(&["dsv", "!", "cut", "a", "!", "errors"]), os(&["dsv", "--csv", "!", "cut", "a", "!", "grep", "ERROR", "!", "sort", "-r", "-n", "ts"]));
+    }
+}
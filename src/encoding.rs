@@ -0,0 +1,293 @@
+use std::io::{Read, BufRead, Write};
+use bstr::ByteSlice;
+use clap::ValueEnum;
+use crate::io::BoxedReader;
+
+const READ_AMOUNT: usize = 8192;
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum Encoding {
+    Auto,
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    #[value(name = "utf-16le", alias = "utf16le")]
+    Utf16le,
+    #[value(name = "utf-16be", alias = "utf16be")]
+    Utf16be,
+    #[value(alias = "iso-8859-1")]
+    Latin1,
+    #[value(alias = "windows-1252")]
+    Cp1252,
+}
+
+// 0x80 to 0x9f, the undefined ones are passed through like latin1
+const CP1252: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+pub fn parse_output_encoding(value: &str) -> Result<Encoding, String> {
+    match Encoding::from_str(value, true)? {
+        Encoding::Auto => Err("auto is only valid for --encoding".into()),
+        encoding => Ok(encoding),
+    }
+}
+
+impl Encoding {
+    // guess from the byte order mark, otherwise from the contents
+    fn detect(sample: &[u8]) -> Self {
+        if sample.starts_with(b"\xff\xfe") {
+            return Self::Utf16le
+        } else if sample.starts_with(b"\xfe\xff") {
+            return Self::Utf16be
+        } else if sample.starts_with(UTF8_BOM) {
+            return Self::Utf8
+        }
+
+        // ascii in utf-16 has every other byte zero
+        let pairs = sample.len() / 2;
+        let (even, odd) = sample.chunks_exact(2).fold((0, 0), |(even, odd), pair| {
+            (even + usize::from(pair[0] == 0), odd + usize::from(pair[1] == 0))
+        });
+        if pairs > 0 && odd > pairs / 2 && even <= pairs / 10 {
+            Self::Utf16le
+        } else if pairs > 0 && even > pairs / 2 && odd <= pairs / 10 {
+            Self::Utf16be
+        } else {
+            match std::str::from_utf8(sample) {
+                // the sample may have cut a character in half
+                Err(e) if e.error_len().is_some() => Self::Cp1252,
+                _ => Self::Utf8,
+            }
+        }
+    }
+
+    fn decode_byte(self, byte: u8) -> char {
+        match (self, byte) {
+            (Self::Cp1252, 0x80 ..= 0x9f) => CP1252[usize::from(byte - 0x80)],
+            _ => char::from(byte),
+        }
+    }
+
+    fn encode_char(self, c: char) -> Option<u8> {
+        let byte = u8::try_from(u32::from(c)).ok();
+        match (self, byte) {
+            (Self::Cp1252, Some(0x80 ..= 0x9f)) | (Self::Cp1252, None) => CP1252.iter().position(|&x| x == c).map(|i| 0x80 + i as u8),
+            _ => byte,
+        }
+    }
+
+    // decodes as much of the input as it can into utf-8, returns how much was used
+    fn decode(self, input: &[u8], output: &mut Vec<u8>, eof: bool) -> usize {
+        let mut buf = [0; 4];
+        match self {
+            Self::Auto | Self::Utf8 => {
+                output.extend_from_slice(input);
+                input.len()
+            },
+            Self::Latin1 | Self::Cp1252 => {
+                for &byte in input {
+                    output.extend_from_slice(self.decode_byte(byte).encode_utf8(&mut buf).as_bytes());
+                }
+                input.len()
+            },
+            Self::Utf16le | Self::Utf16be => {
+                let mut units: Vec<u16> = input.chunks_exact(2).map(|pair| {
+                    let pair = [pair[0], pair[1]];
+                    if self == Self::Utf16le { u16::from_le_bytes(pair) } else { u16::from_be_bytes(pair) }
+                }).collect();
+                // keep a dangling high surrogate for next time
+                if !eof && units.last().is_some_and(|u| (0xd800 .. 0xdc00).contains(u)) {
+                    units.pop();
+                }
+                for c in char::decode_utf16(units.iter().copied()) {
+                    let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+                    output.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                let mut used = units.len() * 2;
+                if eof && used < input.len() {
+                    output.extend_from_slice(char::REPLACEMENT_CHARACTER.encode_utf8(&mut buf).as_bytes());
+                    used = input.len();
+                }
+                used
+            },
+        }
+    }
+
+    fn encode(self, input: &[u8], output: &mut Vec<u8>) {
+        for c in input.chars() {
+            match self {
+                Self::Auto | Self::Utf8 => output.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                Self::Utf16le => output.extend(c.encode_utf16(&mut [0; 2]).iter().flat_map(|u| u.to_le_bytes())),
+                Self::Utf16be => output.extend(c.encode_utf16(&mut [0; 2]).iter().flat_map(|u| u.to_be_bytes())),
+                Self::Latin1 | Self::Cp1252 => output.push(self.encode_char(c).unwrap_or(b'?')),
+            }
+        }
+    }
+}
+
+struct Decoder {
+    inner: BoxedReader,
+    encoding: Encoding,
+    pending: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    started: bool,
+    eof: bool,
+}
+
+impl Read for Decoder {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.fill_buf()?.read(buf)?;
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for Decoder {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        while self.pos >= self.output.len() && !self.eof {
+            self.output.clear();
+            self.pos = 0;
+
+            let start = self.pending.len();
+            self.pending.resize(start + READ_AMOUNT, 0);
+            let count = self.inner.read(&mut self.pending[start..]);
+            self.pending.truncate(start + *count.as_ref().unwrap_or(&0));
+            let count = count?;
+            self.eof = count == 0;
+
+            if self.encoding == Encoding::Auto {
+                // go with whatever the first read gave rather than wait on a slow stream,
+                // files give a full READ_AMOUNT anyway
+                self.encoding = Encoding::detect(&self.pending);
+            }
+            let used = self.encoding.decode(&self.pending, &mut self.output, self.eof);
+            self.pending.drain(.. used);
+
+            if !self.started && !self.output.is_empty() {
+                self.started = true;
+                // the bom has done its job
+                if self.output.starts_with(UTF8_BOM) {
+                    self.pos = UTF8_BOM.len();
+                }
+            }
+        }
+        Ok(&self.output[self.pos.min(self.output.len()) ..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos += amount;
+    }
+}
+
+pub fn decode(inner: BoxedReader, encoding: Encoding) -> BoxedReader {
+    if encoding == Encoding::Utf8 {
+        return inner
+    }
+    Box::new(Decoder {
+        inner,
+        encoding,
+        pending: vec![],
+        output: vec![],
+        pos: 0,
+        started: false,
+        eof: false,
+    })
+}
+
+struct Encoder {
+    inner: Box<dyn Write>,
+    encoding: Encoding,
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        // don't split a character across writes
+        let end = match std::str::from_utf8(&self.pending) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => self.pending.len(),
+        };
+
+        let mut output = vec![];
+        if !self.started {
+            self.started = true;
+            // utf-16 consumers generally want a bom
+            if matches!(self.encoding, Encoding::Utf16le | Encoding::Utf16be) {
+                self.encoding.encode("\u{feff}".as_bytes(), &mut output);
+            }
+        }
+        self.encoding.encode(&self.pending[..end], &mut output);
+        self.pending.drain(.. end);
+        self.inner.write_all(&output)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn encode(inner: Box<dyn Write>, encoding: Encoding) -> Box<dyn Write> {
+    if encoding == Encoding::Utf8 {
+        return inner
+    }
+    Box::new(Encoder {
+        inner,
+        encoding,
+        pending: vec![],
+        started: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // hands out one chunk per read, like a slow pipe, and fails if read past the end
+    struct Chunks(Vec<&'static [u8]>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let chunk = self.0.first_mut().ok_or_else(|| std::io::Error::other("read past the last chunk"))?;
+            let count = chunk.len().min(buf.len());
+            buf[..count].copy_from_slice(&chunk[..count]);
+            *chunk = &chunk[count..];
+            if chunk.is_empty() {
+                self.0.remove(0);
+            }
+            Ok(count)
+        }
+    }
+
+    fn decoder(chunks: Vec<&'static [u8]>) -> BoxedReader {
+        decode(Box::new(std::io::BufReader::new(Chunks(chunks))), Encoding::Auto)
+    }
+
+    #[test]
+    fn auto_detects_from_the_first_read() {
+        let mut reader = decoder(vec![b"a,b\n"]);
+        assert_eq!(reader.fill_buf().unwrap(), b"a,b\n");
+    }
+
+    #[test]
+    fn auto_detects() {
+        let mut output = String::new();
+        decoder(vec![b"\xef\xbb\xbfa,b\n", b""]).read_to_string(&mut output).unwrap();
+        assert_eq!(output, "a,b\n");
+
+        let mut output = String::new();
+        decoder(vec![b"caf\xe9 \x80\n", b""]).read_to_string(&mut output).unwrap();
+        assert_eq!(output, "café €\n");
+
+        let mut output = String::new();
+        decoder(vec![b"\xff\xfea\0,\0b\0\n\0", b""]).read_to_string(&mut output).unwrap();
+        assert_eq!(output, "a,b\n");
+    }
+}
@@ -0,0 +1,255 @@
+use bstr::{BString, ByteSlice};
+use anyhow::{Result, bail};
+use std::collections::HashSet;
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+
+// how many rows after the first one to compare it against
+pub const SAMPLE_ROWS: usize = 20;
+
+static DATE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(concat!(
+    r"^(?:\d{4}-\d{1,2}-\d{1,2}(?:[T ]\d{1,2}:\d{2}(?::\d{2}(?:\.\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?)?",
+    r"|\d{1,2}[/.-]\d{1,2}[/.-]\d{2,4}",
+    r"|\d{1,2}:\d{2}(?::\d{2}(?:\.\d+)?)?)$",
+)).unwrap());
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    Empty,
+    Number,
+    Date,
+    Bool,
+    Text,
+}
+
+impl Kind {
+    fn of(value: &[u8]) -> Self {
+        let value = value.trim();
+        if value.is_empty() {
+            Self::Empty
+        } else if is_number(value) {
+            Self::Number
+        } else if DATE_REGEX.is_match(value) {
+            Self::Date
+        } else if value.eq_ignore_ascii_case(b"true") || value.eq_ignore_ascii_case(b"false") {
+            Self::Bool
+        } else {
+            Self::Text
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::Number => "a number",
+            Self::Date => "a date",
+            Self::Bool => "a boolean",
+            Self::Text => "text",
+        }
+    }
+
+    fn plural(self) -> &'static str {
+        match self {
+            Self::Empty => "empty values",
+            Self::Number => "numbers",
+            Self::Date => "dates",
+            Self::Bool => "booleans",
+            Self::Text => "text",
+        }
+    }
+}
+
+fn is_number(value: &[u8]) -> bool {
+    let value = value.strip_prefix(b"-").or_else(|| value.strip_prefix(b"+")).unwrap_or(value);
+    let value = value.strip_prefix("$".as_bytes())
+        .or_else(|| value.strip_prefix("€".as_bytes()))
+        .or_else(|| value.strip_prefix("£".as_bytes()))
+        .unwrap_or(value);
+    let value = value.strip_suffix(b"%").unwrap_or(value);
+    let value: Vec<u8> = value.iter().copied().filter(|&c| c != b',').collect();
+    value.first().is_some_and(|c| c.is_ascii_digit() || *c == b'.')
+        && value.to_str().is_ok_and(|v| v.parse::<f64>().is_ok())
+}
+
+pub struct Decision {
+    pub is_header: bool,
+    pub reason: String,
+}
+
+// the old rule, used when the sample has nothing to say
+pub fn looks_like_header(row: &[BString]) -> bool {
+    row.iter().all(|c| matches!(c.first(), Some(b'_' | b'a' ..= b'z' | b'A' ..= b'Z')))
+}
+
+// compare the first row to the ones after it, column by column
+pub fn detect(first: &[BString], sample: &[&[BString]]) -> Decision {
+    let mut score = 0isize;
+    let mut reasons = vec![];
+
+    let mut seen = HashSet::new();
+    if first.iter().any(|c| !c.is_empty() && !seen.insert(c)) {
+        score -= 1;
+        reasons.push("the first row has repeated values".to_owned());
+    }
+
+    for (i, value) in first.iter().enumerate() {
+        let column: Vec<_> = sample.iter().filter_map(|row| row.get(i)).filter(|c| !c.trim().is_empty()).collect();
+        if column.is_empty() {
+            continue
+        }
+
+        let kind = Kind::of(value);
+        let kinds: Vec<_> = column.iter().map(|c| Kind::of(c)).collect();
+        let common = kinds.iter().copied()
+            .max_by_key(|&k| kinds.iter().filter(|&&x| x == k).count())
+            .unwrap();
+        // mostly one type, allowing for the odd bad value
+        let consistent = kinds.iter().filter(|&&k| k == common).count() * 5 >= kinds.len() * 4;
+
+        if kind == Kind::Empty {
+            score -= 1;
+            reasons.push(format!("column {} is empty", i + 1));
+        } else if column.contains(&value) {
+            score -= 1;
+            reasons.push(format!("{value:?} appears again in column {}", i + 1));
+        } else if consistent && common != Kind::Text && kind != common {
+            score += 2;
+            reasons.push(format!("{value:?} is {} above {}", kind.name(), common.plural()));
+        } else if consistent && common != Kind::Text {
+            score -= 2;
+            reasons.push(format!("{value:?} is {} like the rest of column {}", kind.name(), i + 1));
+        } else if kind == Kind::Text && common == Kind::Text && column.len() >= 3 {
+            // text over text, so see if the length stands out, it takes a few values to make a pattern
+            let len = value.chars().count();
+            let lengths: Vec<_> = column.iter().map(|c| c.chars().count()).collect();
+            let (min, max) = (*lengths.iter().min().unwrap(), *lengths.iter().max().unwrap());
+            if min == max && len == min {
+                score -= 1;
+                reasons.push(format!("{value:?} is {len} characters like the rest of column {}", i + 1));
+            } else if len > max * 2 || len * 2 < min {
+                score += 1;
+                reasons.push(format!("{value:?} is {len} characters, the rest of column {} are {min} to {max}", i + 1));
+            }
+        }
+    }
+
+    let (is_header, verdict) = if score != 0 {
+        (score > 0, format!("score {score}"))
+    } else {
+        let is_header = looks_like_header(first);
+        let verdict = if is_header { "no clear signal, every value starts with a letter" } else { "no clear signal" };
+        (is_header, verdict.to_owned())
+    };
+    if sample.is_empty() {
+        reasons.push("no other rows to compare against".to_owned());
+    }
+
+    Decision {
+        is_header,
+        reason: format!("{verdict}: {}", if reasons.is_empty() { "it looks like the rest of the rows".to_owned() } else { reasons.join(", ") }),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default, clap::ValueEnum)]
+pub enum DedupeHeader {
+    // id, id_2, id_3
+    Suffix,
+    Error,
+    #[default]
+    Keep,
+}
+
+// name empty columns and deal with repeated names
+pub fn dedupe(header: Vec<BString>, policy: DedupeHeader) -> Result<Vec<BString>> {
+    // empty names can't be picked out, whatever the policy
+    let mut header: Vec<BString> = header.into_iter()
+        .enumerate()
+        .map(|(i, name)| if name.trim().is_empty() { format!("column_{}", i + 1).into() } else { name })
+        .collect();
+    if policy == DedupeHeader::Keep {
+        return Ok(header)
+    }
+
+    let mut seen = HashSet::new();
+    for i in 0 .. header.len() {
+        if seen.contains(&header[i]) {
+            if policy == DedupeHeader::Error {
+                let first = header.iter().position(|h| *h == header[i]).unwrap();
+                bail!("duplicate header name {:?} in columns {} and {}", header[i], first + 1, i + 1);
+            }
+            // skip over any names that are already taken
+            let name = (2..)
+                .map(|n| { let mut name = header[i].clone(); name.extend_from_slice(format!("_{n}").as_bytes()); name })
+                .find(|name| !seen.contains(name) && !header[i+1 ..].contains(name))
+                .unwrap();
+            header[i] = name;
+        }
+        seen.insert(header[i].clone());
+    }
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_header(input: &str) -> bool {
+        let rows: Vec<Vec<BString>> = input.lines().map(|l| l.split(',').map(BString::from).collect()).collect();
+        let sample: Vec<&[BString]> = rows[1..].iter().map(|r| &r[..]).collect();
+        detect(&rows[0], &sample).is_header
+    }
+
+    #[test]
+    fn text_headers_over_text() {
+        assert!(is_header("name,status\nbob,active\nalice,active"));
+        assert!(is_header("a,b\nx,y\nz,w"));
+        assert!(is_header("iso,country\nus,United States\ngb,United Kingdom\nfr,France"));
+    }
+
+    #[test]
+    fn data_rows_that_start_with_letters() {
+        assert!(!is_header("alice,30,london\nbob,25,paris\ncarol,41,rome"));
+        assert!(!is_header("alice,bob,carol\nalice,dave,erin"));
+    }
+
+    #[test]
+    fn headers_that_dont_start_with_letters() {
+        assert!(is_header("2024_q1,%change\n10,5%\n20,-3%"));
+        assert!(is_header("name,age,joined\nalice,30,2024-01-05\nbob,25,2023-11-30"));
+    }
+
+    #[test]
+    fn lengths() {
+        // nothing but the length marks these out
+        assert!(is_header("1st favourite,2nd favourite\nred,blue\ngreen,yellow\ntan,grey"));
+        assert!(!is_header("uk,fr\nde,es\nit,pt\nnl,be"));
+        // too few values to go on
+        assert!(!is_header("1st favourite,2nd favourite\nred,blue\ngreen,yellow"));
+        assert!(is_header("uk,fr\nde,es\nit,pt"));
+    }
+
+    #[test]
+    fn no_sample() {
+        assert!(is_header("name,age"));
+        assert!(!is_header("1,2"));
+    }
+
+    fn dedupe_str(header: &str, policy: DedupeHeader) -> Result<Vec<BString>> {
+        dedupe(header.split(',').map(BString::from).collect(), policy)
+    }
+
+    #[test]
+    fn dedupe_fills_empty_names() {
+        assert_eq!(dedupe_str("a,,b, ", DedupeHeader::Keep).unwrap(), ["a", "column_2", "b", "column_4"]);
+        assert_eq!(dedupe_str("a,,b", DedupeHeader::Suffix).unwrap(), ["a", "column_2", "b"]);
+    }
+
+    #[test]
+    fn dedupe_repeated_names() {
+        assert_eq!(dedupe_str("id,id,x", DedupeHeader::Keep).unwrap(), ["id", "id", "x"]);
+        assert_eq!(dedupe_str("id,id,id", DedupeHeader::Suffix).unwrap(), ["id", "id_2", "id_3"]);
+        // id_2 is already taken further on
+        assert_eq!(dedupe_str("id,id,id_2", DedupeHeader::Suffix).unwrap(), ["id", "id_3", "id_2"]);
+        assert!(dedupe_str("id,x,id", DedupeHeader::Error).unwrap_err().to_string().contains("columns 1 and 3"));
+    }
+}
@@ -1,7 +1,9 @@
 use std::collections::VecDeque;
-use std::io::{Read, Write};
+use std::io::{Read, Write, BufRead, BufReader, IsTerminal};
+use std::path::Path;
 use bstr::{BString, BStr, ByteSlice};
-use anyhow::Result;
+use anyhow::{Result, Context};
+use crate::encoding::Encoding;
 use nix::fcntl::{fcntl, FcntlArg, OFlag, FdFlag};
 use std::os::fd::{AsFd, RawFd, AsRawFd};
 
@@ -18,7 +20,10 @@ pub struct Reader<R> {
     inner: R,
     buffer: BString,
     used: usize,
+    line_count: usize,
     pub is_eof: bool,
+    // records that have been read ahead: the row, whether it is a header and the line it started on
+    pub lookahead: VecDeque<(Vec<BString>, bool, usize)>,
 }
 
 impl<R: Read+AsFd> Reader<R> {
@@ -37,7 +42,9 @@ impl<R: Read> Reader<R> {
             inner,
             buffer: vec![].into(),
             used: 0,
+            line_count: 0,
             is_eof: false,
+            lookahead: VecDeque::new(),
         }
     }
 
@@ -67,6 +74,23 @@ impl<R: Read> Reader<R> {
         Ok(self)
     }
 
+    // the next few lines, without consuming them
+    pub fn peek_lines(&mut self, irs: &BStr, count: usize) -> Result<Vec<BString>> {
+        while !self.is_eof && self.buffer[..self.used].find_iter(irs).count() < count {
+            self.read()?;
+        }
+        let strip_cr = irs == b"\n";
+        let mut lines: Vec<BString> = self.buffer[..self.used]
+            .split_str(irs)
+            .take(count)
+            .map(|line| if strip_cr { line.strip_suffix(b"\r").unwrap_or(line) } else { line }.into())
+            .collect();
+        if lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        Ok(lines)
+    }
+
     pub fn line_reader(&mut self) -> LineReader<'_, R> {
         LineReader{ inner: self, start: 0 }
     }
@@ -92,6 +116,11 @@ impl<R: Read> LineReader<'_, R> {
         self.inner.is_eof
     }
 
+    // number of lines read so far
+    pub fn line_count(&self) -> usize {
+        self.inner.line_count
+    }
+
     pub fn get_line(&mut self, irs: &BStr) -> Option<(&BStr, bool)> {
         let strip_cr = irs == b"\n";
 
@@ -99,6 +128,7 @@ impl<R: Read> LineReader<'_, R> {
         if let Some((mut line, rest)) = slice.split_once_str(irs) {
             // got a line
             self.start = self.inner.used - rest.len();
+            self.inner.line_count += 1;
             if strip_cr {
                 line = line.strip_suffix(b"\r").unwrap_or(line);
             }
@@ -107,6 +137,7 @@ impl<R: Read> LineReader<'_, R> {
         // this is the last line - output if eof, otherwise save it for later
         } else if self.inner.is_eof && !slice.is_empty() {
             self.start = self.inner.used;
+            self.inner.line_count += 1;
             Some((slice.into(), true))
 
         } else {
@@ -171,3 +202,297 @@ impl<W: Write> Writer<W> {
         Ok(!self.buffers.is_empty())
     }
 }
+
+pub type BoxedReader = Box<dyn BufRead + Send>;
+
+#[derive(Debug, Copy, Clone, PartialEq, clap::ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+impl Compression {
+    fn detect(magic: &[u8]) -> Option<Self> {
+        if magic.starts_with(b"\x1f\x8b\x08") {
+            Some(Self::Gzip)
+        } else if magic.starts_with(b"\x28\xb5\x2f\xfd") {
+            Some(Self::Zstd)
+        } else if magic.starts_with(b"\xfd7zXZ\x00") {
+            Some(Self::Xz)
+        } else if magic.starts_with(b"BZh")
+            && magic.get(3).is_some_and(|c| (b'1' ..= b'9').contains(c))
+            // either a block or the end of the stream
+            && matches!(magic.get(4..10), Some(b"\x31\x41\x59\x26\x53\x59" | b"\x17\x72\x45\x38\x50\x90"))
+        {
+            Some(Self::Bzip2)
+        } else {
+            None
+        }
+    }
+}
+
+// sniff the magic bytes and decompress if needed,
+// only looking at what the first read gives so that streaming input isn't held up
+fn sniff<R: Read + Send + 'static>(inner: R) -> std::io::Result<BoxedReader> {
+    let mut inner = BufReader::new(inner);
+    let compression = Compression::detect(inner.fill_buf()?);
+
+    Ok(match compression {
+        Some(Compression::Gzip) => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(inner))),
+        Some(Compression::Zstd) => Box::new(BufReader::new(zstd::Decoder::with_buffer(inner)?)),
+        Some(Compression::Xz) => Box::new(BufReader::new(xz2::read::XzDecoder::new_multi_decoder(inner))),
+        Some(Compression::Bzip2) => Box::new(BufReader::new(bzip2::read::MultiBzDecoder::new(inner))),
+        None => Box::new(inner),
+    })
+}
+
+// defers sniffing until the first read, so nothing blocks if the input is never read
+enum Decompressor {
+    Pending(Box<dyn Read + Send>),
+    Ready(BoxedReader),
+}
+
+impl Decompressor {
+    fn reader(&mut self) -> std::io::Result<&mut BoxedReader> {
+        if let Self::Pending(inner) = self {
+            let inner = std::mem::replace(inner, Box::new(std::io::empty()));
+            *self = Self::Ready(sniff(inner)?);
+        }
+        match self {
+            Self::Ready(reader) => Ok(reader),
+            Self::Pending(_) => unreachable!(),
+        }
+    }
+}
+
+impl Read for Decompressor {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader()?.read(buf)
+    }
+}
+
+impl BufRead for Decompressor {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.reader()?.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Self::Ready(reader) = self {
+            reader.consume(amt);
+        }
+    }
+}
+
+pub fn decompress<R: Read + Send + 'static>(inner: R) -> BoxedReader {
+    Box::new(Decompressor::Pending(Box::new(inner)))
+}
+
+fn decode(reader: BoxedReader, encoding: Option<Encoding>) -> BoxedReader {
+    match encoding {
+        Some(encoding) => crate::encoding::decode(reader, encoding),
+        None => reader,
+    }
+}
+
+pub fn open_file<P: AsRef<Path>>(path: P, encoding: Option<Encoding>) -> Result<BoxedReader> {
+    Ok(decode(decompress(std::fs::File::open(path)?), encoding))
+}
+
+pub fn stdin(encoding: Option<Encoding>) -> BoxedReader {
+    let stdin = std::io::stdin();
+    let reader = if stdin.is_terminal() {
+        // don't wait for the magic bytes when someone is typing
+        Box::new(BufReader::new(stdin))
+    } else {
+        decompress(stdin)
+    };
+    decode(reader, encoding)
+}
+
+type SharedWriter = std::sync::Arc<std::sync::Mutex<Box<dyn Write + Send>>>;
+
+// lets the library read and write somewhere other than stdin and stdout
+#[derive(Clone, Default)]
+pub struct Redirect {
+    input: std::sync::Arc<std::sync::Mutex<Option<Box<dyn Read + Send>>>>,
+    output: Option<SharedWriter>,
+}
+
+impl std::fmt::Debug for Redirect {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Redirect").field("output", &self.output.is_some()).finish()
+    }
+}
+
+impl Redirect {
+    pub fn new<R: Read + Send + 'static, W: Write + Send + 'static>(input: R, output: W) -> Self {
+        Self {
+            input: std::sync::Arc::new(std::sync::Mutex::new(Some(Box::new(input)))),
+            output: Some(std::sync::Arc::new(std::sync::Mutex::new(Box::new(output)))),
+        }
+    }
+
+    pub fn has_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    // output to another process, counting a redirected output as one since it isn't a file or terminal
+    pub fn is_stdout_fifo(&self) -> bool {
+        use nix::sys::stat::{fstat, SFlag};
+        self.output.is_some() || !fstat(std::io::stdout().as_fd())
+            .is_ok_and(|s| !SFlag::S_IFIFO.intersects(SFlag::from_bits_truncate(s.st_mode)))
+    }
+
+    // the input can only be read once, by the first command
+    pub fn stdin(&self, encoding: Option<Encoding>) -> BoxedReader {
+        match self.input.lock().unwrap().take() {
+            Some(input) => decode(decompress(input), encoding),
+            None if self.output.is_some() => Box::new(std::io::empty()),
+            None => stdin(encoding),
+        }
+    }
+
+    pub fn stdout(&self) -> Box<dyn Write> {
+        match &self.output {
+            Some(output) => Box::new(Shared(output.clone())),
+            None => Box::new(std::io::stdout().lock()),
+        }
+    }
+}
+
+struct Shared(SharedWriter);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+// an encoder that can write its trailer and report any error, rather than on drop
+trait Encoder: Write {
+    fn finish(self: Box<Self>) -> std::io::Result<()>;
+}
+
+impl<W: Write> Encoder for flate2::write::GzEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish()?.flush()
+    }
+}
+
+impl<W: Write> Encoder for zstd::Encoder<'static, W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish()?.flush()
+    }
+}
+
+impl<W: Write> Encoder for xz2::write::XzEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish()?.flush()
+    }
+}
+
+impl<W: Write> Encoder for bzip2::write::BzEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish()?.flush()
+    }
+}
+
+type SharedEncoder = std::rc::Rc<std::cell::RefCell<Option<Box<dyn Encoder>>>>;
+
+struct Compressor(SharedEncoder);
+
+impl Write for Compressor {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.0.borrow_mut().as_mut() {
+            Some(encoder) => encoder.write(buf),
+            None => Err(std::io::Error::other("compressed output is already finished")),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // flushing after every row would ruin the compression
+        Ok(())
+    }
+}
+
+// ends the compressed stream once everything has been written
+pub struct Finish(SharedEncoder);
+
+impl Finish {
+    pub fn finish(self) -> std::io::Result<()> {
+        match self.0.borrow_mut().take() {
+            Some(encoder) => encoder.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn compress<W: Write + 'static>(inner: W, compression: Compression) -> Result<(Box<dyn Write>, Finish)> {
+    let encoder: Box<dyn Encoder> = match compression {
+        Compression::Gzip => Box::new(flate2::write::GzEncoder::new(inner, flate2::Compression::default())),
+        Compression::Zstd => Box::new(zstd::Encoder::new(inner, 0).context("failed to start zstd")?),
+        Compression::Xz => Box::new(xz2::write::XzEncoder::new(inner, 6)),
+        Compression::Bzip2 => Box::new(bzip2::write::BzEncoder::new(inner, bzip2::Compression::default())),
+    };
+    let encoder = std::rc::Rc::new(std::cell::RefCell::new(Some(encoder)));
+    Ok((Box::new(Compressor(encoder.clone())), Finish(encoder)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // gives out one chunk and then fails, like a pipe where nothing more has been written yet
+    struct OneChunk(Option<&'static [u8]>);
+
+    impl Read for OneChunk {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let chunk = self.0.take().expect("read past the first chunk");
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn sniff_only_reads_what_is_there() {
+        let mut reader = sniff(OneChunk(Some(b"a\n"))).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "a\n");
+    }
+
+    struct Output(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn compress_round_trip() {
+        for compression in [Compression::Gzip, Compression::Zstd, Compression::Xz, Compression::Bzip2] {
+            let output = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let (mut file, finish) = compress(Output(output.clone()), compression).unwrap();
+            file.write_all(b"a,b\n1,2\n").unwrap();
+            drop(file);
+            finish.finish().unwrap();
+
+            let compressed = output.take();
+            assert_eq!(Compression::detect(&compressed), Some(compression));
+            let mut text = String::new();
+            decompress(std::io::Cursor::new(compressed)).read_to_string(&mut text).unwrap();
+            assert_eq!(text, "a,b\n1,2\n", "{compression:?}");
+        }
+    }
+}
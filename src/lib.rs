@@ -0,0 +1,103 @@
+//! The dsv command line tool as a library.
+//!
+//! [`Pipeline`] runs the same commands as `dsv` over any reader and writer:
+//!
+//! ```
+//! use dsv::Pipeline;
+//!
+//! let input = "name,size\nb,2\na,1\n";
+//! let (code, output) = Pipeline::new()
+//!     .option("--csv")
+//!     .sort(["name"])
+//!     .output(std::io::Cursor::new(input))
+//!     .unwrap();
+//! assert_eq!(code, std::process::ExitCode::SUCCESS);
+//! assert_eq!(output, b"name,size\na,1\nb,2\n");
+//! ```
+//!
+//! The pieces dsv is built from are also here:
+//!
+//! * [`guess_delimiter`] sniffs the separator of a line.
+//! * [`LineParser`] splits lines into fields, with quotes and escapes.
+//! * [`ColumnSlicer`] picks columns by name, index, range, glob or regex.
+//! * [`justify`] lines up columns like the pretty output.
+//!
+//! Every subcommand is available through [`Pipeline::stage`] by name, but the
+//! `Processor` and `Writer` traits behind them are not part of the API and may change.
+//! A custom stage can be written as a `dsv-NAME` plugin instead.
+//! Errors are all a [`dsv::Error`](Error).
+
+mod base;
+mod subcommands;
+mod column_slicer;
+mod writer;
+mod python;
+mod utils;
+mod io;
+mod encoding;
+mod header;
+mod config;
+mod script;
+mod pipeline;
+mod parse;
+use std::io::IsTerminal;
+use std::process::ExitCode;
+use anyhow::Result;
+use bstr::BStr;
+use clap::CommandFactory;
+use std::sync::Mutex;
+
+pub use base::Ifs;
+pub use column_slicer::ColumnSlicer;
+pub use pipeline::{Pipeline, Error};
+pub use parse::LineParser;
+pub use writer::justify;
+use base::Processor;
+use subcommands::Cli;
+
+pub(crate) static CONTROL_C_HANDLERS: Mutex<Vec<fn()>> = Mutex::new(vec![]);
+
+fn run_cleanup() {
+    for func in CONTROL_C_HANDLERS.lock().unwrap().iter() {
+        func();
+    }
+}
+
+/// Guesses the delimiter from a line, e.g. a comma for `a,b,c`, or a tab if there are no clues.
+///
+/// ```
+/// assert!(matches!(dsv::guess_delimiter(b"a,b,c"), dsv::Ifs::Plain(d) if d == ","));
+/// ```
+pub fn guess_delimiter(line: &[u8]) -> Ifs {
+    <base::DefaultProcessor as Processor>::guess_delimiter(BStr::new(line), b"\t".into())
+}
+
+// what the dsv binary runs
+#[doc(hidden)]
+pub fn main() -> Result<ExitCode> {
+    let args = config::args()?;
+    let cli = Cli::parse_args(args.clone());
+
+    ctrlc::set_handler(|| {
+        run_cleanup();
+        std::process::exit(130);
+    })?;
+
+    let result = subcommands::run(cli.command, cli.opts, args, |base, receiver| {
+        if std::io::stdin().is_terminal() {
+            Cli::command().print_help()?;
+            Ok(ExitCode::SUCCESS)
+        } else {
+            // run as if cat
+            base::DefaultProcessor{}.run(base, receiver)
+        }
+    });
+    run_cleanup();
+
+    // bad arguments to a command in a pipeline
+    match result.map_err(|e| e.downcast::<clap::Error>()) {
+        Err(Ok(e)) => e.exit(),
+        Err(Err(e)) => Err(e),
+        Ok(code) => Ok(code),
+    }
+}
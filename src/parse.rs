@@ -0,0 +1,74 @@
+use bstr::{BString, ByteVec};
+use crate::base::{BaseOptions, Ifs, ScopelessBase};
+use crate::pipeline::Error;
+
+/// Splits lines into fields the way dsv reads its input, with quoted fields that can span lines.
+///
+/// ```
+/// use dsv::{Ifs, LineParser};
+///
+/// let mut parser = LineParser::new(Ifs::Plain(",".into()));
+/// assert_eq!(parser.parse_line(b"a,\"b, c\",d"), Some(vec!["a".into(), "b, c".into(), "d".into()]));
+/// // the quote is still open, so the row goes on to the next line
+/// assert_eq!(parser.parse_line(b"x,\"multi"), None);
+/// assert_eq!(parser.parse_line(b"line\""), Some(vec!["x".into(), "multi\nline".into()]));
+/// parser.finish().unwrap();
+/// ```
+pub struct LineParser {
+    base: ScopelessBase,
+    row: Vec<BString>,
+}
+
+impl LineParser {
+    /// A parser that splits on `ifs` with `"` as the quote character.
+    pub fn new(ifs: Ifs) -> Self {
+        let mut base = ScopelessBase::new(BaseOptions::default(), std::sync::mpsc::channel().0);
+        base.ifs = ifs;
+        Self {
+            base,
+            row: vec![],
+        }
+    }
+
+    /// Quote fields with this character instead of `"`.
+    pub fn quote_char(mut self, quote: u8) -> Self {
+        self.base.opts.quote_char = Some(quote);
+        self
+    }
+
+    /// Treat the character after this one literally, e.g. `\,` is a comma inside a field.
+    pub fn escape_char(mut self, escape: u8) -> Self {
+        self.base.opts.escape_char = Some(escape);
+        self
+    }
+
+    /// Don't treat quotes specially.
+    pub fn no_quoting(mut self) -> Self {
+        self.base.opts.no_quoting = true;
+        self
+    }
+
+    /// Parses a line without its line ending, returning the row once it is complete.
+    pub fn parse_line(&mut self, line: &[u8]) -> Option<Vec<BString>> {
+        let quote = self.base.opts.quote_char();
+        let (row, incomplete) = self.base.parse_line(line.into(), std::mem::take(&mut self.row), quote);
+        if !incomplete {
+            return Some(row)
+        }
+        self.row = row;
+        // add the embedded newline
+        if let Some(last) = self.row.last_mut() {
+            last.push_byte(b'\n');
+        }
+        None
+    }
+
+    /// Checks the last row was complete.
+    pub fn finish(self) -> Result<(), Error> {
+        if self.row.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Failed("unterminated quote at the end of the input".into()))
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use crate::base::{DefaultProcessor, Processor};
+use crate::subcommands::{self, Cli};
+use crate::utils::Break;
+
+/// Why a [`Pipeline`] could not run.
+#[derive(Debug)]
+pub enum Error {
+    /// Bad options or arguments to a command, with the usage message.
+    Usage(String),
+    /// Reading the input or writing the output failed.
+    Io(std::io::Error),
+    /// A command gave up, e.g. an unknown field or a ragged row under `--strict`.
+    Failed(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Usage(e) => write!(f, "{}", e.trim_end()),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Failed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Usage(_) => None,
+            Self::Io(e) => Some(e),
+            Self::Failed(e) => e.source(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Self>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        let e = match e.downcast::<clap::Error>() {
+            Ok(e) => return Self::Usage(e.render().to_string()),
+            Err(e) => e,
+        };
+        match e.downcast::<std::io::Error>() {
+            Ok(e) => Self::Io(e),
+            Err(e) => Self::Failed(e.into()),
+        }
+    }
+}
+
+/// A series of dsv commands, the same as `dsv OPTIONS ! COMMAND ARGS ! COMMAND ARGS ...`.
+///
+/// ```
+/// use dsv::Pipeline;
+///
+/// let pipeline = Pipeline::new().option("--csv").grep("ERROR").sort(["ts"]);
+/// assert_eq!(pipeline.args(), ["dsv", "--csv", "!", "grep", "-e", "ERROR", "!", "sort", "-k", "ts"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    options: Vec<OsString>,
+    stages: Vec<Vec<OsString>>,
+}
+
+impl Pipeline {
+    /// An empty pipeline, which copies the input like `dsv cat`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an option for every command, e.g. `--csv` or `--no-header`.
+    pub fn option<S: Into<OsString>>(mut self, option: S) -> Self {
+        self.options.push(option.into());
+        self
+    }
+
+    /// Adds any command with its arguments, e.g. `.stage("uniq", ["-c"])`.
+    pub fn stage<I: IntoIterator<Item=S>, S: Into<OsString>>(mut self, command: &str, args: I) -> Self {
+        self.stages.push(std::iter::once(command.into()).chain(args.into_iter().map(Into::into)).collect());
+        self
+    }
+
+    /// Keeps the rows matching a regex.
+    pub fn grep(self, pattern: &str) -> Self {
+        self.stage("grep", ["-e", pattern])
+    }
+
+    /// Keeps only these columns.
+    pub fn cut<I: IntoIterator<Item=S>, S: AsRef<str>>(self, fields: I) -> Self {
+        self.stage("cut", fields.into_iter().flat_map(|f| ["-f".to_owned(), f.as_ref().to_owned()]))
+    }
+
+    /// Sorts by these columns.
+    pub fn sort<I: IntoIterator<Item=S>, S: AsRef<str>>(self, fields: I) -> Self {
+        self.stage("sort", fields.into_iter().flat_map(|f| ["-k".to_owned(), f.as_ref().to_owned()]))
+    }
+
+    /// Keeps the first `lines` rows.
+    pub fn head(self, lines: usize) -> Self {
+        self.stage("head", ["-n".to_owned(), lines.to_string()])
+    }
+
+    /// Keeps the last `lines` rows.
+    pub fn tail(self, lines: usize) -> Self {
+        self.stage("tail", ["-n".to_owned(), lines.to_string()])
+    }
+
+    /// The command line this pipeline is equivalent to.
+    pub fn args(&self) -> Vec<OsString> {
+        let mut args = vec![OsString::from(env!("CARGO_PKG_NAME"))];
+        args.extend(self.options.iter().cloned());
+        if let [stage] = &self.stages[..] {
+            args.extend(stage.iter().cloned());
+        } else {
+            for stage in &self.stages {
+                args.push("!".into());
+                args.extend(stage.iter().cloned());
+            }
+        }
+        args
+    }
+
+    /// Runs the pipeline from `input` to `output`.
+    ///
+    /// A failure exit code means the same as on the command line, e.g. grep found nothing.
+    pub fn run<R: Read + Send + 'static, W: Write + Send + 'static>(&self, input: R, output: W) -> Result<ExitCode, Error> {
+        let args = self.args();
+        let cli = Cli::try_parse_args(args.clone()).map_err(|e| Error::Usage(e.render().to_string()))?;
+        let mut opts = cli.opts;
+        opts.inner.redirect = crate::io::Redirect::new(input, output);
+
+        let result = subcommands::run(cli.command, opts, args, |base, receiver| DefaultProcessor{}.run(base, receiver));
+        match result {
+            Ok(code) => Ok(code),
+            Err(e) if e.is::<Break>() => Ok(ExitCode::SUCCESS),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Runs the pipeline and returns the exit code with everything it wrote.
+    ///
+    /// ```
+    /// let (_, output) = dsv::Pipeline::new().option("--csv").cut(["b"]).output(&b"a,b\n1,2\n"[..]).unwrap();
+    /// assert_eq!(output, b"b\n2\n");
+    /// ```
+    pub fn output<R: Read + Send + 'static>(&self, input: R) -> Result<(ExitCode, Vec<u8>), Error> {
+        let buffer = Buffer::default();
+        let code = self.run(input, buffer.clone())?;
+        let output = std::mem::take(&mut *buffer.0.lock().unwrap());
+        Ok((code, output))
+    }
+}
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
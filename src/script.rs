@@ -0,0 +1,164 @@
+use std::ffi::OsString;
+use std::collections::HashMap;
+use anyhow::{Result, Context, bail};
+use crate::config::{command_index, shell_split_with, value_options};
+
+// a pipeline script has one command per line, e.g.
+//
+//   #!/usr/bin/env -S dsv -f
+//   --csv                    # lines starting with - are options for every command
+//   grep -k level "$LEVEL"
+//   sort ts \
+//       --reverse
+//   cut ts,msg
+pub fn parse(contents: &str, vars: &HashMap<String, String>) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+    let lookup = |name: &str| -> Result<String> {
+        vars.get(name).cloned()
+            .or_else(|| std::env::var(name).ok())
+            .with_context(|| format!("undefined variable ${name} (use --var {name}=VALUE)"))
+    };
+
+    let mut options = vec![];
+    let mut stages = vec![];
+    let mut line = String::new();
+    let mut line_number = 0;
+
+    for (i, part) in contents.lines().enumerate() {
+        if line.is_empty() {
+            line_number = i + 1;
+        }
+        // a trailing backslash continues onto the next line
+        if let Some(part) = part.strip_suffix('\\') {
+            line.push_str(part);
+            line.push(' ');
+            continue
+        }
+        line.push_str(part);
+
+        let words = shell_split_with(&line, Some(&lookup)).with_context(|| format!("line {line_number}"))?;
+        line.clear();
+        if words.first().is_some_and(|w| w.starts_with('-')) {
+            options.extend(words);
+        } else {
+            // ! can also separate commands on the same line
+            stages.extend(words.split(|w| w == "!").filter(|s| !s.is_empty()).map(|s| s.to_owned()));
+        }
+    }
+    if !line.is_empty() {
+        bail!("line {line_number} ends with a backslash");
+    }
+    if stages.is_empty() {
+        bail!("no commands in the script");
+    }
+    Ok((options, stages))
+}
+
+// turns `dsv -f FILE [--var NAME=VALUE] [OPTIONS]` into `dsv OPTIONS ! COMMAND ! COMMAND ...`
+pub fn expand_args(args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let takes_value = value_options();
+    let Some((arg0, rest)) = args.split_first() else { return Ok(args) };
+    let command = command_index(rest, &takes_value);
+
+    let mut file = None;
+    let mut vars = HashMap::new();
+    let mut options = vec![];
+    let mut rest = rest[.. command.unwrap_or(rest.len())].iter();
+    while let Some(arg) = rest.next() {
+        let text = arg.to_str().unwrap_or_default();
+        let (flag, value) = match text.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(OsString::from(value))),
+            _ if text.starts_with("-f") && text.len() > 2 => ("-f", Some(OsString::from(&text[2..]))),
+            _ => (text, None),
+        };
+
+        match flag {
+            "-f" | "--file" | "--var" => {
+                let value = value.or_else(|| rest.next().cloned()).with_context(|| format!("{flag} needs a value"))?;
+                if flag == "--var" {
+                    let value = value.to_str().context("--var is not valid utf-8")?;
+                    let Some((name, value)) = value.split_once('=') else { bail!("expected --var NAME=VALUE, not {value:?}") };
+                    vars.insert(name.to_owned(), value.to_owned());
+                } else {
+                    file = Some(value);
+                }
+            },
+            _ => {
+                options.push(arg.clone());
+                // keep the value of other options with them
+                if value.is_none() && takes_value.get(flag).copied().unwrap_or(false) {
+                    options.extend(rest.next().cloned());
+                }
+            },
+        }
+    }
+
+    let Some(file) = file else { return Ok(args) };
+    if let Some(command) = command {
+        bail!("can't give both --file and a command ({:?})", args[command + 1]);
+    }
+
+    let path = std::path::Path::new(&file);
+    let contents = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let (script_options, stages) = parse(&contents, &vars).with_context(|| format!("in {}", path.display()))?;
+
+    // the command line wins over the script
+    let mut expanded = vec![arg0.clone()];
+    expanded.extend(script_options.into_iter().map(OsString::from));
+    expanded.extend(options);
+    expanded.push("!".into());
+    for (i, stage) in stages.into_iter().enumerate() {
+        if i > 0 {
+            expanded.push("!".into());
+        }
+        expanded.extend(stage.into_iter().map(OsString::from));
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn options_and_stages() {
+        let script = "#!/usr/bin/env -S dsv -f\n--csv  # for every command\n\ngrep -k level \"$LEVEL\"\nsort ts \\\n    --reverse\ncut ts,msg ! head 5\n";
+        let (options, stages) = parse(script, &vars(&[("LEVEL", "ERROR warn")])).unwrap();
+        assert_eq!(options, ["--csv"]);
+        assert_eq!(stages, [
+            vec!["grep", "-k", "level", "ERROR warn"],
+            vec!["sort", "ts", "--reverse"],
+            vec!["cut", "ts,msg"],
+            vec!["head", "5"],
+        ]);
+    }
+
+    #[test]
+    fn errors() {
+        let err = parse("cat\ngrep $NO_SUCH_DSV_VARIABLE\n", &vars(&[])).unwrap_err();
+        assert_eq!(format!("{err:#}"), "line 2: undefined variable $NO_SUCH_DSV_VARIABLE (use --var NO_SUCH_DSV_VARIABLE=VALUE)");
+        let err = parse("cat\nsort \\\n  a \\\n", &vars(&[])).unwrap_err();
+        assert_eq!(err.to_string(), "line 2 ends with a backslash");
+        let err = parse("--csv\n# nothing else\n", &vars(&[])).unwrap_err();
+        assert_eq!(err.to_string(), "no commands in the script");
+    }
+
+    #[test]
+    fn expand_file() {
+        let path = std::env::temp_dir().join(format!("dsv-script-test-{}", std::process::id()));
+        std::fs::write(&path, "--csv\ngrep x \"$V\"\ncut a\n").unwrap();
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        let file = path.to_str().unwrap();
+        let result = expand_args(args(&["dsv", "-f", file, "--var", "V=y z", "--tsv"]));
+        let result2 = expand_args(args(&["dsv", &format!("--file={file}"), "cut", "b"]));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap(), args(&["dsv", "--csv", "--tsv", "!", "grep", "x", "y z", "!", "cut", "a"]));
+        assert!(result2.unwrap_err().to_string().contains("can't give both --file and a command"));
+        // no -f leaves the arguments alone
+        assert_eq!(expand_args(args(&["dsv", "--csv", "cut", "a"])).unwrap(), args(&["dsv", "--csv", "cut", "a"]));
+    }
+}
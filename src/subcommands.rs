@@ -1,9 +1,9 @@
-use std::ffi::{OsStr};
+use std::ffi::{OsStr, OsString};
 use std::process::ExitCode;
 use std::sync::mpsc::{self, Sender, Receiver};
 use anyhow::Result;
 use crate::base::{Base, Processor, BaseOptions, Message, Callbacks};
-use clap::{Subcommand, Parser, CommandFactory};
+use clap::{Subcommand, Parser, CommandFactory, FromArgMatches};
 use std::io::{BufRead};
 
 #[derive(Parser)]
@@ -14,6 +14,26 @@ pub struct Cli {
     pub command: Option<Command>,
     #[command(flatten)]
     pub opts: BaseOptions,
+    // these are handled before parsing, see script.rs
+    #[arg(short = 'f', long, value_name = "FILE", help = "read a pipeline from a file, one command per line")]
+    file: Option<std::path::PathBuf>,
+    #[arg(long = "var", value_name = "NAME=VALUE", help = "set a variable for the pipeline file, otherwise they come from the environment")]
+    vars: Vec<String>,
+}
+
+impl Cli {
+    // repeated options override each other, so the config can set defaults the command line overrides
+    pub fn try_parse_args<I: IntoIterator<Item=T>, T: Into<OsString> + Clone>(args: I) -> Result<Self, clap::Error> {
+        let command = Self::command().args_override_self(true);
+        let names: Vec<_> = command.get_subcommands().map(|c| c.get_name().to_owned()).collect();
+        let mut command = names.iter().fold(command, |command, name| command.mut_subcommand(name, |c| c.args_override_self(true)));
+        let matches = command.try_get_matches_from_mut(args)?;
+        Self::from_arg_matches(&matches).map_err(|e| e.format(&mut command))
+    }
+
+    pub fn parse_args<I: IntoIterator<Item=T>, T: Into<OsString> + Clone>(args: I) -> Self {
+        Self::try_parse_args(args).unwrap_or_else(|e| e.exit())
+    }
 }
 
 macro_rules! add_subcommands {
@@ -22,6 +42,7 @@ macro_rules! add_subcommands {
             pub mod $name;
         )*
         pub mod _pipeline;
+        pub mod _plugin;
 
         #[derive(Subcommand)]
         #[allow(non_camel_case_types)]
@@ -31,11 +52,15 @@ macro_rules! add_subcommands {
             )*
             #[command(name = "!")]
             _pipeline(_pipeline::Opts),
+            // dsv-NAME on the PATH
+            #[command(external_subcommand)]
+            _plugin(Vec<OsString>),
         }
 
         pub fn run<F: Fn(&mut Base, Receiver<Message>) -> Result<ExitCode>>(
             subcommand: Option<Command>,
             mut cli_opts: BaseOptions,
+            args: Vec<OsString>,
             default: F,
         ) -> Result<ExitCode> {
             std::thread::scope(|scope| {
@@ -47,9 +72,9 @@ macro_rules! add_subcommands {
                         Some(Command::$name(opts)) => $name::Handler::new(opts, &mut base)?.run(&mut base, receiver),
                     )*
                     Some(Command::_pipeline(opts)) => {
-                        let args = std::env::args_os().collect();
                         _pipeline::Handler::new(opts, &mut base, args)?.run(&mut base, receiver)
                     },
+                    Some(Command::_plugin(args)) => _plugin::Handler::new(args, &mut base)?.run(&mut base, receiver),
                     None => default(&mut base, receiver),
                 }
             })
@@ -60,6 +85,7 @@ macro_rules! add_subcommands {
             $(
                 $name($name::Handler),
             )*
+            _plugin(_plugin::Handler),
         }
 
         impl Subcommands {
@@ -68,16 +94,19 @@ macro_rules! add_subcommands {
                 sender: Sender<Message>,
                 scope: &'a std::thread::Scope<'a, 'b>,
                 is_stdout_tty: bool,
+                redirect: &crate::io::Redirect,
             ) -> Result<(Self, Base<'a, 'b>)> {
 
                 const ARG0: &str = env!("CARGO_PKG_NAME");
-                let mut cli = Cli::parse_from(std::iter::once(ARG0.as_ref()).chain(args));
+                let mut cli = Cli::try_parse_args(std::iter::once(ARG0.as_ref()).chain(args))?;
+                cli.opts.inner.redirect = redirect.clone();
                 cli.opts.post_process(Some(is_stdout_tty));
                 let mut base = Base::new(cli.opts, sender, scope);
                 let handler = match cli.command {
                     $(
                         Some(Command::$name(opts)) => Self::$name($name::Handler::new(opts, &mut base)?),
                     )*
+                    Some(Command::_plugin(args)) => Self::_plugin(_plugin::Handler::new(args, &mut base)?),
                     Some(Command::_pipeline(_)) | None => {
                         Cli::command().print_help()?;
                         return crate::utils::Break.to_err()
@@ -90,24 +119,28 @@ macro_rules! add_subcommands {
             pub fn register_cleanup(&self) {
                 match self {
                     $( Self::$name(handler) => handler.register_cleanup(), )*
+                    Self::_plugin(handler) => handler.register_cleanup(),
                 }
             }
 
             pub fn forward_messages(self, base: &mut Base, receiver: Receiver<Message>) -> Result<ExitCode> {
                 match self {
                     $( Self::$name(handler) => handler.forward_messages(base, receiver), )*
+                    Self::_plugin(handler) => handler.forward_messages(base, receiver),
                 }
             }
 
             pub fn process_file<R: BufRead>(self, file: R, base: &mut Base, do_callbacks: Callbacks) -> Result<ExitCode> {
                 match self {
                     $( Self::$name(handler) => handler.process_file(file, base, do_callbacks), )*
+                    Self::_plugin(handler) => handler.process_file(file, base, do_callbacks),
                 }
             }
 
             pub fn run(self, base: &mut Base, receiver: Receiver<Message>) -> Result<ExitCode> {
                 match self {
                     $( Self::$name(handler) => handler.run(base, receiver), )*
+                    Self::_plugin(handler) => handler.run(base, receiver),
                 }
             }
 
@@ -121,6 +154,12 @@ macro_rules! add_subcommands {
                         });
                     },
                     )*
+                    Self::_plugin(handler) => {
+                        let mut writer = handler.make_writer(base.opts.clone());
+                        base.scope.spawn(move || {
+                            writer.run(receiver)
+                        });
+                    },
                 }
             }
 
@@ -133,11 +172,17 @@ add_subcommands!(
     cat,
     cut,
     flip,
+    fromarrow,
     fromhtml,
     fromjson,
     frommarkdown,
+    fromparquet,
+    fromregex,
+    fromxlsx,
+    fromxml,
     grep,
     head,
+    infer_schema,
     join,
     page,
     paste,
@@ -155,10 +200,16 @@ add_subcommands!(
     summary,
     tac,
     tail,
+    toarrow,
     tocsv,
+    tohtml,
     tojson,
     tomarkdown,
+    toparquet,
     totsv,
+    toxlsx,
+    toxml,
     uniq,
+    validate,
     xargs,
 );
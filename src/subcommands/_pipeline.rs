@@ -59,6 +59,7 @@ impl Processor for Handler {
                 new_sender,
                 base.scope,
                 base.opts.inner.is_stdout_tty && i == 0,
+                &base.opts.inner.redirect,
             );
             if let Ok((handler, base)) = sub {
                 handler.register_cleanup();
@@ -97,7 +98,7 @@ impl Processor for Handler {
 
             // first handler gets to read from stdin
             let (handler, mut base, _) = first;
-            let result = handler.process_file(std::io::stdin().lock(), &mut base, Callbacks::all());
+            let result = handler.process_file(base.opts.inner.redirect.stdin(base.opts.encoding), &mut base, Callbacks::all());
             err_sender.send(result).unwrap();
 
         } else {
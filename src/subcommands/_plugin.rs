@@ -0,0 +1,180 @@
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread::JoinHandle;
+use crate::base::{self, Message, Ofs};
+use crate::utils::{Break, MaybeBreak};
+use anyhow::{Result, Context, bail};
+use bstr::{BString, ByteSlice};
+use clap::CommandFactory;
+use serde_json::{json, Value};
+
+const PREFIX: &str = "dsv-";
+
+// unknown subcommands run dsv-NAME from the PATH, which reads and writes one json message per line:
+//   {"type": "header", "row": ["a", "b"]}
+//   {"type": "row", "row": ["1", "2"]}
+//   {"type": "ofs", "value": ","} (null for pretty output)
+//   {"type": "separator"}
+//   {"type": "eof"}
+// and it can also write {"type": "raw", "value": "..."} and {"type": "stderr", "row": [...]}
+pub fn find(name: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(format!("{PREFIX}{name}")))
+        .find(|path| path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0))
+}
+
+fn unknown_subcommand(name: &str) -> String {
+    let command = super::Cli::command();
+    let mut candidates: Vec<_> = command.get_subcommands()
+        .map(|c| c.get_name())
+        .filter(|c| !c.starts_with('!'))
+        .map(|c| (crate::utils::edit_distance(name, c), c))
+        .filter(|(distance, _)| *distance <= 2)
+        .collect();
+    candidates.sort();
+
+    let mut message = format!("unrecognized subcommand {name:?} and no {PREFIX}{name} on the PATH");
+    if let Some((_, candidate)) = candidates.first() {
+        message += &format!(", did you mean {candidate:?}?");
+    }
+    message
+}
+
+fn to_json(row: &[BString]) -> Value {
+    row.iter().map(|c| c.to_str_lossy()).collect()
+}
+
+fn to_row(value: Option<&Value>) -> Result<Vec<BString>> {
+    let Some(Value::Array(row)) = value else { bail!("expected a list of strings") };
+    Ok(row.iter().map(|c| match c {
+        Value::String(c) => c.as_str().into(),
+        Value::Null => BString::default(),
+        c => c.to_string().into(),
+    }).collect())
+}
+
+fn parse_message(line: &[u8]) -> Result<Option<Message>> {
+    let value: Value = serde_json::from_slice(line)?;
+    let row = || to_row(value.get("row"));
+    let text = |key| value.get(key).and_then(|v| v.as_str()).map(BString::from);
+
+    Ok(Some(match value.get("type").and_then(|t| t.as_str()) {
+        Some("header") => Message::Header(row()?),
+        Some("row") => Message::Row(row()?),
+        Some("separator") => Message::Separator,
+        Some("ofs") => Message::Ofs(text("value").map_or(Ofs::Pretty, Ofs::Plain)),
+        Some("raw") => Message::Raw(text("value").context("raw message has no value")?, true, false),
+        Some("stderr") => Message::Stderr(row()?),
+        Some("eof") => return Ok(None),
+        _ => bail!("unknown message type"),
+    }))
+}
+
+// forwards what the plugin writes until it says eof or exits
+fn read_output<R: BufRead>(name: String, output: R, sender: Sender<Message>) -> Result<()> {
+    for (i, line) in output.split(b'\n').enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue
+        }
+        let msg = parse_message(&line).with_context(|| format!("{PREFIX}{name} line {}: {:?}", i + 1, line.as_bstr()))?;
+        let Some(msg) = msg else { break };
+        if sender.send(msg).is_err() {
+            break
+        }
+    }
+    Ok(())
+}
+
+pub struct Handler {
+    name: String,
+    child: Child,
+    stdin: Option<BufWriter<ChildStdin>>,
+    reader: Option<JoinHandle<Result<()>>>,
+}
+
+impl Handler {
+    pub fn new(mut args: Vec<OsString>, base: &mut base::Base) -> Result<Self> {
+        let name = args.remove(0).to_string_lossy().into_owned();
+        let Some(path) = find(&name) else { bail!("{}", unknown_subcommand(&name)) };
+
+        let mut child = Command::new(&path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run {}", path.display()))?;
+
+        let stdin = child.stdin.take().map(BufWriter::new);
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let sender = base.sender.clone();
+        let reader = {
+            let name = name.clone();
+            std::thread::spawn(move || read_output(name, stdout, sender))
+        };
+
+        Ok(Self {
+            name,
+            child,
+            stdin,
+            reader: Some(reader),
+        })
+    }
+
+    fn send(&mut self, value: Value) -> Result<()> {
+        let Some(stdin) = self.stdin.as_mut() else { return Ok(()) };
+        let result = serde_json::to_writer(&mut *stdin, &value)
+            .map_err(std::io::Error::from)
+            .and_then(|_| stdin.write_all(b"\n"))
+            // a whole record at a time, so a plugin reading a stream like tail -f sees rows as they come
+            .and_then(|_| stdin.flush());
+        match result {
+            // the plugin has stopped reading, e.g. like head
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                self.stdin = None;
+                Break.to_err()
+            },
+            result => Ok(result?),
+        }
+    }
+}
+
+impl base::Processor for Handler {
+    fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+        self.send(json!({"type": "header", "row": to_json(&header)}))
+    }
+
+    fn on_row(&mut self, _base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+        self.send(json!({"type": "row", "row": to_json(&row)}))
+    }
+
+    fn on_ofs(&mut self, base: &mut base::Base, ofs: Ofs) -> MaybeBreak {
+        let value = match &ofs {
+            Ofs::Plain(ofs) => json!(ofs.to_str_lossy()),
+            Ofs::Pretty => Value::Null,
+        };
+        // the plugin can send its own ofs later if it wants to
+        base.on_ofs(ofs)?;
+        Break::when(self.send(json!({"type": "ofs", "value": value})).is_err())
+    }
+
+    fn on_eof(mut self, base: &mut base::Base) -> Result<bool> {
+        let _ = self.send(json!({"type": "eof"}));
+        if let Some(mut stdin) = self.stdin.take() {
+            let _ = stdin.flush();
+        }
+
+        let result = self.reader.take().unwrap().join().unwrap();
+        let status = self.child.wait()?;
+        result?;
+        base.on_eof()?;
+        if !status.success() {
+            base.log(format!("{PREFIX}{} exited with {status}\n", self.name))?;
+        }
+        Ok(status.success())
+    }
+}
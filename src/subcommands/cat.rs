@@ -1,19 +1,42 @@
-use crate::io::{Reader};
-use anyhow::Result;
-use std::fs::File;
-use std::io::BufReader;
+use crate::io::{Reader, BoxedReader};
+use crate::utils::Break;
+use anyhow::{Result, Context};
 use crate::base::{self, Processor};
+use std::path::{Path, PathBuf};
+use std::collections::VecDeque;
+use std::cmp::Ordering;
+use std::io::{Read, BufRead};
+use std::sync::mpsc::{Receiver, sync_channel};
 use bstr::BString;
 use clap::{Parser};
 
+const PREFETCH_CHUNK_SIZE: usize = 1 << 16;
+const PREFETCH_CHUNKS: usize = 16;
+
 #[derive(Parser, Default, Clone)]
-#[command(about = "concatenate files by row")]
+#[command(about = "concatenate files by row", disable_version_flag = true)]
 pub struct Opts {
     #[arg(short = 'n', long, help = "number all output lines")]
     number: bool,
     #[arg(short = 's', long, help = "determine header after reading all input")]
     slurp: bool,
-    #[arg(help = "other files to concatenate to stdin")]
+    #[arg(long, value_name = "NAME", help = "add a column with the file each row came from (stdin is -)")]
+    filename_column: Option<String>,
+    #[arg(long, requires = "filename_column", help = "use only the basename in --filename-column")]
+    basename: bool,
+    #[arg(long, value_name = "NAME", help = "add a column with the input line each row started on")]
+    line_column: Option<String>,
+    #[arg(short = 'r', long, help = "read directories recursively")]
+    recursive: bool,
+    #[arg(short = 'V', long, help = "natural sort of version numbers when expanding directories and globs")]
+    version_sort: bool,
+    #[arg(long, value_name = "GLOB", help = "when expanding directories and globs, only read files whose name matches GLOB")]
+    include: Vec<String>,
+    #[arg(long, value_name = "GLOB", help = "when expanding directories and globs, skip files whose name matches GLOB")]
+    exclude: Vec<String>,
+    #[arg(short = 'j', long, value_name = "N", help = "read up to N files at once in the background")]
+    parallel: Option<usize>,
+    #[arg(help = "other files, directories or quoted globs to concatenate to stdin")]
     files: Vec<String>,
 }
 
@@ -22,49 +45,99 @@ pub struct Handler {
     row_count: usize,
     children: Option<Vec<Child>>,
     have_combined: bool,
+    have_input: bool,
+    filename: BString,
+    line_number: usize,
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
 }
 
 impl Handler {
     pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
         Ok(Self {
+            include: parse_globs(&opts.include)?,
+            exclude: parse_globs(&opts.exclude)?,
             row_count: 0,
             opts,
             children: None,
             have_combined: false,
+            have_input: false,
+            filename: b"-".into(),
+            line_number: 0,
         })
     }
 
-    fn open_file(base: &mut base::Base, file: &String) -> Result<Option<BufReader<File>>> {
-        match File::open(file) {
-            Ok(file) => Ok(Some(BufReader::new(file))),
-            Err(e) => {
-                base.log(format!("{e}: {file}\n"))?;
-                Ok(None)
-            }
+    fn label(path: &Path, basename: bool) -> BString {
+        match path.file_name() {
+            Some(name) if basename => name.as_encoded_bytes().into(),
+            _ => path.as_os_str().as_encoded_bytes().into(),
         }
     }
 
-    fn get_children(&mut self, base: &mut base::Base) -> Result<&mut Vec<Child>> {
-        let init = self.children.is_none();
-        let children = self.children.get_or_insert_with(Vec::new);
-        if init {
-            for file in &std::mem::take(&mut self.opts.files) {
-                if let Some(file) = Self::open_file(base, file)? {
-                    let reader = Reader::new(file);
-                    children.push(Child{
-                        inner: ChildProcessor,
-                        base: None,
-                        reader,
-                        prev_row: vec![],
-                        first_row: true,
-                        first_read: true,
-                        mapping: vec![],
-                        extra_row: None,
-                    });
+    fn expand_files(&self) -> Result<Vec<PathBuf>> {
+        let wanted = |path: &Path| {
+            let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            (self.include.is_empty() || self.include.iter().any(|g| g.matches(&name)))
+                && !self.exclude.iter().any(|g| g.matches(&name))
+        };
+
+        let mut files = vec![];
+        for file in &self.opts.files {
+            let path = Path::new(file);
+            let mut expanded = vec![];
+            if self.opts.recursive && path.is_dir() {
+                walk_dir(path, &mut expanded)?;
+            } else if !path.exists() && file.contains(['*', '?', '[']) {
+                for entry in glob::glob(file).with_context(|| format!("invalid glob: {file}"))? {
+                    let entry = entry?;
+                    if self.opts.recursive && entry.is_dir() {
+                        walk_dir(&entry, &mut expanded)?;
+                    } else if entry.is_file() {
+                        expanded.push(entry);
+                    }
+                }
+                if expanded.is_empty() {
+                    // nothing matched, so complain about it when it is opened
+                    files.push(path.to_owned());
+                    continue
                 }
+            } else {
+                // taken as is, any errors will come up when it is opened
+                files.push(path.to_owned());
+                continue
             }
+
+            expanded.retain(|path| wanted(path));
+            if self.opts.version_sort {
+                expanded.sort_by(|a, b| natural_cmp(a.as_os_str().as_encoded_bytes(), b.as_os_str().as_encoded_bytes()));
+            } else {
+                expanded.sort();
+            }
+            expanded.dedup();
+            files.append(&mut expanded);
+        }
+        Ok(files)
+    }
+
+    fn get_children(&mut self) -> Result<&mut Vec<Child>> {
+        if self.children.is_none() {
+            let files = self.expand_files()?;
+            let children = files.into_iter().map(|path| Child{
+                name: Self::label(&path, self.opts.basename),
+                path,
+                inner: ChildProcessor,
+                base: None,
+                reader: None,
+                opened: false,
+                prev_row: vec![],
+                first_row: true,
+                first_read: true,
+                mapping: vec![],
+                extra_row: None,
+            }).collect();
+            self.children = Some(children);
         }
-        Ok(children)
+        Ok(self.children.as_mut().unwrap())
     }
 
     fn make_combined_header(&mut self, base: &mut base::Base, row: Vec<BString>, is_header: bool) -> Result<Option<Vec<BString>>> {
@@ -77,7 +150,7 @@ impl Handler {
 
         let mut have_any_headers = is_header;
         // i got my header, get everyone elses
-        for child in self.get_children(base)? {
+        for child in self.get_children()? {
             if let Some((row, is_header)) = child.process_one_row(base, base::Callbacks::None)? {
                 if is_header {
                     have_any_headers = true;
@@ -107,55 +180,73 @@ impl Handler {
     }
 
     fn on_child_row(&mut self, base: &mut base::Base, child: &Child, row: Vec<BString>) -> Result<()> {
+        self.filename.clone_from(&child.name);
+        self.line_number = child.line_number();
         if self.opts.slurp {
             let mut template = vec![b"".into(); child.mapping.iter().copied().max().unwrap_or(0) + 1];
             for (i, col) in child.mapping.iter().zip(row) {
                 template[*i] = col;
             }
-            self.on_row(base, template)
+            self.on_source_row(base, template)
         } else {
-            self.on_row(base, row)
-        }
-    }
-
-}
-
-impl Processor for Handler {
-
-    fn on_header(&mut self, base: &mut base::Base, mut header: Vec<BString>) -> Result<()> {
-        if !self.have_combined && self.opts.slurp {
-            header = self.make_combined_header(base, header, true)?.unwrap();
-        }
-
-        if self.opts.number {
-            header.insert(0, b"n".into());
+            self.on_source_row(base, row)
         }
-        base.on_header(header)
     }
 
-    fn on_row(&mut self, base: &mut base::Base, mut row: Vec<BString>) -> Result<()> {
+    fn on_source_row(&mut self, base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+        self.have_input = true;
         if !self.have_combined && self.opts.slurp && let Some(header) = self.make_combined_header(base, row.clone(), false)? {
             self.on_header(base, header)?;
         }
+        let row = self.add_columns(row, false);
+        base.on_row(row)
+    }
 
+    fn add_columns(&mut self, row: Vec<BString>, is_header: bool) -> Vec<BString> {
+        let mut columns = vec![];
         if self.opts.number {
-            self.row_count += 1;
-            row.insert(0, format!("{}", self.row_count).into());
+            if is_header {
+                columns.push(b"n".into());
+            } else {
+                self.row_count += 1;
+                columns.push(format!("{}", self.row_count).into());
+            }
         }
-        base.on_row(row)
+        if let Some(name) = &self.opts.filename_column {
+            columns.push(if is_header { name.as_str().into() } else { self.filename.clone() });
+        }
+        if let Some(name) = &self.opts.line_column {
+            columns.push(if is_header { name.as_str().into() } else { format!("{}", self.line_number).into() });
+        }
+        columns.extend(row);
+        columns
     }
 
-    fn on_eof(mut self, base: &mut base::Base) -> Result<bool> {
-        while !self.have_combined && self.opts.slurp && !self.opts.files.is_empty() {
-            // we got no rows, process the next file
-            let file = self.opts.files.remove(0);
-            if let Some(file) = Self::open_file(base, &file)? {
-                ChildProcessor.process_file(file, base, base::Callbacks::ON_HEADER | base::Callbacks::ON_ROW)?;
+    fn write_children(&mut self, base: &mut base::Base) -> Result<()> {
+        self.get_children()?;
+        // we got nothing on stdin, the first file with any rows stands in for it
+        while !self.have_input && let Some(children) = &mut self.children && !children.is_empty() {
+            let mut child = children.remove(0);
+            while let Some((row, is_header)) = child.process_one_row(base, base::Callbacks::ON_OFS)? {
+                if is_header {
+                    self.on_header(base, row)?;
+                } else {
+                    self.filename.clone_from(&child.name);
+                    self.line_number = child.line_number();
+                    self.on_source_row(base, row)?;
+                }
             }
         }
 
-        self.get_children(base)?;
-        for mut child in self.children.take().unwrap() {
+        let mut children: VecDeque<_> = self.children.take().unwrap().into();
+        while let Some(mut child) = children.pop_front() {
+            if let Some(n) = self.opts.parallel {
+                // keep the next few files reading in the background
+                child.open(base, true)?;
+                for next in children.iter_mut().take(n.saturating_sub(1)) {
+                    next.open(base, true)?;
+                }
+            }
             if let Some(row) = child.extra_row.take() {
                 self.on_child_row(base, &child, row)?;
             }
@@ -165,16 +256,161 @@ impl Processor for Handler {
                 }
             }
         }
+        Ok(())
+    }
+
+}
+
+impl Processor for Handler {
+
+    fn on_alignment(&mut self, base: &mut base::Base, alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        // the added columns go in front
+        let added = usize::from(self.opts.number) + usize::from(self.opts.filename_column.is_some()) + usize::from(self.opts.line_column.is_some());
+        let alignment = std::iter::repeat_n(None, added).chain(alignment).collect();
+        Ok(base.on_alignment(alignment)?)
+    }
+
+    fn on_header(&mut self, base: &mut base::Base, mut header: Vec<BString>) -> Result<()> {
+        self.have_input = true;
+        if !self.have_combined && self.opts.slurp {
+            header = self.make_combined_header(base, header, true)?.unwrap();
+        }
+
+        let header = self.add_columns(header, true);
+        base.on_header(header)
+    }
 
+    fn on_row(&mut self, base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+        self.filename = b"-".into();
+        self.line_number = base.line_number;
+        self.on_source_row(base, row)
+    }
+
+    fn on_eof(mut self, base: &mut base::Base) -> Result<bool> {
+        // silence the break
+        Break::is_break(self.write_children(base))?;
         base.on_eof()
     }
 
 }
 
+fn parse_globs(globs: &[String]) -> Result<Vec<glob::Pattern>> {
+    globs.iter().map(|g| glob::Pattern::new(g).with_context(|| format!("invalid glob: {g}"))).collect()
+}
+
+fn walk_dir(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        // don't follow symlinked directories, they may loop
+        if file_type.is_dir() {
+            walk_dir(&path, files)?;
+        } else if file_type.is_file() || (file_type.is_symlink() && path.is_file()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// compare runs of digits by their numeric value
+fn natural_cmp(mut a: &[u8], mut b: &[u8]) -> Ordering {
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x_len = a.iter().take_while(|c| c.is_ascii_digit()).count();
+                let y_len = b.iter().take_while(|c| c.is_ascii_digit()).count();
+                let x_digits = &a[a[..x_len].iter().take_while(|&&c| c == b'0').count() .. x_len];
+                let y_digits = &b[b[..y_len].iter().take_while(|&&c| c == b'0').count() .. y_len];
+                let ordering = x_digits.len().cmp(&y_digits.len())
+                    .then_with(|| x_digits.cmp(y_digits))
+                    .then_with(|| x_len.cmp(&y_len));
+                if ordering.is_ne() {
+                    return ordering
+                }
+                a = &a[x_len..];
+                b = &b[y_len..];
+            },
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y)
+                }
+                a = &a[1..];
+                b = &b[1..];
+            },
+        }
+    }
+}
+
+// chunks of a file read on another thread
+struct Prefetched {
+    receiver: Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Prefetched {
+    fn spawn(mut file: BoxedReader, base: &base::Base) -> Self {
+        let (sender, receiver) = sync_channel(PREFETCH_CHUNKS);
+        base.scope.spawn(move || {
+            loop {
+                let mut chunk = vec![0; PREFETCH_CHUNK_SIZE];
+                let result = match file.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(count) => {
+                        chunk.truncate(count);
+                        Ok(chunk)
+                    },
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(err) => Err(err),
+                };
+                let failed = result.is_err();
+                // the receiver is gone if we stopped early
+                if sender.send(result).is_err() || failed {
+                    break
+                }
+            }
+        });
+        Self{ receiver, chunk: vec![], pos: 0 }
+    }
+}
+
+impl Read for Prefetched {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.fill_buf()?.read(buf)?;
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for Prefetched {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.pos >= self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => self.chunk = chunk?,
+                // the thread has finished
+                Err(_) => self.chunk.clear(),
+            }
+            self.pos = 0;
+        }
+        Ok(&self.chunk[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos += amount;
+    }
+}
+
 struct Child {
+    name: BString,
+    path: PathBuf,
     inner: ChildProcessor,
     base: Option<base::ScopelessBase>,
-    reader: Reader<BufReader<File>>,
+    reader: Option<Reader<BoxedReader>>,
+    opened: bool,
     prev_row: Vec<BString>,
     first_row: bool,
     first_read: bool,
@@ -183,17 +419,44 @@ struct Child {
 }
 
 impl Child {
+    fn line_number(&self) -> usize {
+        self.base.as_ref().map_or(0, |base| base.line_number)
+    }
+
+    // files are opened as late as possible so we don't run out of fds
+    fn open(&mut self, base: &mut base::Base, prefetch: bool) -> Result<()> {
+        if !self.opened {
+            self.opened = true;
+            if self.path.is_dir() {
+                base.log(format!("Is a directory (use -r to read it): {}\n", self.path.display()))?;
+                return Ok(())
+            }
+            match crate::io::open_file(&self.path, base.opts.encoding) {
+                Ok(file) if prefetch => self.reader = Some(Reader::new(Box::new(Prefetched::spawn(file, base)))),
+                Ok(file) => self.reader = Some(Reader::new(file)),
+                Err(e) => base.log(format!("{e}: {}\n", self.path.display()))?,
+            }
+        }
+        Ok(())
+    }
+
     fn process_one_row(&mut self, base: &mut base::Base, do_callbacks: base::Callbacks) -> Result<Option<(Vec<BString>, bool)>> {
+        self.open(base, false)?;
+        let Some(reader) = &mut self.reader else {
+            return Ok(None)
+        };
         let prev_row = std::mem::take(&mut self.prev_row);
 
         // we need our own base as we might modify the opts
         let mut base = if let Some(inner) = self.base.take() {
             base::Base{ inner, scope: base.scope }
         } else {
-            base.clone()
+            let mut base = base.clone();
+            base.filename = Some(self.name.clone());
+            base
         };
 
-        let result = self.inner.process_one_row(&mut self.reader, &mut base, do_callbacks, prev_row, self.first_row, self.first_read);
+        let result = self.inner.process_one_row(reader, &mut base, do_callbacks, prev_row, self.first_row, self.first_read);
         self.base = Some(base.inner);
         self.first_row = false;
         self.first_read = false;
@@ -202,6 +465,8 @@ impl Child {
             self.prev_row = prev_row;
             Ok(Some((row, is_header)))
         } else {
+            // done with this file
+            self.reader = None;
             Ok(None)
         }
     }
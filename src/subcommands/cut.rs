@@ -5,11 +5,11 @@ use crate::column_slicer::ColumnSlicer;
 use clap::{Parser};
 
 #[derive(Parser)]
-#[command(about = "select columns")]
+#[command(about = "select columns", after_help = crate::column_slicer::FIELDS_HELP)]
 pub struct Opts {
-    #[arg(required_unless_present = "old_style_fields", help = "select only these fields")]
+    #[arg(required_unless_present = "old_style_fields", allow_negative_numbers = true, help = "select only these fields")]
     fields: Vec<String>,
-    #[arg(short = 'f', long = "fields", value_name = "fields", help = "select only these fields")]
+    #[arg(short = 'f', long = "fields", value_name = "fields", allow_hyphen_values = true, help = "select only these fields")]
     old_style_fields: Vec<String>,
     #[arg(short = 'x', long, help = "exclude, rather than include, field names")]
     complement: bool,
@@ -20,6 +20,8 @@ pub struct Opts {
 pub struct Handler {
     complement: bool,
     column_slicer: ColumnSlicer,
+    // comes before the header, so it can only be sliced once the header is known
+    alignment: Option<Vec<Option<base::Alignment>>>,
 }
 
 impl Handler {
@@ -28,14 +30,23 @@ impl Handler {
 
         Ok(Self {
             complement: opts.complement,
-            column_slicer: ColumnSlicer::new(&opts.fields, opts.regex),
+            column_slicer: ColumnSlicer::new(&opts.fields, opts.regex)?,
+            alignment: None,
         })
     }
 }
 
 impl base::Processor for Handler {
+    fn on_alignment(&mut self, _base: &mut base::Base, alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        self.alignment = Some(alignment);
+        Ok(())
+    }
+
     fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
-        self.column_slicer.make_header_map(&header);
+        self.column_slicer.make_header_map(&header, base.opts.lenient_fields)?;
+        if let Some(alignment) = self.alignment.take() {
+            base.on_alignment(self.column_slicer.slice_with(&alignment, self.complement, Some(|_| None)))?;
+        }
         let header = self.column_slicer.slice(&header, self.complement, true);
         base.on_header(header)
     }
@@ -35,6 +35,11 @@ impl Handler {
 }
 
 impl base::Processor for Handler {
+    // every column becomes a row, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut base::Base, _alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
     fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
         self.header = Some(header);
         base.on_header(vec![b"row".into(), b"column".into(), b"key".into(), b"value".into()])
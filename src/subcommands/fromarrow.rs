@@ -0,0 +1,79 @@
+use crate::utils::Break;
+use anyhow::{Result, Context};
+use crate::base::{self, Processor, Callbacks};
+use super::fromparquet::{project_schema, schema_header, batch_to_rows};
+use std::io::{BufRead, BufReader, Read};
+use std::process::ExitCode;
+use clap::Parser;
+use arrow::ipc::reader::StreamReader;
+
+#[derive(Parser)]
+#[command(about = "convert from arrow ipc stream")]
+pub struct Opts {
+    #[arg(help = "arrow file to read, otherwise read from stdin")]
+    file: Option<String>,
+    #[arg(short = 'c', long, value_delimiter = ',', help = "read only these columns")]
+    columns: Vec<String>,
+    #[arg(short = 'r', long, help = "treat columns as regexes")]
+    regex: bool,
+}
+
+pub struct Handler {
+    opts: Opts,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
+        Ok(Self {
+            opts,
+        })
+    }
+
+    fn process_arrow<R: Read>(&mut self, file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<()> {
+        let reader = StreamReader::try_new(file, None)?;
+        let schema = reader.schema();
+        let indices = project_schema(&schema, &self.opts.columns, self.opts.regex, base.opts.lenient_fields)?;
+        let schema = match &indices {
+            Some(indices) => schema.project(indices)?,
+            None => schema.as_ref().clone(),
+        };
+
+        if do_callbacks.contains(Callbacks::ON_HEADER) {
+            self.on_header(base, schema_header(&schema))?;
+        }
+        for batch in reader {
+            let mut batch = batch?;
+            if let Some(indices) = &indices {
+                batch = batch.project(indices)?;
+            }
+            if do_callbacks.contains(Callbacks::ON_ROW) {
+                for row in batch_to_rows(&batch)? {
+                    self.on_row(base, row)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl base::Processor for Handler {
+    fn process_file<R: BufRead>(mut self, file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<ExitCode> {
+        let ofs = self.determine_delimiters(b"".into(), &base.opts).1;
+        if base.on_ofs(ofs).is_err() {
+            return Ok(ExitCode::SUCCESS)
+        }
+
+        let result = if let Some(path) = &self.opts.file {
+            let file = std::fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+            self.process_arrow(BufReader::new(file), base, do_callbacks)
+        } else {
+            self.process_arrow(file, base, do_callbacks)
+        };
+        // silence the break
+        Break::is_break(result)?;
+        if do_callbacks.contains(Callbacks::ON_EOF) {
+            return self.on_eof_detailed(base)
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
@@ -1,11 +1,20 @@
 use anyhow::Result;
-use crate::base;
+use crate::base::{self, Processor};
 use std::io::BufRead;
 use std::process::ExitCode;
 use std::collections::HashMap;
-use bstr::{BString, ByteVec};
+use bstr::{BString, ByteSlice, ByteVec};
 use clap::{Parser};
-use quick_xml::{events::Event, reader::Reader, errors::SyntaxError};
+use quick_xml::{events::{Event, BytesStart}, reader::Reader, errors::SyntaxError};
+
+const VOID_ELEMENTS: &[&[u8]] = &[b"area", b"base", b"br", b"col", b"embed", b"hr", b"img", b"input", b"link", b"meta", b"param", b"source", b"track", b"wbr"];
+
+#[derive(Copy, Clone, PartialEq, Debug, Default, clap::ValueEnum)]
+enum Nested {
+    #[default]
+    Flatten,
+    Skip,
+}
 
 #[derive(Parser, Default)]
 #[command(about = "convert from html table")]
@@ -14,16 +23,39 @@ pub struct Opts {
     strict: bool,
     #[arg(long, help = "output the innerHTML of table cells, not the innerText")]
     inner_html: bool,
+    #[arg(short = 't', long, help = "only read the Nth table (1-based) out of those matching the other options")]
+    table: Option<usize>,
+    #[arg(long, help = "only read the table with this id")]
+    id: Option<String>,
+    #[arg(long, help = "only read tables with this class")]
+    class: Option<String>,
+    #[arg(short = 's', long, help = "only read tables matching this css-like selector e.g. 'div#main table.data'")]
+    selector: Option<String>,
+    #[arg(short = 'a', long, help = "read all tables, separating each one")]
+    all: bool,
+    #[arg(long, value_enum, default_value_t = Nested::default(), help = "what to do with tables inside table cells")]
+    nested: Nested,
+    #[arg(long, help = "add the table caption as the first column")]
+    caption: bool,
 }
 
 pub struct Handler {
     opts: Opts,
+    selector: Option<Selector>,
 }
 
 impl Handler {
     pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
+        let mut selector = opts.selector.as_deref().map(Selector::new).transpose()?;
+        if opts.id.is_some() || opts.class.is_some() {
+            let selector = selector.get_or_insert_with(|| Selector(vec![Compound::default()]));
+            let last = selector.0.last_mut().unwrap();
+            last.id = last.id.take().or_else(|| opts.id.as_ref().map(|x| x.as_bytes().into()));
+            last.classes.extend(opts.class.iter().map(|x| x.as_bytes().into()));
+        }
         Ok(Self {
             opts,
+            selector,
         })
     }
 }
@@ -49,20 +81,254 @@ fn add_rowspan(rowspans: &mut Rowspans, column: usize, span: usize, value: BStri
     rowspans.insert(column, (span, value));
 }
 
-impl base::Processor for Handler {
+struct Element {
+    name: BString,
+    id: Option<BString>,
+    classes: Vec<BString>,
+}
 
-    fn process_file<R: BufRead>(mut self, file: R, base: &mut base::Base, do_callbacks: base::Callbacks) -> Result<ExitCode> {
+impl Element {
+    fn new(tag: &BytesStart) -> Result<Self> {
+        let mut id = None;
+        let mut classes = vec![];
+        for attr in tag.html_attributes().with_checks(false) {
+            let attr = attr?;
+            match attr.key.0 {
+                b"id" => id = Some(attr.value.as_ref().into()),
+                b"class" => classes.extend(attr.value.fields().map(|c| c.into())),
+                _ => (),
+            }
+        }
+        Ok(Self {
+            name: tag.local_name().as_ref().to_ascii_lowercase().into(),
+            id,
+            classes,
+        })
+    }
+}
 
-        let ofs = self.determine_delimiters(b"".into(), &base.opts).1;
-        if base.on_ofs(ofs).is_err() {
-            return Ok(ExitCode::SUCCESS)
+// a tag name with optional #id and .class parts e.g. table#prices.data
+#[derive(Default, Debug)]
+struct Compound {
+    name: Option<BString>,
+    id: Option<BString>,
+    classes: Vec<BString>,
+}
+
+impl Compound {
+    fn new(part: &str) -> Result<Self> {
+        let mut compound = Self::default();
+        let mut rest = part;
+        while !rest.is_empty() {
+            let end = rest[1..].find(['#', '.']).map_or(rest.len(), |i| i + 1);
+            let (token, tail) = rest.split_at(end);
+            rest = tail;
+            if token == "#" || token == "." {
+                anyhow::bail!("invalid selector: {part}");
+            }
+            if let Some(id) = token.strip_prefix('#') {
+                compound.id = Some(id.into());
+            } else if let Some(class) = token.strip_prefix('.') {
+                compound.classes.push(class.into());
+            } else if compound.name.is_none() && compound.id.is_none() && compound.classes.is_empty() {
+                if token != "*" {
+                    compound.name = Some(token.to_ascii_lowercase().into());
+                }
+            } else {
+                anyhow::bail!("invalid selector: {part}");
+            }
+        }
+        Ok(compound)
+    }
+
+    fn matches(&self, element: &Element) -> bool {
+        self.name.as_ref().is_none_or(|n| *n == element.name)
+            && self.id.as_ref().is_none_or(|id| element.id.as_ref() == Some(id))
+            && self.classes.iter().all(|c| element.classes.contains(c))
+    }
+}
+
+// descendant selectors only e.g. div#main table.data
+#[derive(Debug)]
+struct Selector(Vec<Compound>);
+
+impl Selector {
+    fn new(selector: &str) -> Result<Self> {
+        let parts = selector.split_whitespace().map(Compound::new).collect::<Result<Vec<_>>>()?;
+        if parts.is_empty() {
+            anyhow::bail!("empty selector");
+        }
+        Ok(Self(parts))
+    }
+
+    fn matches(&self, elements: &[Element]) -> bool {
+        let Some((last, ancestors)) = self.0.split_last() else { return true };
+        let Some((element, mut elements)) = elements.split_last() else { return false };
+        if !last.matches(element) {
+            return false
+        }
+        // greedily match the ancestors from the innermost out
+        for compound in ancestors.iter().rev() {
+            match elements.iter().rposition(|e| compound.matches(e)) {
+                Some(i) => elements = &elements[..i],
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+// the table currently being read
+#[derive(Default)]
+struct Table {
+    // index into the open elements
+    depth: usize,
+    state: Vec<BString>,
+    current_row: Vec<BString>,
+    all_th: bool,
+    rows: usize,
+    got_header: bool,
+    rowspans: Rowspans,
+    caption: Option<BString>,
+    // open tables inside this one
+    nested: Vec<usize>,
+}
+
+impl Table {
+    fn in_cell(&self) -> bool {
+        matches!(self.state.last().map(|x| x.as_slice()), Some(b"td" | b"th"))
+    }
+
+    fn in_caption(&self) -> bool {
+        self.nested.is_empty() && self.state.last().is_some_and(|x| x == "caption")
+    }
+}
+
+impl Handler {
+    fn start_tag(&self, base: &mut base::Base, table: &mut Table, tag: &BytesStart) -> Result<()> {
+        if !table.nested.is_empty() {
+            match self.opts.nested {
+                Nested::Skip => return Ok(()),
+                Nested::Flatten => if !self.opts.inner_html {
+                    let name = tag.local_name();
+                    // keep the cells of the nested table apart
+                    if matches!(name.as_ref(), b"td" | b"th" | b"tr") && let Some(last) = table.current_row.last_mut() && !last.is_empty() {
+                        last.push(b' ');
+                    }
+                    return Ok(())
+                },
+            }
+        }
+
+        if table.in_cell() {
+            if self.opts.inner_html && let Some(last) = table.current_row.last_mut() {
+                last.push(b'<');
+                last.push_str(&**tag);
+                last.push(b'>');
+            }
+            return Ok(())
         }
 
-        let mut state: Vec<BString> = vec![];
-        let mut current_row: Vec<BString> = vec![];
-        let mut got_header = false;
+        let name = tag.local_name();
+        let name = name.as_ref();
+        match (table.state.last().map(|x| x.as_slice()), name) {
+            (Some(b"table"), b"caption" | b"thead" | b"tbody")
+            | (Some(b"table"), b"tr")
+            | (Some(b"thead" | b"tbody"), b"tr")
+            | (Some(b"tr"), b"th" | b"td")
+            => {
+                table.state.push(name.into());
+                match name {
+                    // good
+                    b"tr" => {
+                        // new row
+                        table.current_row.clear();
+                        table.all_th = true;
+                        decrement_rowspans(&mut table.rowspans);
+                        apply_rowspans(&mut table.current_row, &table.rowspans);
+                    },
+                    b"td" | b"th" => {
+                        table.all_th &= name == b"th";
+                        apply_rowspans(&mut table.current_row, &table.rowspans);
+                        // new column
+                        table.current_row.push(b"".into());
+                        for attr in tag.html_attributes().with_checks(false) {
+                            let attr = attr?;
+                            if attr.key.0 == b"rowspan" {
+                                if let Some(span) = crate::utils::try_parse(&attr.value) && span > 0 {
+                                    add_rowspan(&mut table.rowspans, table.current_row.len(), span, b"".into());
+                                } else {
+                                    base.log(format!("invalid rowspan {:?}\n", attr.value))?;
+                                }
+                            }
+                        }
+                    },
+                    b"caption" => {
+                        table.caption = Some(b"".into());
+                    },
+                    _ => (),
+                }
+            },
+            _ => {
+                // bad
+                if self.opts.strict {
+                    anyhow::bail!("invalid tags {:?}", table.state)
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn end_tag(&mut self, base: &mut base::Base, table: &mut Table, name: &[u8], raw: &[u8], do_callbacks: base::Callbacks) -> Result<()> {
+        match table.state.last().map(|x| x.as_slice()) {
+            Some(x @ (b"th" | b"td")) if x != name && self.opts.inner_html => {
+                if let Some(last) = table.current_row.last_mut() {
+                    last.push_str(b"</");
+                    last.push_str(raw);
+                    last.push(b'>');
+                }
+            },
+            _ => (),
+        }
+        let had_tr = table.state.iter().any(|x| x == b"tr");
+        let had_thead = table.state.iter().any(|x| x == b"thead");
+
+        if let Some(pos) = table.state.iter().rposition(|t| t == name) {
+            // don't pop the table itself, that happens when the element closes
+            table.state.drain(pos.max(1)..);
+        }
+
+        if had_tr && !table.state.iter().any(|x| x == b"tr") {
+            // a first row of only th is a header too
+            let is_header = had_thead || (table.rows == 0 && table.all_th && !table.current_row.is_empty());
+            let mut row = std::mem::take(&mut table.current_row);
+            if self.opts.caption {
+                let caption = if is_header { b"caption".into() } else { table.caption.clone().unwrap_or_default() };
+                row.insert(0, caption);
+            }
+            table.rows += 1;
+
+            if is_header && table.got_header {
+                base.log("got duplicate html table header\n")?;
+            } else if is_header && do_callbacks.contains(base::Callbacks::ON_HEADER) {
+                self.on_header(base, row)?;
+            } else if !is_header && do_callbacks.contains(base::Callbacks::ON_ROW) {
+                self.on_row(base, row)?;
+            }
+            table.got_header = is_header;
+        }
+        Ok(())
+    }
+
+    fn process_html<R: BufRead>(&mut self, file: R, base: &mut base::Base, do_callbacks: base::Callbacks) -> Result<()> {
         let mut buffer = vec![];
-        let mut rowspans = Rowspans::new();
+        let mut elements: Vec<Element> = vec![];
+        let mut table: Option<Table> = None;
+        // number of tables matching the selector so far
+        let mut matched = 0;
+        let mut tables_read = 0;
+        // without any options, all tables are read as one
+        let select_one = self.opts.table.is_some() || (self.selector.is_some() && !self.opts.all);
 
         let mut reader = Reader::from_reader(file);
         let config = reader.config_mut();
@@ -75,101 +341,82 @@ impl base::Processor for Handler {
             buffer.clear();
             match reader.read_event_into(&mut buffer) {
                 Ok(Event::Start(tag)) => {
-                    if matches!(state.last().map(|x| x.as_slice()), Some(b"td" | b"th")) {
-                        if self.opts.inner_html && let Some(last) = current_row.last_mut() {
-                            last.push(b'<');
-                            last.push_str(&*tag);
-                            last.push(b'>');
-                        }
-                        continue
+                    let element = Element::new(&tag)?;
+                    let is_table = element.name == "table";
+                    let is_void = VOID_ELEMENTS.contains(&element.name.as_slice());
+                    if !is_void {
+                        elements.push(element);
                     }
 
-                    let name = tag.local_name();
-                    let name = name.as_ref();
-                    match (state.last().map(|x| x.as_slice()), name) {
-                        (None, b"table" | b"thead" | b"tbody")
-                        | (Some(b"table"), b"thead" | b"tbody" | b"tr")
-                        | (Some(b"thead" | b"tbody"), b"tr")
-                        | (Some(b"tr"), b"th" | b"td")
-                        => {
-                            state.push(name.into());
-                            match name {
-                                // good
-                                b"tr" => {
-                                    // new row
-                                    current_row.clear();
-                                    decrement_rowspans(&mut rowspans);
-                                    apply_rowspans(&mut current_row, &rowspans);
-                                },
-                                b"td" | b"th" => {
-                                    apply_rowspans(&mut current_row, &rowspans);
-                                    // new column
-                                    current_row.push(b"".into());
-                                    for attr in tag.html_attributes().with_checks(false) {
-                                        let attr = attr?;
-                                        if attr.key.0 == b"rowspan" {
-                                            if let Some(span) = crate::utils::try_parse(&attr.value) && span > 0 {
-                                                add_rowspan(&mut rowspans, current_row.len(), span, b"".into());
-                                            } else {
-                                                base.log(format!("invalid rowspan {:?}\n", attr.value))?;
-                                            }
-                                        }
-                                    }
-                                },
-                                _ => {
-                                    // new table
-                                    rowspans.clear();
-                                }
-                            }
-                        },
-                        _ => {
-                            // bad
-                            if self.opts.strict {
-                                anyhow::bail!("invalid tags {:?}", state)
+                    if let Some(table) = &mut table {
+                        let starts_nested = is_table && (table.in_cell() || !table.nested.is_empty());
+                        // skipped nested tables don't even get their own tag
+                        if starts_nested && self.opts.nested == Nested::Skip {
+                            table.nested.push(elements.len() - 1);
+                        }
+                        self.start_tag(base, table, &tag)?;
+                        if starts_nested && self.opts.nested == Nested::Flatten {
+                            table.nested.push(elements.len() - 1);
+                        }
+
+                    } else if is_table && self.selector.as_ref().is_none_or(|s| s.matches(&elements)) {
+                        matched += 1;
+                        if self.opts.table.is_none_or(|n| n == matched) {
+                            if tables_read > 0 && self.opts.all && base.on_separator().is_err() {
+                                break
                             }
-                        },
+                            tables_read += 1;
+                            table = Some(Table{
+                                depth: elements.len() - 1,
+                                state: vec![b"table".into()],
+                                ..Table::default()
+                            });
+                        }
                     }
-
                 },
                 Ok(Event::End(tag)) => {
-                    let name = tag.local_name();
-                    let name = name.as_ref();
-                    match state.last().map(|x| x.as_slice()) {
-                        Some(x @ (b"th" | b"td")) if x != name && self.opts.inner_html => {
-                            if let Some(last) = current_row.last_mut() {
+                    let name = tag.local_name().as_ref().to_ascii_lowercase();
+                    let Some(pos) = elements.iter().rposition(|e| e.name == name) else { continue };
+                    elements.truncate(pos);
+
+                    if let Some(t) = &mut table {
+                        if elements.len() <= t.depth {
+                            // finished the table
+                            table = None;
+                            if select_one {
+                                break
+                            }
+                            continue
+                        }
+                        let nested = !t.nested.is_empty();
+                        t.nested.retain(|&d| d < elements.len());
+                        if nested {
+                            if self.opts.inner_html && self.opts.nested == Nested::Flatten && let Some(last) = t.current_row.last_mut() {
                                 last.push_str(b"</");
                                 last.push_str(&*tag);
                                 last.push(b'>');
                             }
-                        },
-                        _ => (),
-                    }
-                    let had_tr = state.iter().any(|x| x == b"tr");
-                    let had_thead = state.iter().any(|x| x == b"thead");
-
-                    if let Some(pos) = state.iter().rposition(|t| t == name) {
-                        state.drain(pos..);
-                    }
-
-                    if had_tr && !state.iter().any(|x| x == b"tr") {
-                        if had_thead && got_header {
-                            base.log("got duplicate html table header\n")?;
-                        } else if had_thead && do_callbacks.contains(base::Callbacks::ON_HEADER) {
-                            self.on_header(base, current_row.clone())?;
-                        } else if !had_thead && do_callbacks.contains(base::Callbacks::ON_HEADER) {
-                            self.on_row(base, current_row.clone())?;
+                            continue
                         }
-                        got_header = had_thead;
+                        self.end_tag(base, t, &name, &tag, do_callbacks)?;
                     }
                 },
                 Ok(Event::Text(text)) => {
-                    let row_len = current_row.len();
-                    if matches!(state.last().map(|x| x.as_slice()), Some(b"td" | b"th")) && let Some(last) = current_row.last_mut() {
+                    if let Some(table) = &mut table {
                         let text = text.into_inner();
-                        let len = text.len();
-                        last.push_str(text);
-                        if let Some((_, value)) = rowspans.get_mut(&row_len) {
-                            value.push_str(&last[last.len() - len ..]);
+                        if table.in_caption() && let Some(caption) = &mut table.caption {
+                            caption.push_str(text.trim());
+                        }
+                        if !table.nested.is_empty() && self.opts.nested == Nested::Skip {
+                            continue
+                        }
+                        let row_len = table.current_row.len();
+                        if table.in_cell() && let Some(last) = table.current_row.last_mut() {
+                            let len = text.len();
+                            last.push_str(text);
+                            if table.nested.is_empty() && let Some((_, value)) = table.rowspans.get_mut(&row_len) {
+                                value.push_str(&last[last.len() - len ..]);
+                            }
                         }
                     }
                 },
@@ -180,6 +427,27 @@ impl base::Processor for Handler {
                 Err(e) => Err(e)?,
             }
         }
+
+        if let Some(n) = self.opts.table && matched < n {
+            base.log(format!("only found {matched} matching tables\n"))?;
+        } else if matched == 0 && self.selector.is_some() {
+            base.log("no tables match the selector\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl base::Processor for Handler {
+
+    fn process_file<R: BufRead>(mut self, file: R, base: &mut base::Base, do_callbacks: base::Callbacks) -> Result<ExitCode> {
+
+        let ofs = self.determine_delimiters(b"".into(), &base.opts).1;
+        if base.on_ofs(ofs).is_err() {
+            return Ok(ExitCode::SUCCESS)
+        }
+
+        // silence the break
+        crate::utils::Break::is_break(self.process_html(file, base, do_callbacks))?;
         if do_callbacks.contains(base::Callbacks::ON_EOF) {
             return self.on_eof_detailed(base)
         }
@@ -1,71 +1,166 @@
+use crate::utils::Break;
 use anyhow::Result;
-use crate::base;
+use crate::base::{self, Processor, Callbacks, Alignment};
+use crate::io::Reader;
+use std::process::ExitCode;
+use std::io::Read;
 use once_cell::sync::Lazy;
 use regex::bytes::Regex;
-use bstr::{BString, BStr, ByteSlice};
+use bstr::{BString, ByteSlice};
 use clap::Parser;
 
-static SEPARATOR: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s*:?-+:?\s*").unwrap());
+static SEPARATOR: Lazy<Regex> = Lazy::new(|| Regex::new(r"^:?-+:?$").unwrap());
 static ESCAPE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\\(.)").unwrap());
 static TABLE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\\.|[^|])*").unwrap());
+static FENCE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^ {0,3}(`{3,}|~{3,})").unwrap());
 
 #[derive(Parser)]
 #[command(about = "convert from markdown table")]
 pub struct Opts {
+    #[arg(short = 't', long, help = "only read the Nth table (1-based)")]
+    table: Option<usize>,
 }
 
 pub struct Handler {
-    just_got_header: bool,
+    opts: Opts,
 }
 
 impl Handler {
-    pub fn new(_opts: Opts, base: &mut base::Base) -> Result<Self> {
-        base.opts.no_header = false;
-        base.opts.header = Some(true);
+    pub fn new(opts: Opts, base: &mut base::Base) -> Result<Self> {
         base.opts.irs = Some("\n".into());
         Ok(Self {
-            just_got_header: false,
+            opts,
         })
     }
 }
 
-impl base::Processor for Handler {
-    fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
-        self.just_got_header = true;
-        base.on_header(header)
+// split a table row into cells, the outer pipes are optional
+fn parse_row(line: &[u8]) -> Option<Vec<BString>> {
+    // anything indented further is a code block
+    if line.iter().take_while(|&&c| c == b' ').count() >= 4 {
+        return None
+    }
+    let line = line.trim();
+    let mut cells: Vec<_> = TABLE_REGEX.find_iter(line).map(|m| m.as_bytes()).collect();
+    if cells.len() < 2 {
+        // no pipes at all
+        return None
+    }
+    if cells.first().is_some_and(|c| c.is_empty()) {
+        cells.remove(0);
     }
+    if cells.len() > 1 && cells.last().is_some_and(|c| c.is_empty()) {
+        cells.pop();
+    }
+    Some(cells.into_iter().map(|col| ESCAPE.replace_all(col.trim(), b"$1").into_owned().into()).collect())
+}
 
-    fn on_row(&mut self, base: &mut base::Base, row: Vec<BString>) -> Result<()> {
-        if self.just_got_header {
-            self.just_got_header = false;
-            if row.iter().all(|r| SEPARATOR.is_match(r)) {
-                return Ok(())
-            }
-        }
+fn parse_alignment(row: &[BString]) -> Option<Vec<Option<Alignment>>> {
+    row.iter().map(|col| {
+        SEPARATOR.is_match(col).then(|| match (col.starts_with(b":"), col.len() > 1 && col.ends_with(b":")) {
+            (true, true) => Some(Alignment::Center),
+            (true, false) => Some(Alignment::Left),
+            (false, true) => Some(Alignment::Right),
+            (false, false) => None,
+        })
+    }).collect()
+}
 
-        base.on_row(row)
-    }
+struct Table {
+    width: usize,
+    selected: bool,
+}
 
-    fn parse_line(&self, base: &mut base::Base, line: &BStr, mut row: Vec<BString>, _quote: u8) -> Result<(Vec<BString>, bool)> {
-        row.clear();
+impl Handler {
+    fn process_lines<R: Read>(&mut self, file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<()> {
+        let mut reader = Reader::new(file);
+        // the opening fence of the code block we are in
+        let mut fence: Option<BString> = None;
+        let mut table: Option<Table> = None;
+        // the previous line, if it could be a table header
+        let mut header: Option<Vec<BString>> = None;
+        let mut count = 0;
+        let mut tables_read = 0;
 
-        let mut matches = TABLE_REGEX.find_iter(line).map(|m| m.as_bytes());
-        // first column should be empty
-        if matches.next().is_none_or(|col| col.trim().len() != col.len()) {
-            base.log(format!("invalid markdown table row: {line}\n"))?;
-            return Ok((row, true))
-        }
+        'outer: loop {
+            let mut lines = reader.line_reader();
+            while let Some((line, _)) = lines.get_line(base.irs.as_ref()) {
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
 
-        let columns = matches.map(|col| ESCAPE.replace_all(col.trim(), b"$1").into_owned().into());
-        row.extend(columns);
+                if let Some(f) = &fence {
+                    // a closing fence must be at least as long as the opening one
+                    let trimmed = line.trim();
+                    if trimmed.starts_with(f) && trimmed.iter().all(|&c| c == f[0]) {
+                        fence = None;
+                    }
+                    continue
+                }
+                if let Some(captures) = FENCE.captures(line) {
+                    fence = Some(captures[1].into());
+                    table = None;
+                    header = None;
+                    continue
+                }
 
-        // last column should be empty
-        if row.pop().is_none_or(|col| col.trim().len() != col.len()) || row.is_empty() {
-            base.log(format!("invalid markdown table row: {line}\n"))?;
-            return Ok((row, true))
-        }
+                let row = parse_row(line);
+                if let Some(t) = &table {
+                    if let Some(mut row) = row {
+                        // rows are padded or truncated to the header
+                        row.resize(t.width, b"".into());
+                        if t.selected && do_callbacks.contains(Callbacks::ON_ROW) {
+                            self.on_row(base, row)?;
+                        }
+                        continue
+                    }
+                    // table ends at the first line that is not a row
+                    table = None;
+                    if self.opts.table.is_some_and(|n| count >= n) {
+                        break 'outer
+                    }
+                }
+
+                if let Some(h) = header.take() && let Some(alignment) = row.as_deref().and_then(parse_alignment) && alignment.len() == h.len() {
+                    count += 1;
+                    let width = h.len();
+                    let selected = self.opts.table.is_none_or(|n| n == count);
+                    if selected {
+                        if tables_read > 0 && base.on_separator().is_err() {
+                            break 'outer
+                        }
+                        tables_read += 1;
+                        if do_callbacks.contains(Callbacks::ON_HEADER) {
+                            // before the header so it applies even if the header is written straight away
+                            self.on_alignment(base, alignment)?;
+                            self.on_header(base, h)?;
+                        }
+                    }
+                    table = Some(Table{ width, selected });
+                    continue
+                }
+                header = row;
+            }
 
-        Ok((row, false))
+            drop(lines);
+            if reader.is_eof {
+                break
+            }
+            reader.read()?;
+        }
+        Ok(())
     }
+}
 
+impl base::Processor for Handler {
+    fn process_file<R: Read>(mut self, file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<ExitCode> {
+        let ofs = self.determine_delimiters(b"".into(), &base.opts).1;
+        if base.on_ofs(ofs).is_err() {
+            return Ok(ExitCode::SUCCESS)
+        }
+        // silence the break
+        Break::is_break(self.process_lines(file, base, do_callbacks))?;
+        if do_callbacks.contains(Callbacks::ON_EOF) {
+            return self.on_eof_detailed(base)
+        }
+        Ok(ExitCode::SUCCESS)
+    }
 }
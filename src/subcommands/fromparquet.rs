@@ -0,0 +1,120 @@
+use crate::utils::Break;
+use anyhow::{Result, Context};
+use crate::base::{self, Processor, Callbacks};
+use crate::column_slicer::ColumnSlicer;
+use std::io::BufRead;
+use std::process::ExitCode;
+use bstr::BString;
+use clap::Parser;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use arrow::datatypes::Schema;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use parquet::arrow::{ProjectionMask, arrow_reader::ParquetRecordBatchReaderBuilder};
+
+#[derive(Parser)]
+#[command(about = "convert from parquet")]
+pub struct Opts {
+    #[arg(help = "parquet file to read, otherwise read from stdin")]
+    file: Option<String>,
+    #[arg(short = 'c', long, value_delimiter = ',', help = "read only these columns")]
+    columns: Vec<String>,
+    #[arg(short = 'r', long, help = "treat columns as regexes")]
+    regex: bool,
+    #[arg(short = 'g', long, value_delimiter = ',', help = "read only these row groups (0-based)")]
+    row_groups: Vec<usize>,
+}
+
+pub struct Handler {
+    opts: Opts,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
+        Ok(Self {
+            opts,
+        })
+    }
+}
+
+// indices of the columns to read
+pub fn project_schema(schema: &Schema, columns: &[String], regex: bool, lenient: bool) -> Result<Option<Vec<usize>>> {
+    if columns.is_empty() {
+        return Ok(None)
+    }
+    let header: Vec<BString> = schema.fields().iter().map(|f| f.name().as_str().into()).collect();
+    let mut slicer = ColumnSlicer::new(columns, regex)?;
+    slicer.make_header_map(&header, lenient)?;
+    Ok(Some(slicer.indices(header.len(), false).collect()))
+}
+
+pub fn schema_header(schema: &Schema) -> Vec<BString> {
+    schema.fields().iter().map(|f| f.name().as_str().into()).collect()
+}
+
+pub fn batch_to_rows(batch: &RecordBatch) -> Result<Vec<Vec<BString>>> {
+    let options = FormatOptions::default().with_null("");
+    let formatters = batch.columns()
+        .iter()
+        .map(|c| ArrayFormatter::try_new(c.as_ref(), &options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let rows = (0 .. batch.num_rows())
+        .map(|i| formatters.iter().map(|f| f.value(i).to_string().into()).collect())
+        .collect();
+    Ok(rows)
+}
+
+impl Handler {
+    fn process_parquet<T: parquet::file::reader::ChunkReader + 'static>(&mut self, file: T, base: &mut base::Base, do_callbacks: Callbacks) -> Result<()> {
+        let mut builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+        if let Some(indices) = project_schema(builder.schema(), &self.opts.columns, self.opts.regex, base.opts.lenient_fields)? {
+            let mask = ProjectionMask::roots(builder.parquet_schema(), indices);
+            builder = builder.with_projection(mask);
+        }
+        if !self.opts.row_groups.is_empty() {
+            let num_row_groups = builder.metadata().num_row_groups();
+            if let Some(i) = self.opts.row_groups.iter().find(|&&i| i >= num_row_groups) {
+                anyhow::bail!("no such row group: {i} (file has {num_row_groups})");
+            }
+            builder = builder.with_row_groups(self.opts.row_groups.clone());
+        }
+
+        let reader = builder.build()?;
+        if do_callbacks.contains(Callbacks::ON_HEADER) {
+            self.on_header(base, schema_header(&reader.schema()))?;
+        }
+        // the reader yields batches of up to 1024 rows, its default batch size
+        for batch in reader {
+            if do_callbacks.contains(Callbacks::ON_ROW) {
+                for row in batch_to_rows(&batch?)? {
+                    self.on_row(base, row)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl base::Processor for Handler {
+    fn process_file<R: BufRead>(mut self, mut file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<ExitCode> {
+        let ofs = self.determine_delimiters(b"".into(), &base.opts).1;
+        if base.on_ofs(ofs).is_err() {
+            return Ok(ExitCode::SUCCESS)
+        }
+
+        let result = if let Some(path) = &self.opts.file {
+            let file = std::fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+            self.process_parquet(file, base, do_callbacks)
+        } else {
+            // parquet needs to seek, so read it all in
+            let mut contents = vec![];
+            file.read_to_end(&mut contents)?;
+            self.process_parquet(bytes::Bytes::from(contents), base, do_callbacks)
+        };
+        // silence the break
+        Break::is_break(result)?;
+        if do_callbacks.contains(Callbacks::ON_EOF) {
+            return self.on_eof_detailed(base)
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
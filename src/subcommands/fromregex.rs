@@ -0,0 +1,129 @@
+use crate::utils::Break;
+use anyhow::Result;
+use crate::base::{self, Processor, Callbacks};
+use crate::io::Reader;
+use std::process::ExitCode;
+use std::io::Read;
+use regex::bytes::{Regex, Captures};
+use bstr::{BString, ByteVec};
+use clap::Parser;
+
+#[derive(Copy, Clone, PartialEq, Debug, clap::ValueEnum)]
+enum Preset {
+    NginxCombined,
+    Common,
+    Syslog,
+}
+
+impl Preset {
+    fn pattern(self) -> &'static str {
+        match self {
+            Self::NginxCombined => r#"^(?P<remote_addr>\S+) \S+ (?P<remote_user>\S+) \[(?P<time_local>[^\]]*)\] "(?P<request>(?:[^"\\]|\\.)*)" (?P<status>\d+) (?P<body_bytes_sent>\S+) "(?P<http_referer>(?:[^"\\]|\\.)*)" "(?P<http_user_agent>(?:[^"\\]|\\.)*)""#,
+            Self::Common => r#"^(?P<host>\S+) (?P<ident>\S+) (?P<user>\S+) \[(?P<time>[^\]]*)\] "(?P<request>(?:[^"\\]|\\.)*)" (?P<status>\d+) (?P<bytes>\S+)"#,
+            Self::Syslog => r"^(?P<timestamp>\w{3} +\d+ \d\d:\d\d:\d\d) (?P<host>\S+) (?P<program>[^:\[\s]+)(?:\[(?P<pid>\d+)\])?: (?P<message>.*)$",
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(about = "convert from text using a regex with named capture groups")]
+pub struct Opts {
+    #[arg(required_unless_present = "preset", help = "regex to match against each line, named capture groups become columns")]
+    pattern: Option<String>,
+    #[arg(short = 'p', long, value_enum, conflicts_with = "pattern", help = "use a built-in pattern")]
+    preset: Option<Preset>,
+    #[arg(short = 's', long, help = "print lines that do not match to stderr")]
+    stderr: bool,
+    #[arg(short = 'c', long, help = "append lines that do not match to the last column of the previous row")]
+    continuation: bool,
+}
+
+pub struct Handler {
+    opts: Opts,
+    pattern: Regex,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
+        let pattern = opts.preset.map_or_else(|| opts.pattern.clone().unwrap(), |p| p.pattern().into());
+        Ok(Self {
+            pattern: Regex::new(&pattern)?,
+            opts,
+        })
+    }
+
+    fn make_header(&self) -> Vec<BString> {
+        // unnamed groups are named by their index
+        self.pattern.capture_names()
+            .enumerate()
+            .skip(1)
+            .map(|(i, name)| name.map_or_else(|| format!("{i}").into(), |n| n.into()))
+            .collect()
+    }
+
+    fn make_row(captures: Captures) -> Vec<BString> {
+        captures.iter()
+            .skip(1)
+            .map(|m| m.map_or(b"" as _, |m| m.as_bytes()).into())
+            .collect()
+    }
+
+    fn process_lines<R: Read>(&mut self, file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<()> {
+        if do_callbacks.contains(Callbacks::ON_HEADER) {
+            let header = self.make_header();
+            self.on_header(base, header)?;
+        }
+
+        let do_row = do_callbacks.contains(Callbacks::ON_ROW);
+        let mut reader = Reader::new(file);
+        // with --continuation, we only know a row is complete once we see the next match
+        let mut pending: Option<Vec<BString>> = None;
+
+        loop {
+            let mut lines = reader.line_reader();
+            while let Some((line, _)) = lines.get_line(base.irs.as_ref()) {
+                if let Some(captures) = self.pattern.captures(line) {
+                    let row = Self::make_row(captures);
+                    if self.opts.continuation {
+                        if let Some(row) = pending.replace(row) && do_row {
+                            self.on_row(base, row)?;
+                        }
+                    } else if do_row {
+                        self.on_row(base, row)?;
+                    }
+                } else if self.opts.continuation && let Some(last) = pending.as_mut().and_then(|row| row.last_mut()) {
+                    last.push_str(&base.irs);
+                    last.push_str(line);
+                } else if self.opts.stderr {
+                    base.write_stderr(vec![line.to_owned()])?;
+                }
+            }
+
+            drop(lines);
+            if reader.is_eof {
+                break
+            }
+            reader.read()?;
+        }
+
+        if let Some(row) = pending && do_row {
+            self.on_row(base, row)?;
+        }
+        Ok(())
+    }
+}
+
+impl base::Processor for Handler {
+    fn process_file<R: Read>(mut self, file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<ExitCode> {
+        let ofs = self.determine_delimiters(b"".into(), &base.opts).1;
+        if base.on_ofs(ofs).is_err() {
+            return Ok(ExitCode::SUCCESS)
+        }
+        // silence the break
+        Break::is_break(self.process_lines(file, base, do_callbacks))?;
+        if do_callbacks.contains(Callbacks::ON_EOF) {
+            return self.on_eof_detailed(base)
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
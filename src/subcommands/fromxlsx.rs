@@ -0,0 +1,458 @@
+use crate::utils::Break;
+use anyhow::{Result, Context};
+use crate::base::{self, Processor, Callbacks};
+use super::fromxml::push_entity;
+use std::io::{BufRead, BufReader, Read, Seek, Cursor};
+use std::process::ExitCode;
+use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+use bstr::{BString, ByteSlice, ByteVec};
+use clap::Parser;
+use quick_xml::{events::{Event, BytesStart}, reader::Reader};
+use zip::ZipArchive;
+
+static CELL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([A-Za-z]*)(\d*)$").unwrap());
+static DATE_FORMAT_IGNORE: Lazy<Regex> = Lazy::new(|| Regex::new(r#""[^"]*"|\[[^\]]*\]|\\."#).unwrap());
+
+#[derive(Parser)]
+#[command(about = "convert from xlsx spreadsheet")]
+pub struct Opts {
+    #[arg(help = "xlsx file to read, otherwise read from stdin")]
+    file: Option<String>,
+    #[arg(short = 's', long, value_name = "NAME|INDEX", help = "read this sheet instead of the first one")]
+    sheet: Option<String>,
+    #[arg(short = 'r', long, help = "read only the cells in this range e.g. A1:F200")]
+    range: Option<String>,
+}
+
+pub struct Handler {
+    opts: Opts,
+    range: Range,
+    width: usize,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
+        let range = opts.range.as_deref().map_or_else(|| Ok(Range::default()), Range::new)?;
+        Ok(Self {
+            opts,
+            range,
+            width: 0,
+        })
+    }
+}
+
+pub fn column_name(mut index: usize) -> String {
+    let mut name = vec![];
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+// None if it is too long to be a column
+fn column_index(name: &[u8]) -> Option<usize> {
+    let index = name.iter().try_fold(0usize, |acc, c| acc.checked_mul(26)?.checked_add((c.to_ascii_uppercase() - b'A') as usize + 1))?;
+    index.checked_sub(1)
+}
+
+// returns the 0-based (column, row) of a cell reference like B3
+fn parse_cell(cell: &[u8]) -> Option<(Option<usize>, Option<usize>)> {
+    let captures = CELL_REGEX.captures(cell)?;
+    let column = if captures[1].is_empty() { None } else { Some(column_index(&captures[1])?) };
+    let row = crate::utils::try_parse::<usize, _>(&captures[2]).map(|r| r.saturating_sub(1));
+    Some((column, row))
+}
+
+#[derive(Debug)]
+struct Range {
+    columns: (usize, usize),
+    rows: (usize, usize),
+}
+
+impl Default for Range {
+    fn default() -> Self {
+        Self {
+            columns: (0, usize::MAX),
+            rows: (0, usize::MAX),
+        }
+    }
+}
+
+impl Range {
+    fn new(range: &str) -> Result<Self> {
+        let (start, end) = range.split_once(':').unwrap_or((range, range));
+        let start = parse_cell(start.as_bytes()).with_context(|| format!("invalid range: {range}"))?;
+        let end = parse_cell(end.as_bytes()).with_context(|| format!("invalid range: {range}"))?;
+        Ok(Self {
+            columns: (start.0.unwrap_or(0), end.0.unwrap_or(usize::MAX)),
+            rows: (start.1.unwrap_or(0), end.1.unwrap_or(usize::MAX)),
+        })
+    }
+
+    fn slice(&self, mut row: Vec<BString>) -> Vec<BString> {
+        row.truncate(self.columns.1.saturating_add(1));
+        row.drain(.. self.columns.0.min(row.len()));
+        row
+    }
+}
+
+pub fn serial_to_date(serial: f64, date1904: bool) -> Option<String> {
+    let epoch = if date1904 {
+        chrono::NaiveDate::from_ymd_opt(1904, 1, 1)?
+    } else {
+        chrono::NaiveDate::from_ymd_opt(1899, 12, 30)?
+    };
+    let days = serial.floor();
+    let seconds = ((serial - days) * 86400.).round() as i64;
+    let date = epoch.and_hms_opt(0, 0, 0)?
+        .checked_add_signed(chrono::TimeDelta::try_days(days as i64)?)?
+        .checked_add_signed(chrono::TimeDelta::try_seconds(seconds)?)?;
+
+    let format = if seconds == 0 {
+        "%Y-%m-%d"
+    } else if days == 0. {
+        "%H:%M:%S"
+    } else {
+        "%Y-%m-%d %H:%M:%S"
+    };
+    Some(date.format(format).to_string())
+}
+
+fn is_date_format(id: usize, format: Option<&BString>) -> bool {
+    if let Some(format) = format {
+        let format = DATE_FORMAT_IGNORE.replace_all(format, b"");
+        format.iter().any(|c| matches!(c.to_ascii_lowercase(), b'y' | b'm' | b'd' | b'h' | b's'))
+    } else {
+        matches!(id, 14 ..= 22 | 27 ..= 36 | 45 ..= 47 | 50 ..= 58)
+    }
+}
+
+fn get_attr(tag: &BytesStart, name: &[u8]) -> Result<Option<BString>> {
+    for attr in tag.attributes().with_checks(false) {
+        let attr = attr?;
+        if attr.key.local_name().as_ref() == name {
+            return Ok(Some(attr.unescape_value()?.as_bytes().into()))
+        }
+    }
+    Ok(None)
+}
+
+fn make_reader<R: BufRead>(file: R) -> Reader<R> {
+    let mut reader = Reader::from_reader(file);
+    reader.config_mut().expand_empty_elements = true;
+    reader
+}
+
+struct Workbook<R> {
+    archive: ZipArchive<R>,
+    shared_strings: Vec<BString>,
+    date_styles: Vec<bool>,
+    date1904: bool,
+}
+
+impl<R: Read + Seek> Workbook<R> {
+    fn new(file: R) -> Result<Self> {
+        let archive = ZipArchive::new(file).context("failed to read xlsx")?;
+        let mut workbook = Self {
+            archive,
+            shared_strings: vec![],
+            date_styles: vec![],
+            date1904: false,
+        };
+        workbook.read_shared_strings()?;
+        workbook.read_styles()?;
+        Ok(workbook)
+    }
+
+    fn open(&mut self, name: &str) -> Result<Option<Reader<BufReader<zip::read::ZipFile<'_, R>>>>> {
+        match self.archive.by_name(name) {
+            Ok(file) => Ok(Some(make_reader(BufReader::new(file)))),
+            Err(zip::result::ZipError::FileNotFound) => Ok(None),
+            Err(e) => Err(e)?,
+        }
+    }
+
+    fn read_shared_strings(&mut self) -> Result<()> {
+        let mut strings = vec![];
+        if let Some(mut reader) = self.open("xl/sharedStrings.xml")? {
+            let mut buffer = vec![];
+            let mut current = BString::new(vec![]);
+            let mut in_text = false;
+            // skip phonetic runs
+            let mut in_phonetic = false;
+            loop {
+                buffer.clear();
+                match reader.read_event_into(&mut buffer)? {
+                    Event::Start(tag) => match tag.local_name().as_ref() {
+                        b"si" => current.clear(),
+                        b"t" => in_text = !in_phonetic,
+                        b"rPh" => in_phonetic = true,
+                        _ => (),
+                    },
+                    Event::End(tag) => match tag.local_name().as_ref() {
+                        b"si" => strings.push(std::mem::take(&mut current)),
+                        b"t" => in_text = false,
+                        b"rPh" => in_phonetic = false,
+                        _ => (),
+                    },
+                    Event::Text(text) if in_text => current.push_str(text.xml_content()?.as_bytes()),
+                    Event::GeneralRef(entity) if in_text => push_entity(&mut current, &entity)?,
+                    Event::Eof => break,
+                    _ => (),
+                }
+            }
+        }
+        self.shared_strings = strings;
+        Ok(())
+    }
+
+    fn read_styles(&mut self) -> Result<()> {
+        let mut formats = HashMap::new();
+        let mut date_styles = vec![];
+        if let Some(mut reader) = self.open("xl/styles.xml")? {
+            let mut buffer = vec![];
+            let mut in_cell_xfs = false;
+            loop {
+                buffer.clear();
+                match reader.read_event_into(&mut buffer)? {
+                    Event::Start(tag) => match tag.local_name().as_ref() {
+                        b"numFmt" => {
+                            let id = get_attr(&tag, b"numFmtId")?.and_then(crate::utils::try_parse::<usize, _>);
+                            if let Some(id) = id && let Some(format) = get_attr(&tag, b"formatCode")? {
+                                formats.insert(id, format);
+                            }
+                        },
+                        b"cellXfs" => in_cell_xfs = true,
+                        b"xf" if in_cell_xfs => {
+                            let id = get_attr(&tag, b"numFmtId")?.and_then(crate::utils::try_parse::<usize, _>).unwrap_or(0);
+                            date_styles.push(is_date_format(id, formats.get(&id)));
+                        },
+                        _ => (),
+                    },
+                    Event::End(tag) if tag.local_name().as_ref() == b"cellXfs" => in_cell_xfs = false,
+                    Event::Eof => break,
+                    _ => (),
+                }
+            }
+        }
+        self.date_styles = date_styles;
+        Ok(())
+    }
+
+    fn find_sheet(&mut self, sheet: Option<&str>) -> Result<String> {
+        let mut sheets = vec![];
+        let mut reader = self.open("xl/workbook.xml")?.context("invalid xlsx: missing workbook")?;
+        let mut buffer = vec![];
+        let mut date1904 = false;
+        loop {
+            buffer.clear();
+            match reader.read_event_into(&mut buffer)? {
+                Event::Start(tag) => match tag.local_name().as_ref() {
+                    b"sheet" => sheets.push((get_attr(&tag, b"name")?.unwrap_or_default(), get_attr(&tag, b"id")?.unwrap_or_default())),
+                    b"workbookPr" => date1904 = get_attr(&tag, b"date1904")?.is_some_and(|x| x == "1" || x == "true"),
+                    _ => (),
+                },
+                Event::Eof => break,
+                _ => (),
+            }
+        }
+        drop(reader);
+        self.date1904 = date1904;
+
+        // a sheet can be called 2, so try the names first
+        let id = match sheet {
+            None => sheets.first().map(|s| &s.1),
+            Some(sheet) => sheets.iter()
+                .find(|s| s.0 == sheet)
+                .or_else(|| sheets.get(crate::utils::try_parse::<usize, _>(sheet)?.checked_sub(1)?))
+                .map(|s| &s.1),
+        };
+        let id = id.with_context(|| format!("no such sheet: {}", sheet.unwrap_or("1")))?.clone();
+
+        // find the file for the sheet
+        let mut reader = self.open("xl/_rels/workbook.xml.rels")?.context("invalid xlsx: missing workbook relationships")?;
+        loop {
+            buffer.clear();
+            match reader.read_event_into(&mut buffer)? {
+                Event::Start(tag) if tag.local_name().as_ref() == b"Relationship" && get_attr(&tag, b"Id")?.as_ref() == Some(&id) => {
+                    let target = get_attr(&tag, b"Target")?.unwrap_or_default();
+                    let target = target.to_str()?;
+                    return Ok(if let Some(target) = target.strip_prefix('/') {
+                        target.to_owned()
+                    } else {
+                        format!("xl/{target}")
+                    })
+                },
+                Event::Eof => break,
+                _ => (),
+            }
+        }
+        anyhow::bail!("invalid xlsx: missing sheet {id}")
+    }
+
+    fn format_value(&self, value: BString, typ: Option<&BString>, style: Option<usize>) -> BString {
+        match typ.map(|t| t.as_slice()) {
+            Some(b"s") => crate::utils::try_parse::<usize, _>(&value)
+                .and_then(|i| self.shared_strings.get(i))
+                .cloned()
+                .unwrap_or_default(),
+            Some(b"b") => if value == "1" { b"TRUE".into() } else { b"FALSE".into() },
+            Some(b"n") | None => {
+                if style.and_then(|s| self.date_styles.get(s)).copied().unwrap_or(false)
+                    && let Some(date) = crate::utils::try_parse(&value).and_then(|v| serial_to_date(v, self.date1904))
+                {
+                    date.into()
+                } else {
+                    value
+                }
+            },
+            _ => value,
+        }
+    }
+}
+
+impl Handler {
+    fn emit_row(&mut self, base: &mut base::Base, row: Vec<BString>, first: &mut bool, do_callbacks: Callbacks) -> Result<()> {
+        let mut row = self.range.slice(row);
+        // trailing empty cells aren't stored, so fill rows out to the width of the first
+        if *first {
+            self.width = row.len();
+        } else if row.len() < self.width {
+            row.resize(self.width, b"".into());
+        }
+        let is_header = *first && base.opts.header.unwrap_or_else(|| crate::header::looks_like_header(&row));
+        *first = false;
+        if is_header && do_callbacks.contains(Callbacks::ON_HEADER) {
+            self.on_header(base, row)
+        } else if !is_header && do_callbacks.contains(Callbacks::ON_ROW) {
+            self.on_row(base, row)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn process_xlsx<R: Read + Seek>(&mut self, file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<()> {
+        let mut workbook = Workbook::new(file)?;
+        let sheet = workbook.find_sheet(self.opts.sheet.as_deref())?;
+
+        let mut zip_file = workbook.archive.by_name(&sheet).with_context(|| format!("invalid xlsx: missing {sheet}"))?;
+        // the archive is borrowed while reading the sheet, so read it all in first
+        let mut contents = vec![];
+        zip_file.read_to_end(&mut contents)?;
+        drop(zip_file);
+        let mut reader = make_reader(contents.as_slice());
+
+        let mut buffer = vec![];
+        let mut row = vec![];
+        let mut row_num = 0;
+        let mut first = true;
+        // current cell
+        let mut column = 0;
+        let mut typ = None;
+        let mut style = None;
+        let mut value = BString::new(vec![]);
+        let mut in_value = false;
+
+        loop {
+            buffer.clear();
+            match reader.read_event_into(&mut buffer)? {
+                Event::Start(tag) => match tag.local_name().as_ref() {
+                    b"row" => {
+                        row.clear();
+                        column = 0;
+                        if let Some(r) = get_attr(&tag, b"r")? {
+                            row_num = crate::utils::try_parse::<usize, _>(&r)
+                                .and_then(|r| r.checked_sub(1))
+                                .with_context(|| format!("invalid xlsx: bad row number {r:?}"))?;
+                        }
+                    },
+                    b"c" => {
+                        if let Some(r) = get_attr(&tag, b"r")? {
+                            let cell = parse_cell(&r).with_context(|| format!("invalid xlsx: bad cell reference {r:?}"))?;
+                            column = cell.0.unwrap_or(column);
+                        }
+                        typ = get_attr(&tag, b"t")?;
+                        style = get_attr(&tag, b"s")?.and_then(crate::utils::try_parse::<usize, _>);
+                        value.clear();
+                    },
+                    // inline strings are in <is><t>
+                    b"v" | b"t" => in_value = true,
+                    _ => (),
+                },
+                Event::End(tag) => match tag.local_name().as_ref() {
+                    b"v" | b"t" => in_value = false,
+                    b"c" => {
+                        if row.len() <= column {
+                            row.resize(column + 1, b"".into());
+                        }
+                        row[column] = workbook.format_value(std::mem::take(&mut value), typ.as_ref(), style);
+                        column += 1;
+                    },
+                    b"row" => {
+                        if row_num > self.range.rows.1 {
+                            break
+                        }
+                        if row_num >= self.range.rows.0 {
+                            self.emit_row(base, std::mem::take(&mut row), &mut first, do_callbacks)?;
+                        }
+                        row_num += 1;
+                    },
+                    _ => (),
+                },
+                Event::Text(text) if in_value => value.push_str(text.xml_content()?.as_bytes()),
+                Event::GeneralRef(entity) if in_value => push_entity(&mut value, &entity)?,
+                Event::Eof => break,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl base::Processor for Handler {
+    fn process_file<R: BufRead>(mut self, mut file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<ExitCode> {
+        let ofs = self.determine_delimiters(b"".into(), &base.opts).1;
+        if base.on_ofs(ofs).is_err() {
+            return Ok(ExitCode::SUCCESS)
+        }
+
+        let result = if let Some(path) = &self.opts.file {
+            let file = std::fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+            self.process_xlsx(file, base, do_callbacks)
+        } else {
+            // zip needs to seek, so read it all in
+            let mut contents = vec![];
+            file.read_to_end(&mut contents)?;
+            self.process_xlsx(Cursor::new(contents), base, do_callbacks)
+        };
+        // silence the break
+        Break::is_break(result)?;
+        if do_callbacks.contains(Callbacks::ON_EOF) {
+            return self.on_eof_detailed(base)
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_references() {
+        for i in [0, 25, 26, 701, 702, 16383] {
+            assert_eq!(column_index(column_name(i).as_bytes()), Some(i));
+        }
+        assert_eq!(parse_cell(b"B3"), Some((Some(1), Some(2))));
+        assert_eq!(parse_cell(b"7"), Some((None, Some(6))));
+        // too many letters to fit
+        assert_eq!(parse_cell(b"ZZZZZZZZZZZZZZZZZZZZ1"), None);
+    }
+}
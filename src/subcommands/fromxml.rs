@@ -0,0 +1,266 @@
+use crate::utils::Break;
+use anyhow::Result;
+use crate::base::{self, Processor, Callbacks};
+use std::io::BufRead;
+use std::process::ExitCode;
+use std::collections::HashMap;
+use bstr::{BStr, BString, ByteSlice, ByteVec};
+use clap::{Parser};
+use indexmap::{IndexMap, IndexSet};
+use quick_xml::{events::{Event, BytesStart, BytesRef}, reader::Reader};
+
+#[derive(Parser)]
+#[command(about = "convert from xml")]
+pub struct Opts {
+    #[arg(short = 'r', long, default_value = "/*/*", help = "path of the elements to turn into rows e.g. //item or /rss/channel/item")]
+    record: String,
+    #[arg(short = 'i', long, help = "index repeated children e.g. tag[0], tag[1] instead of joining them")]
+    index: bool,
+    #[arg(short = 'j', long, default_value = ",", help = "join repeated children with this string")]
+    join: String,
+    #[arg(short = 's', long, help = "determine header after reading all input")]
+    slurp: bool,
+}
+
+pub struct Handler {
+    opts: Opts,
+    record: RecordPath,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
+        Ok(Self {
+            record: RecordPath::new(&opts.record),
+            opts,
+        })
+    }
+}
+
+pub fn push_entity(text: &mut BString, entity: &BytesRef) -> Result<()> {
+    if let Some(c) = entity.resolve_char_ref()? {
+        text.push_char(c);
+    } else if let Some(value) = quick_xml::escape::resolve_predefined_entity(&entity.decode()?) {
+        text.push_str(value);
+    }
+    Ok(())
+}
+
+struct RecordPath {
+    anchored: bool,
+    parts: Vec<BString>,
+}
+
+impl RecordPath {
+    fn new(path: &str) -> Self {
+        // //item and item match anywhere, /a/item must match from the root
+        let anchored = path.starts_with('/') && !path.starts_with("//");
+        Self {
+            anchored,
+            parts: path.split('/').filter(|p| !p.is_empty()).map(|p| p.into()).collect(),
+        }
+    }
+
+    fn matches(&self, path: &[BString]) -> bool {
+        if path.len() < self.parts.len() || (self.anchored && path.len() != self.parts.len()) {
+            return false
+        }
+        path[path.len() - self.parts.len() ..].iter()
+            .zip(&self.parts)
+            .all(|(name, part)| part == "*" || name == part)
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    name: BString,
+    attrs: Vec<(BString, BString)>,
+    text: BString,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(tag: &BytesStart) -> Result<Self> {
+        let mut attrs = vec![];
+        for attr in tag.attributes().with_checks(false) {
+            let attr = attr?;
+            attrs.push((attr.key.local_name().as_ref().into(), attr.unescape_value()?.as_bytes().into()));
+        }
+        Ok(Self {
+            name: tag.local_name().as_ref().into(),
+            attrs,
+            ..Self::default()
+        })
+    }
+
+    fn flatten(&self, prefix: Option<&BStr>, index: bool, join: &BStr, result: &mut IndexMap<BString, BString>) {
+        let make_key = |name: &[u8]| -> BString {
+            if let Some(prefix) = prefix {
+                bstr::concat([prefix, b"/".as_bstr(), name.as_bstr()]).into()
+            } else {
+                name.into()
+            }
+        };
+        let mut insert = |key: BString, value: &[u8]| {
+            result.entry(key)
+                .and_modify(|v| {
+                    v.push_str(join);
+                    v.push_str(value);
+                })
+                .or_insert_with(|| value.into());
+        };
+
+        for (k, v) in &self.attrs {
+            insert(make_key(&bstr::concat([b"@", k.as_slice()])), v);
+        }
+
+        let text = self.text.trim();
+        // an empty element like <note/> is still a column
+        let is_empty_leaf = prefix.is_some() && self.attrs.is_empty() && self.children.is_empty();
+        if !text.is_empty() || is_empty_leaf {
+            insert(prefix.map_or_else(|| b"#text".into(), |p| p.to_owned()), text);
+        }
+
+        let mut counts = HashMap::new();
+        if index {
+            for child in &self.children {
+                *counts.entry(&child.name).or_insert(0) += 1;
+            }
+        }
+        let mut seen = HashMap::new();
+        for child in &self.children {
+            let key = if counts.get(&child.name).is_some_and(|&c| c > 1) {
+                let i = seen.entry(&child.name).or_insert(0);
+                *i += 1;
+                make_key(format!("{}[{}]", child.name, *i - 1).as_bytes())
+            } else {
+                make_key(&child.name)
+            };
+            child.flatten(Some(key.as_ref()), index, join, result);
+        }
+    }
+}
+
+impl Handler {
+
+    fn process_record(
+        &mut self,
+        base: &mut base::Base,
+        header: &IndexSet<BString>,
+        row: &IndexMap<BString, BString>,
+    ) -> Result<()> {
+        let values = header.iter().map(|k| row.get(k).cloned().unwrap_or_default()).collect();
+        self.on_row(base, values)
+    }
+
+    fn process_xml<R: BufRead>(&mut self, file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<()> {
+        let do_header = do_callbacks.contains(Callbacks::ON_HEADER);
+        let do_row = do_callbacks.contains(Callbacks::ON_ROW);
+
+        let mut reader = Reader::from_reader(file);
+        let config = reader.config_mut();
+        config.check_end_names = false;
+        config.allow_unmatched_ends = true;
+
+        let mut buffer = vec![];
+        let mut path: Vec<BString> = vec![];
+        // non-empty while we are inside a record
+        let mut nodes: Vec<Node> = vec![];
+        let mut header: Option<IndexSet<BString>> = None;
+        let mut slurped = vec![];
+
+        loop {
+            buffer.clear();
+            let event = reader.read_event_into(&mut buffer)?;
+            let (start, end) = match &event {
+                Event::Start(tag) => (Some(tag), false),
+                Event::Empty(tag) => (Some(tag), true),
+                Event::End(_) => (None, true),
+                Event::Text(text) => {
+                    if let Some(node) = nodes.last_mut() {
+                        node.text.push_str(text.xml_content()?.as_bytes());
+                    }
+                    (None, false)
+                },
+                Event::CData(text) => {
+                    if let Some(node) = nodes.last_mut() {
+                        node.text.push_str(&**text);
+                    }
+                    (None, false)
+                },
+                Event::GeneralRef(entity) => {
+                    if let Some(node) = nodes.last_mut() {
+                        push_entity(&mut node.text, entity)?;
+                    }
+                    (None, false)
+                },
+                Event::Eof => break,
+                _ => (None, false),
+            };
+
+            if let Some(tag) = start {
+                path.push(tag.local_name().as_ref().into());
+                if !nodes.is_empty() || self.record.matches(&path) {
+                    nodes.push(Node::new(tag)?);
+                }
+            }
+
+            if end {
+                path.pop();
+                if let Some(node) = nodes.pop() {
+                    if let Some(parent) = nodes.last_mut() {
+                        parent.children.push(node);
+                        continue
+                    }
+
+                    // finished a record
+                    let mut row = IndexMap::new();
+                    node.flatten(None, self.opts.index, self.opts.join.as_bytes().into(), &mut row);
+
+                    if self.opts.slurp {
+                        slurped.push(row);
+                        continue
+                    }
+
+                    // get the header only from the first record
+                    let first = header.is_none();
+                    let header = header.get_or_insert_with(|| row.keys().cloned().collect());
+                    if first && do_header {
+                        self.on_header(base, header.iter().cloned().collect())?;
+                    }
+                    if do_row {
+                        self.process_record(base, header, &row)?;
+                    }
+                }
+            }
+        }
+
+        if self.opts.slurp && !slurped.is_empty() {
+            let header: IndexSet<BString> = slurped.iter().flat_map(|row| row.keys()).cloned().collect();
+            if do_header {
+                self.on_header(base, header.iter().cloned().collect())?;
+            }
+            if do_row {
+                for row in &slurped {
+                    self.process_record(base, &header, row)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl base::Processor for Handler {
+    fn process_file<R: BufRead>(mut self, file: R, base: &mut base::Base, do_callbacks: Callbacks) -> Result<ExitCode> {
+        let ofs = self.determine_delimiters(b"".into(), &base.opts).1;
+        if base.on_ofs(ofs).is_err() {
+            return Ok(ExitCode::SUCCESS)
+        }
+        // silence the break
+        Break::is_break(self.process_xml(file, base, do_callbacks))?;
+        if do_callbacks.contains(Callbacks::ON_EOF) {
+            return self.on_eof_detailed(base)
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
@@ -1,7 +1,7 @@
 use crate::utils::Break;
 use anyhow::{Result, Context};
 use std::collections::{VecDeque, HashSet};
-use std::io::{BufReader, BufRead};
+use std::io::BufRead;
 use crate::base;
 use regex::bytes::{Regex, RegexBuilder};
 use bstr::{BString};
@@ -27,7 +27,7 @@ pub struct CommonOpts {
     max_count: usize,
     #[arg(short = 'o', long, help = "print only the matched (non-empty) parts of a matching column")]
     only_matching: bool,
-    #[arg(short = 'k', long, help = "search only on these fields")]
+    #[arg(short = 'k', long, allow_hyphen_values = true, help = "search only on these fields")]
     fields: Vec<String>,
     #[arg(short = 'r', long, help = "treat fields as regexes")]
     regex: bool,
@@ -36,7 +36,7 @@ pub struct CommonOpts {
 }
 
 #[derive(Parser, Default)]
-#[command(about = "print lines that match patterns")]
+#[command(about = "print lines that match patterns", after_help = crate::column_slicer::FIELDS_HELP)]
 pub struct Opts {
     #[arg(required_unless_present_any = ["regexp", "file"], help = "pattern to search for")]
     pub patterns: Vec<String>,
@@ -83,15 +83,14 @@ impl Handler {
 
         let after = opts.after_context.or(opts.context).unwrap_or(0);
         let before = opts.before_context.or(opts.context);
-        let before = before.map(|b| VecDeque::with_capacity(b));
-        let column_slicer = crate::column_slicer::ColumnSlicer::new(&opts.common.fields, opts.common.regex);
+        let before = before.map(VecDeque::with_capacity);
+        let column_slicer = crate::column_slicer::ColumnSlicer::new(&opts.common.fields, opts.common.regex)?;
 
         // construct the regex pattern
         let mut patterns = std::mem::take(&mut opts.patterns);
         patterns.append(&mut opts.common.regexp);
         for file in &opts.common.file {
-            let file = std::fs::File::open(file).with_context(|| format!("failed to open {file}"))?;
-            let file = BufReader::new(file);
+            let file = crate::io::open_file(file, base.opts.encoding).with_context(|| format!("failed to open {file}"))?;
             for line in file.lines() {
                 patterns.push(line?);
             }
@@ -150,8 +149,18 @@ impl Handler {
 
 impl base::Processor for Handler {
 
+    fn on_alignment(&mut self, base: &mut base::Base, mut alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        if self.opts.count {
+            return Ok(())
+        }
+        if self.opts.line_number {
+            alignment.insert(0, None);
+        }
+        Ok(base.on_alignment(alignment)?)
+    }
+
     fn on_header(&mut self, base: &mut base::Base, mut header: Vec<BString>) -> Result<()> {
-        self.column_slicer.make_header_map(&header);
+        self.column_slicer.make_header_map(&header, base.opts.lenient_fields)?;
         if self.opts.line_number {
             header.insert(0, b"n".into());
         }
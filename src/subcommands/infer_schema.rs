@@ -0,0 +1,338 @@
+use anyhow::Result;
+use crate::base;
+use crate::utils::Break;
+use super::validate::{Type, DATE_FORMATS, parse_value};
+use bstr::{BString, BStr, ByteSlice};
+use clap::{Parser, ValueEnum};
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher, DefaultHasher};
+use serde_json::{json, Value, Map};
+
+// how many hashes to keep for estimating the distinct count
+const SKETCH_SIZE: usize = 1024;
+// in order of preference, the first one that fits every value wins
+const CANDIDATES: &[Type] = &[Type::Int, Type::Float, Type::Percent, Type::Size, Type::Date];
+
+#[derive(Copy, Clone, PartialEq, Debug, Default, ValueEnum)]
+enum Format {
+    // one row per column
+    #[default]
+    Table,
+    // a CREATE TABLE statement
+    Sql,
+    JsonSchema,
+    // json that validate reads
+    Schema,
+}
+
+#[derive(Parser)]
+#[command(about = "guess the type of each column")]
+pub struct Opts {
+    #[arg(short = 'n', long, value_name = "NUM", help = "only look at the first NUM rows")]
+    sample: Option<usize>,
+    #[arg(long = "as", value_enum, default_value_t, help = "what to output")]
+    format: Format,
+    #[arg(long, default_value = "data", help = "table name for --as sql")]
+    table_name: String,
+    #[arg(long, help = "for --as schema, mark columns unique if no value repeats in the sample")]
+    unique: bool,
+    #[arg(long, help = "for --as schema and json-schema, require values between the smallest and largest (or longest) in the sample")]
+    range: bool,
+}
+
+// the k smallest hashes, to estimate the distinct count without keeping every value
+#[derive(Default)]
+struct Sketch {
+    hashes: BTreeSet<u64>,
+}
+
+impl Sketch {
+    fn insert(&mut self, value: &BStr) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if self.hashes.len() < SKETCH_SIZE {
+            self.hashes.insert(hash);
+        } else if hash < *self.hashes.last().unwrap() && self.hashes.insert(hash) {
+            self.hashes.pop_last();
+        }
+    }
+
+    fn is_exact(&self) -> bool {
+        self.hashes.len() < SKETCH_SIZE
+    }
+
+    fn estimate(&self) -> usize {
+        if self.is_exact() {
+            self.hashes.len()
+        } else {
+            ((SKETCH_SIZE - 1) as f64 * u64::MAX as f64 / *self.hashes.last().unwrap() as f64) as usize
+        }
+    }
+}
+
+type Bound = Option<(f64, BString)>;
+
+struct Candidate {
+    typ: Type,
+    min: Bound,
+    max: Bound,
+}
+
+struct Column {
+    name: BString,
+    count: usize,
+    nulls: usize,
+    candidates: Vec<Candidate>,
+    date_formats: Vec<&'static str>,
+    // for strings, compared as bytes
+    min: Option<BString>,
+    max: Option<BString>,
+    max_length: usize,
+    distinct: Sketch,
+}
+
+impl Column {
+    fn new(name: BString) -> Self {
+        Self {
+            name,
+            count: 0,
+            nulls: 0,
+            candidates: CANDIDATES.iter().map(|&typ| Candidate{ typ, min: None, max: None }).collect(),
+            date_formats: DATE_FORMATS.to_vec(),
+            min: None,
+            max: None,
+            max_length: 0,
+            distinct: Sketch::default(),
+        }
+    }
+
+    fn add(&mut self, value: &BStr) {
+        let value = value.trim().as_bstr();
+        if value.is_empty() {
+            self.nulls += 1;
+            return
+        }
+
+        self.count += 1;
+        self.max_length = self.max_length.max(value.chars().count());
+        self.distinct.insert(value);
+        if self.min.as_ref().is_none_or(|min| value < min) {
+            self.min = Some(value.into());
+        }
+        if self.max.as_ref().is_none_or(|max| value > max) {
+            self.max = Some(value.into());
+        }
+
+        self.date_formats.retain(|f| parse_value(Type::Date, Some(f), value).is_some());
+        let format = self.date_formats.first().copied();
+        self.candidates.retain_mut(|c| {
+            let parsed = if c.typ == Type::Date {
+                format.and_then(|f| parse_value(Type::Date, Some(f), value))
+            } else {
+                parse_value(c.typ, None, value)
+            };
+            let Some(parsed) = parsed else { return false };
+            if c.min.as_ref().is_none_or(|(min, _)| parsed < *min) {
+                c.min = Some((parsed, value.into()));
+            }
+            if c.max.as_ref().is_none_or(|(max, _)| parsed > *max) {
+                c.max = Some((parsed, value.into()));
+            }
+            true
+        });
+    }
+
+    fn typ(&self) -> Type {
+        if self.count == 0 {
+            return Type::String
+        }
+        self.candidates.first().map_or(Type::String, |c| c.typ)
+    }
+
+    fn format(&self) -> Option<&'static str> {
+        (self.typ() == Type::Date).then(|| self.date_formats[0])
+    }
+
+    fn bounds(&self) -> (Option<&BString>, Option<&BString>) {
+        match self.candidates.first() {
+            Some(c) if self.count > 0 => (c.min.as_ref().map(|(_, v)| v), c.max.as_ref().map(|(_, v)| v)),
+            _ => (self.min.as_ref(), self.max.as_ref()),
+        }
+    }
+
+    fn is_unique(&self) -> bool {
+        self.distinct.is_exact() && self.count > 1 && self.distinct.estimate() == self.count
+    }
+
+    fn sql_type(&self) -> &'static str {
+        match self.typ() {
+            Type::Int => "INTEGER",
+            Type::Float | Type::Number => "REAL",
+            Type::Date if self.format().is_some_and(|f| f.contains("%H")) => "TIMESTAMP",
+            Type::Date => "DATE",
+            Type::Enum | Type::Percent | Type::Size | Type::String => "TEXT",
+        }
+    }
+
+    // min and max as json, numbers where the type is a number
+    fn json_bounds(&self) -> [Option<Value>; 2] {
+        let (min, max) = self.bounds();
+        [min, max].map(|bound| {
+            let bound = bound?;
+            Some(match self.typ() {
+                Type::Int => json!(crate::utils::try_parse::<i64, _>(bound.trim())?),
+                Type::Float | Type::Number => json!(crate::utils::try_parse::<f64, _>(bound.trim())?),
+                _ => json!(bound.to_str_lossy()),
+            })
+        })
+    }
+}
+
+fn quote_sql(name: &BStr) -> String {
+    format!("\"{}\"", name.to_str_lossy().replace('"', "\"\""))
+}
+
+pub struct Handler {
+    opts: Opts,
+    columns: Vec<Column>,
+    row_count: usize,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
+        Ok(Self {
+            opts,
+            columns: vec![],
+            row_count: 0,
+        })
+    }
+
+    fn as_table(&self, base: &mut base::Base) -> Result<()> {
+        let header = ["column", "type", "nullable", "distinct", "min", "max", "max_length", "format"];
+        base.on_header(header.iter().map(|&h| h.into()).collect())?;
+        for column in &self.columns {
+            let (min, max) = column.bounds();
+            let distinct = if column.distinct.is_exact() { "" } else { "~" };
+            base.on_row(vec![
+                column.name.clone(),
+                column.typ().name().into(),
+                (column.nulls > 0).to_string().into(),
+                format!("{distinct}{}", column.distinct.estimate()).into(),
+                min.cloned().unwrap_or_default(),
+                max.cloned().unwrap_or_default(),
+                column.max_length.to_string().into(),
+                column.format().unwrap_or_default().into(),
+            ])?;
+        }
+        Ok(())
+    }
+
+    fn as_sql(&self, base: &mut base::Base) -> Result<()> {
+        let columns: Vec<_> = self.columns.iter().map(|c| {
+            let null = if c.nulls > 0 { "" } else { " NOT NULL" };
+            format!("    {} {}{null}", quote_sql(c.name.as_ref()), c.sql_type())
+        }).collect();
+        let sql = format!("CREATE TABLE {} (\n{}\n);", quote_sql(self.opts.table_name.as_bytes().into()), columns.join(",\n"));
+        base.write_raw(sql.into(), true, false)?;
+        Ok(())
+    }
+
+    fn as_json_schema(&self, base: &mut base::Base) -> Result<()> {
+        let mut properties = Map::new();
+        for column in &self.columns {
+            let mut property = Map::new();
+            let typ = match column.typ() {
+                Type::Int => "integer",
+                Type::Float | Type::Number => "number",
+                _ => "string",
+            };
+            property.insert("type".into(), if column.nulls > 0 { json!([typ, "null"]) } else { json!(typ) });
+            match (column.typ(), column.format()) {
+                (Type::Int | Type::Float | Type::Number, _) if !self.opts.range => (),
+                (Type::Int | Type::Float | Type::Number, _) => {
+                    let [min, max] = column.json_bounds();
+                    if let Some(min) = min { property.insert("minimum".into(), min); }
+                    if let Some(max) = max { property.insert("maximum".into(), max); }
+                },
+                (Type::Date, Some("%Y-%m-%d")) => { property.insert("format".into(), json!("date")); },
+                (Type::Date, Some("%Y-%m-%dT%H:%M:%S")) => { property.insert("format".into(), json!("date-time")); },
+                _ if self.opts.range => { property.insert("maxLength".into(), json!(column.max_length)); },
+                _ => (),
+            }
+            properties.insert(column.name.to_str_lossy().into(), Value::Object(property));
+        }
+
+        let schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "required": self.columns.iter().map(|c| c.name.to_str_lossy()).collect::<Vec<_>>(),
+            "properties": properties,
+        });
+        base.write_raw(serde_json::to_string_pretty(&schema)?.into(), true, false)?;
+        Ok(())
+    }
+
+    fn as_schema(&self, base: &mut base::Base) -> Result<()> {
+        let columns: Vec<_> = self.columns.iter().map(|column| {
+            let mut schema = Map::new();
+            schema.insert("name".into(), json!(column.name.to_str_lossy()));
+            schema.insert("type".into(), json!(column.typ().name()));
+            schema.insert("nullable".into(), json!(column.nulls > 0));
+            // the sample says nothing about data yet to come, so these are opt in
+            if self.opts.unique && column.is_unique() {
+                schema.insert("unique".into(), json!(true));
+            }
+            if let Some(format) = column.format() {
+                schema.insert("format".into(), json!(format));
+            }
+            // strings would be bounded by length, which is too strict to guess
+            if self.opts.range && column.typ() != Type::String {
+                let [min, max] = column.json_bounds();
+                if let Some(min) = min { schema.insert("min".into(), min); }
+                if let Some(max) = max { schema.insert("max".into(), max); }
+            }
+            Value::Object(schema)
+        }).collect();
+        base.write_raw(serde_json::to_string_pretty(&json!({"columns": columns}))?.into(), true, false)?;
+        Ok(())
+    }
+}
+
+impl base::Processor for Handler {
+    // the output is one row per column, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut base::Base, _alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+        self.columns = header.into_iter().map(Column::new).collect();
+        Ok(())
+    }
+
+    fn on_row(&mut self, _base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+        for i in self.columns.len() .. row.len() {
+            let mut column = Column::new(format!("column_{}", i + 1).into());
+            // the rows so far didn't have this column
+            column.nulls = self.row_count;
+            self.columns.push(column);
+        }
+        for (i, column) in self.columns.iter_mut().enumerate() {
+            column.add(row.get(i).map_or(b"".as_bstr(), |v| v.as_bstr()));
+        }
+
+        self.row_count += 1;
+        Break::when(self.opts.sample.is_some_and(|n| self.row_count >= n))
+    }
+
+    fn on_eof(self, base: &mut base::Base) -> Result<bool> {
+        match self.opts.format {
+            Format::Table => self.as_table(base),
+            Format::Sql => self.as_sql(base),
+            Format::JsonSchema => self.as_json_schema(base),
+            Format::Schema => self.as_schema(base),
+        }?;
+        base.on_eof()
+    }
+}
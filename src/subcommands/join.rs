@@ -9,7 +9,7 @@ use crate::column_slicer::ColumnSlicer;
 use clap::{Parser};
 
 #[derive(Parser, Clone)]
-#[command(about = "join lines of two files on a common field")]
+#[command(about = "join lines of two files on a common field", after_help = crate::column_slicer::FIELDS_HELP)]
 pub struct Opts {
     #[arg(value_name = "FILE", help = "join stdin with FILE")]
     file: String,
@@ -107,8 +107,7 @@ impl Handler {
             child.left = false;
             base.scope.spawn(move || {
                 let result = (|| {
-                    let file = std::fs::File::open(&right_file).with_context(|| format!("failed to open {right_file}"))?;
-                    let file = std::io::BufReader::new(file);
+                    let file = crate::io::open_file(&right_file, base.opts.encoding).with_context(|| format!("failed to open {right_file}"))?;
                     child.process_file(file, &mut base, Callbacks::ON_HEADER | Callbacks::ON_ROW)?;
                     Ok(())
                 })();
@@ -160,7 +159,7 @@ impl Joiner {
         let is_fields_set = !fields.0.is_empty() || !fields.1.is_empty();
         let mut stores = (HashMap::new(), HashMap::new());
         let mut headers = (None, None);
-        let mut slicers = (ColumnSlicer::new(fields.0, opts.regex), ColumnSlicer::new(fields.1, opts.regex));
+        let mut slicers = (ColumnSlicer::new(fields.0, opts.regex)?, ColumnSlicer::new(fields.1, opts.regex)?);
         let mut got_headers = false;
         let mut buffer = vec![];
 
@@ -169,6 +168,8 @@ impl Joiner {
                 Message::Separator => unreachable!(),
                 Message::Raw(..) => unreachable!(),
                 Message::Eof => (),
+                // columns get rearranged, so alignment no longer applies
+                Message::Alignment(_) => (),
                 Message::Stderr(_) => unreachable!(),
                 Message::RawStderr(..) => unreachable!(),
                 Message::Ofs(ofs) => if is_left {
@@ -191,8 +192,8 @@ impl Joiner {
                             let fields: Vec<_> = left.intersection(&right).copied().cloned().collect();
                             if fields.is_empty() {
                                 // default join field is the first
-                                slicers.0 = ColumnSlicer::new(&["1".into()], false);
-                                slicers.1 = ColumnSlicer::new(&["1".into()], false);
+                                slicers.0 = ColumnSlicer::new(&["1".into()], false)?;
+                                slicers.1 = ColumnSlicer::new(&["1".into()], false)?;
                             } else {
                                 slicers.0 = ColumnSlicer::from_names(fields.iter());
                                 slicers.1 = ColumnSlicer::from_names(fields.iter());
@@ -200,8 +201,8 @@ impl Joiner {
                         }
 
                         // make header maps
-                        slicers.0.make_header_map(headers.0);
-                        slicers.1.make_header_map(headers.1);
+                        slicers.0.make_header_map(headers.0, base.opts.lenient_fields)?;
+                        slicers.1.make_header_map(headers.1, base.opts.lenient_fields)?;
 
                         // paste the headers together
                         let mut header = slicers.0.slice(headers.0, false, true);
@@ -31,8 +31,7 @@ impl Handler {
             let mut base = base.clone();
             base.scope.spawn(move || {
                 let result = (|| {
-                    let file = std::fs::File::open(file)?;
-                    let file = std::io::BufReader::new(file);
+                    let file = crate::io::open_file(file, base.opts.encoding)?;
                     Child{ sender }.process_file(file, &mut base, Callbacks::ON_HEADER | Callbacks::ON_ROW)?;
                     Ok(())
                 })();
@@ -49,6 +48,11 @@ impl Handler {
 }
 
 impl Processor for Handler {
+    // the other files have their own columns, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut Base, _alignment: Vec<Option<Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
     fn on_header(&mut self, base: &mut Base, header: Vec<BString>) -> Result<()> {
         base.on_header(self.paste_row(header))
     }
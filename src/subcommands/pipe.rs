@@ -11,9 +11,9 @@ use crate::column_slicer::ColumnSlicer;
 use clap::{Parser};
 
 #[derive(Parser, Clone)]
-#[command(about = "pipe rows through a process")]
+#[command(about = "pipe rows through a process", after_help = crate::column_slicer::FIELDS_HELP)]
 pub struct Opts {
-    #[arg(short = 'k', long, help = "pipe only these fields")]
+    #[arg(short = 'k', long, allow_hyphen_values = true, help = "pipe only these fields")]
     fields: Vec<String>,
     #[arg(short = 'x', long, help = "exclude, rather than include, field names")]
     complement: bool,
@@ -47,7 +47,7 @@ impl Handler {
         let column_slicer = if opts.fields.is_empty() {
             None
         } else {
-            Some(ColumnSlicer::new(&opts.fields, opts.regex))
+            Some(ColumnSlicer::new(&opts.fields, opts.regex)?)
         };
 
         let (sender, receiver) = mpsc::channel();
@@ -124,7 +124,7 @@ impl base::Processor for Handler {
 
     fn on_header(&mut self, base: &mut base::Base, mut header: Vec<BString>) -> Result<()> {
         if let Some(slicer) = &mut self.column_slicer {
-            slicer.make_header_map(&header);
+            slicer.make_header_map(&header, base.opts.lenient_fields)?;
         }
         header.extend(self.opts.append_columns.iter().map(|x| x.as_bytes().into()));
         base.on_header(header)
@@ -190,7 +190,7 @@ struct JoinHandler {
 impl base::Processor for JoinHandler {
     fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
         if let Some(slicer) = &mut self.column_slicer {
-            slicer.make_header_map(&header);
+            slicer.make_header_map(&header, base.opts.lenient_fields)?;
         }
         self.header_len = header.len();
         base.on_header(header)
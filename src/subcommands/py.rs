@@ -202,6 +202,11 @@ impl Handler {
 
 impl base::Processor for Handler {
 
+    // the script can change the columns, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut base::Base, _alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
     fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
         self.process_header(&header);
         Ok(())
@@ -9,7 +9,7 @@ use crate::python;
 
 #[derive(Parser, Default)]
 struct OtherOpts {
-    #[arg(short = 'k', long, help = "sort based only on these fields")]
+    #[arg(short = 'k', long, allow_hyphen_values = true, help = "sort based only on these fields")]
     fields: Vec<String>,
     #[arg(short = 'x', long, help = "exclude, rather than include, field names")]
     complement: bool,
@@ -20,7 +20,7 @@ struct OtherOpts {
 }
 
 #[derive(Parser, Default)]
-#[command(about = "aggregate rows using python")]
+#[command(about = "aggregate rows using python", after_help = crate::column_slicer::FIELDS_HELP)]
 pub struct Opts {
     #[command(flatten)]
     common: py::CommonOpts,
@@ -53,7 +53,7 @@ impl Handler {
         let mut py_opts = py::Opts::default();
         py_opts.common = opts.common;
         let inner = py::Handler::new(py_opts, base)?;
-        let column_slicer = ColumnSlicer::new(&opts.other.fields, opts.other.regex);
+        let column_slicer = ColumnSlicer::new(&opts.other.fields, opts.other.regex)?;
 
         let py = inner.py.acquire_gil();
         let postprocess = py.compile_code_cstr(POSTPROCESS, None, python::StartToken::File)?;
@@ -83,8 +83,13 @@ impl Handler {
 
 impl base::Processor for Handler {
 
-    fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
-        self.column_slicer.make_header_map(&header);
+    // the output columns are the groups and aggregates, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut base::Base, _alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+        self.column_slicer.make_header_map(&header, base.opts.lenient_fields)?;
         self.inner.process_header(&header);
         self.header = Some(header);
         Ok(())
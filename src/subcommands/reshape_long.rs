@@ -7,11 +7,11 @@ use regex::bytes::Regex;
 use clap::{Parser};
 
 #[derive(Parser, Default)]
-#[command(about = "reshape to long format")]
+#[command(about = "reshape to long format", after_help = crate::column_slicer::FIELDS_HELP)]
 pub struct Opts {
     #[arg(default_value = "value", help = "value field (timevar/wide variable)")]
     value: String,
-    #[arg(short = 'k', long, help = "reshape only these fields")]
+    #[arg(short = 'k', long, allow_hyphen_values = true, help = "reshape only these fields")]
     fields: Vec<String>,
     #[arg(short = 'r', long, help = "treat fields as regexes")]
     regex: bool,
@@ -33,8 +33,8 @@ pub struct Handler {
 impl Handler {
     pub fn new(opts: Opts, _: &mut base::Base) -> Result<Self> {
         Ok(Self{
-            column_slicer: ColumnSlicer::new(&opts.fields, opts.regex),
-            format_slicer: ColumnSlicer::new([&opts.format], true),
+            column_slicer: ColumnSlicer::new(&opts.fields, opts.regex)?,
+            format_slicer: ColumnSlicer::new([&opts.format], true)?,
             format_pattern: Regex::new(&opts.format).unwrap(),
             opts,
             wide_header: None,
@@ -45,15 +45,22 @@ impl Handler {
 
 impl base::Processor for Handler {
 
+    // the wide columns are folded into rows, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut base::Base, _alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
     fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
-        self.column_slicer.make_header_map(&header);
-        self.format_slicer.make_header_map(&header);
+        self.column_slicer.make_header_map(&header, base.opts.lenient_fields)?;
+        // a format that matches nothing just means nothing to reshape
+        self.format_slicer.make_header_map(&header, true)?;
 
         let mut group_header = vec![];
         let mut wide_header = HashSet::new();
         let mut header_matches = vec![];
+        let len = header.len();
         for (i, h) in header.into_iter().enumerate() {
-            if self.column_slicer.matches(i) && let Some(c) = self.format_pattern.captures(&h) {
+            if self.column_slicer.matches(i, len) && let Some(c) = self.format_pattern.captures(&h) {
                 wide_header.insert(c.get(1).map_or(b"" as _, |m| m.as_bytes()).to_owned());
                 header_matches.push((
                     c.get(1).or_else(|| c.name("key")).map_or(b"" as _, |m| m.as_bytes()).to_owned().into(),
@@ -82,8 +89,9 @@ impl base::Processor for Handler {
 
             let mut keys = vec![];
             let mut wide = vec![];
+            let len = row.len();
             for (i, col) in row.into_iter().enumerate() {
-                if self.column_slicer.matches(i) && self.format_slicer.matches(i) {
+                if self.column_slicer.matches(i, len) && self.format_slicer.matches(i, len) {
                     wide.push(col);
                 } else {
                     keys.push(col);
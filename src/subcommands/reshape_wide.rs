@@ -20,11 +20,11 @@ fn name_format(format: &BStr, a: &BStr, b: &BStr) -> BString {
 }
 
 #[derive(Parser, Default)]
-#[command(about = "reshape to wide format")]
+#[command(about = "reshape to wide format", after_help = crate::column_slicer::FIELDS_HELP)]
 pub struct Opts {
     #[arg(help = "value field (timevar/wide variable)")]
     value: String,
-    #[arg(help = "fields to group by (idvar/long variable)")]
+    #[arg(allow_negative_numbers = true, help = "fields to group by (idvar/long variable)")]
     fields: Vec<String>,
     #[arg(short = 'x', long, help = "exclude, rather than include, field names")]
     complement: bool,
@@ -52,8 +52,8 @@ impl Handler {
     pub fn new(opts: Opts, _: &mut base::Base) -> Result<Self> {
         Ok(Self{
             slicers: Slicers{
-                group: ColumnSlicer::new(&opts.fields, opts.regex),
-                long: ColumnSlicer::new([&opts.value], opts.regex),
+                group: ColumnSlicer::new(&opts.fields, opts.regex)?,
+                long: ColumnSlicer::new([&opts.value], opts.regex)?,
                 complement: opts.complement,
             },
             group_header: None,
@@ -75,9 +75,14 @@ impl Slicers {
 
 impl base::Processor for Handler {
 
-    fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
-        self.slicers.group.make_header_map(&header);
-        self.slicers.long.make_header_map(&header);
+    // the long values become new columns, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut base::Base, _alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+        self.slicers.group.make_header_map(&header, base.opts.lenient_fields)?;
+        self.slicers.long.make_header_map(&header, base.opts.lenient_fields)?;
         self.group_header = Some(self.slicers.group.slice(&header, self.slicers.complement, true));
         self.wide_header = Some(self.slicers.wide_indices(header.len()).map(|i| header[i].clone()).collect());
         Ok(())
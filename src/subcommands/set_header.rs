@@ -51,8 +51,8 @@ impl base::Processor for Handler {
     fn on_header(&mut self, base: &mut base::Base, mut header: Vec<BString>) -> Result<()> {
         self.got_header = true;
 
-        let mut column_slicer = ColumnSlicer::new(&[], false);
-        column_slicer.make_header_map(&header);
+        let mut column_slicer = ColumnSlicer::new(&[], false)?;
+        column_slicer.make_header_map(&header, base.opts.lenient_fields)?;
         for [old, new] in self.opts.rename.as_chunks().0 {
             if let Some(i) = column_slicer.get_single_field_index(old) {
                 if let Some(h) = header.get_mut(i) {
@@ -9,11 +9,11 @@ use clap::{Parser, ArgAction};
 const ORS: u8 = b'\x00';
 
 #[derive(Parser)]
-#[command(about = "sort the rows", disable_help_flag = true, disable_version_flag = true)]
+#[command(about = "sort the rows", after_help = crate::column_slicer::FIELDS_HELP, disable_help_flag = true, disable_version_flag = true)]
 pub struct Opts {
-    #[arg(help = "sort based only on these fields")]
+    #[arg(allow_negative_numbers = true, help = "sort based only on these fields")]
     fields: Vec<String>,
-    #[arg(short = 'k', long = "fields", value_name = "fields", help = "sort based only on these fields")]
+    #[arg(short = 'k', long = "fields", value_name = "fields", allow_hyphen_values = true, help = "sort based only on these fields")]
     old_style_fields: Vec<String>,
     #[arg(short = 'x', long, help = "exclude, rather than include, field names")]
     complement: bool,
@@ -63,7 +63,7 @@ pub struct Handler {
 impl Handler {
     pub fn new(mut opts: Opts, _base: &mut base::Base) -> Result<Self> {
         opts.fields.extend(opts.old_style_fields.iter().flat_map(|x| x.split(',')).map(|x| x.into()));
-        let column_slicer = ColumnSlicer::new(&opts.fields, opts.regex);
+        let column_slicer = ColumnSlicer::new(&opts.fields, opts.regex)?;
         Ok(Self {
             proc: None,
             opts,
@@ -76,13 +76,13 @@ impl Handler {
 
 impl base::Processor for Handler {
     fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
-        self.column_slicer.make_header_map(&header);
+        self.column_slicer.make_header_map(&header, base.opts.lenient_fields)?;
         base.on_header(header)
     }
 
     fn on_row(&mut self, _base: &mut base::Base, row: Vec<BString>) -> Result<()> {
         let key = self.column_slicer.slice(&row, self.opts.complement, true);
-        let key = crate::writer::format_columns(key, &self.ofs, (&[ORS]).into(), true).0;
+        let key = crate::writer::format_columns(key, &self.ofs, (&[ORS]).into(), true, &Default::default()).0;
         // add row index as first column
         let index = [format!("{}", self.rows.len()).into()];
         let key = index.iter().chain(key.iter());
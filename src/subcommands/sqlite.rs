@@ -41,6 +41,11 @@ impl Handler {
 
 impl base::Processor for Handler {
 
+    // the query decides the columns, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut base::Base, _alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
     fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
         self.got_header = true;
         self.on_row(base, header)
@@ -51,7 +56,7 @@ impl base::Processor for Handler {
 
         assert!(self.got_header, "cannot use sqlite without a header");
         let proc = self.start_proc()?;
-        let row = crate::writer::format_columns(row, &base::Ofs::Plain(DELIM.as_bytes()), ORS.into(), true).0;
+        let row = crate::writer::format_columns(row, &base::Ofs::Plain(DELIM.as_bytes()), ORS.into(), true, &Default::default()).0;
         proc.stdin.write_all(&row.join(DELIM.as_bytes()))?;
         proc.stdin.write_all(ORS)?;
         Ok(())
@@ -16,7 +16,7 @@ fn nice_float(val: f64) -> String {
 
 static NUM_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d+(?:\.\d+)?").unwrap());
 
-fn parse_size(bytes: &BStr) -> Option<f64> {
+pub fn parse_size(bytes: &BStr) -> Option<f64> {
     let m = NUM_REGEX.find(bytes)?;
     let suffix = bytes[m.end() .. ].trim();
     let len = suffix.len().min(3);
@@ -42,6 +42,13 @@ fn parse_size(bytes: &BStr) -> Option<f64> {
     Some(val * mul as f64)
 }
 
+// whether the value is a number, percentage or size, as detected by summary
+pub fn is_numeric(value: &BStr) -> bool {
+    crate::utils::try_parse::<f64, _>(value).is_some()
+        || value.strip_suffix(b"%").and_then(crate::utils::try_parse::<f64, _>).is_some()
+        || parse_size(value).is_some()
+}
+
 fn make_counter<T: Eq + std::hash::Hash, I: IntoIterator<Item=T>>(values: I) -> HashMap<T, usize> {
     let mut counts = HashMap::new();
     for c in values {
@@ -60,11 +67,11 @@ fn get_quartiles<T>(values: &[T]) -> (&T, &T, &T) {
 }
 
 #[derive(Parser, Default)]
-#[command(about = "product automatic summaries of the data")]
+#[command(about = "product automatic summaries of the data", after_help = crate::column_slicer::FIELDS_HELP)]
 pub struct Opts {
     #[arg(long, value_enum, default_value_t = base::AutoChoices::Auto, help = "show a separator between the columns")]
     col_sep: base::AutoChoices,
-    #[arg(help = "select only these fields")]
+    #[arg(allow_negative_numbers = true, help = "select only these fields")]
     fields: Vec<String>,
     #[arg(short = 'x', long, help = "exclude, rather than include, field names")]
     complement: bool,
@@ -125,7 +132,7 @@ impl Handler {
 
         Ok(Self{
             complement: opts.complement,
-            column_slicer: (!opts.fields.is_empty()).then(|| ColumnSlicer::new(&opts.fields, opts.regex)),
+            column_slicer: (!opts.fields.is_empty()).then(|| ColumnSlicer::new(&opts.fields, opts.regex)).transpose()?,
             col_sep: opts.col_sep.is_on(base.opts.inner.is_stdout_tty),
             header: None,
             rows: vec![],
@@ -136,9 +143,14 @@ impl Handler {
 
 impl base::Processor for Handler {
 
-    fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+    // the output is one row per column, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut base::Base, _alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
         let header = if let Some(slicer) = &mut self.column_slicer {
-            slicer.make_header_map(&header);
+            slicer.make_header_map(&header, base.opts.lenient_fields)?;
             slicer.slice(&header, self.complement, true)
         } else {
             header
@@ -159,8 +171,8 @@ impl base::Processor for Handler {
         let mut header = self.header.unwrap_or_default();
         let num_cols = self.rows.iter().map(|r| r.len()).max().unwrap_or(0).max(header.len());
 
-        let mut column_slicer = ColumnSlicer::new(&[], false);
-        column_slicer.make_header_map(&header);
+        let mut column_slicer = ColumnSlicer::new(&[], false)?;
+        column_slicer.make_header_map(&header, base.opts.lenient_fields)?;
 
         if header.len() < num_cols {
             header.extend((header.len() .. num_cols).map(|i| format!("{i}").into()));
@@ -274,37 +286,39 @@ fn display_enum(base: &mut base::Base, header: &BString, column: &Vec<Option<&BS
     })))
 }
 
-fn display_date(base: &mut base::Base, header: &BString, column: &Vec<Option<&BString>>, cutoff: f64) -> Option<Result<()>> {
+// seconds since the epoch, from one of the date formats summary knows or a timestamp
+pub fn parse_date(value: &str) -> Option<f64> {
     const DATE_YARDSTICK: f64 = chrono::NaiveDate
         ::from_ymd_opt(2000, 1, 1).unwrap()
         .and_hms_opt(0, 0, 0).unwrap()
         .and_utc()
         .timestamp() as _;
 
-    let parsed: Vec<_> = column.iter().map(|&c| {
-        let c = std::str::from_utf8(c?).ok()?;
-        [
-            "%Y-%m-%dT%H:%M:%S.%f%z",
-            "%Y-%m-%d %H:%M:%S.%f",
-            "%Y-%m-%dT%H:%M:%S%z",
-            "%Y-%m-%dT%H:%M:%S",
-            "%Y-%m-%d %H:%M:%S",
-            "%Y/%m/%d %H:%M:%S",
-            "%d/%m/%y %H:%M:%S",
-        ].iter().find_map(|f| chrono::DateTime::parse_from_str(c, f).map(|d| d.to_utc()).ok())
-        .map(|date| date.timestamp() as f64)
-        .or_else(|| {
-            let val = c.parse().ok()?;
-            if val > DATE_YARDSTICK * 1000. {
-                // this is in milliseconds
-                Some(val / 1000.)
-            } else if val > DATE_YARDSTICK {
-                Some(val)
-            } else {
-                None
-            }
-        })
-    }).collect();
+    [
+        "%Y-%m-%dT%H:%M:%S.%f%z",
+        "%Y-%m-%d %H:%M:%S.%f",
+        "%Y-%m-%dT%H:%M:%S%z",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y/%m/%d %H:%M:%S",
+        "%d/%m/%y %H:%M:%S",
+    ].iter().find_map(|f| chrono::DateTime::parse_from_str(value, f).map(|d| d.to_utc()).ok())
+    .map(|date| date.timestamp() as f64)
+    .or_else(|| {
+        let val = value.parse().ok()?;
+        if val > DATE_YARDSTICK * 1000. {
+            // this is in milliseconds
+            Some(val / 1000.)
+        } else if val > DATE_YARDSTICK {
+            Some(val)
+        } else {
+            None
+        }
+    })
+}
+
+fn display_date(base: &mut base::Base, header: &BString, column: &Vec<Option<&BString>>, cutoff: f64) -> Option<Result<()>> {
+    let parsed: Vec<_> = column.iter().map(|&c| parse_date(std::str::from_utf8(c?).ok()?)).collect();
 
     let stats = get_numeric_stats(&parsed, cutoff, |x| {
         chrono::DateTime::from_timestamp(x.floor() as _, ((x % 1.) * 1_000_000.) as _).unwrap().to_rfc3339()
@@ -0,0 +1,84 @@
+use anyhow::{Result, Context};
+use crate::base;
+use super::toparquet::Batcher;
+use std::fs::File;
+use std::io::Write;
+use bstr::BString;
+use clap::Parser;
+use arrow::record_batch::RecordBatch;
+use arrow::ipc::writer::StreamWriter;
+
+#[derive(Parser)]
+#[command(about = "convert to arrow ipc stream")]
+pub struct Opts {
+    #[arg(help = "arrow file to write, otherwise write to stdout")]
+    file: Option<String>,
+    #[arg(long, default_value_t = 1024, help = "number of rows to infer the schema from, also used as the batch size")]
+    sample: usize,
+}
+
+pub struct Handler {
+    file: Option<File>,
+    writer: Option<StreamWriter<Vec<u8>>>,
+    batcher: Batcher,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, base: &mut base::Base) -> Result<Self> {
+        base.opts.inner.trailer = base::AutoChoices::Never;
+        let file = opts.file.as_ref()
+            .map(|path| File::create(path).with_context(|| format!("failed to create {path}")))
+            .transpose()?;
+        Ok(Self {
+            file,
+            writer: None,
+            batcher: Batcher::new(opts.sample),
+        })
+    }
+
+    // send out whatever has been written so far
+    fn flush(&mut self, base: &mut base::Base, buffer: Vec<u8>) -> Result<()> {
+        if let Some(file) = &mut self.file {
+            file.write_all(&buffer)?;
+        } else {
+            base.write_raw(buffer.into(), false, false)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, base: &mut base::Base, batch: RecordBatch) -> Result<()> {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => self.writer.insert(StreamWriter::try_new(vec![], &batch.schema())?),
+        };
+        writer.write(&batch)?;
+        let buffer = std::mem::take(writer.get_mut());
+        self.flush(base, buffer)
+    }
+}
+
+impl base::Processor for Handler {
+    fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+        self.batcher.header = Some(header);
+        Ok(())
+    }
+
+    fn on_row(&mut self, base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+        if let Some(batch) = self.batcher.push(row)? {
+            self.write(base, batch)?;
+        }
+        Ok(())
+    }
+
+    fn on_eof(mut self, base: &mut base::Base) -> Result<bool> {
+        if let Some(batch) = self.batcher.finish()? {
+            self.write(base, batch)?;
+        }
+        if let Some(mut writer) = self.writer.take() {
+            writer.finish()?;
+            let buffer = writer.into_inner()?;
+            self.flush(base, buffer)?;
+        }
+        base.on_eof()
+    }
+}
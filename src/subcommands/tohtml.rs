@@ -0,0 +1,277 @@
+use anyhow::Result;
+use crate::base;
+use crate::writer::{Writer, BaseWriter, WriterState, get_rgb_values};
+use super::toxml::escape;
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+use std::io::Write;
+use bstr::{BString, BStr, ByteSlice, ByteVec};
+use clap::Parser;
+
+const HTML_HEAD: &str = "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n</head>\n<body>";
+static SGR_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\x1b\[([0-9;]*)m").unwrap());
+
+#[derive(Parser, Clone, Default)]
+#[command(about = "convert to html table")]
+pub struct Opts {
+    #[arg(short = 's', long, help = "output a full html document rather than just the table")]
+    standalone: bool,
+    #[arg(short = 'n', long, help = "right align numeric cells")]
+    align_numbers: bool,
+    #[arg(long, help = "add inline css for the header and column colours")]
+    style: bool,
+}
+
+pub struct Handler {
+    opts: Opts,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, base: &mut base::Base) -> Result<Self> {
+        base.opts.inner.trailer = base::AutoChoices::Never;
+        base.opts.inner.numbered_columns = base::AutoChoices::Never;
+        Ok(Self {
+            opts,
+        })
+    }
+}
+
+impl base::Processor<HtmlWriter> for Handler {
+    fn make_writer(&self, opts: base::BaseOptions) -> base::Output<HtmlWriter> {
+        let mut writer = HtmlWriter::new(&opts);
+        writer.opts = self.opts.clone();
+        base::Output::with_writer(opts, writer)
+    }
+}
+
+fn xterm_colour(i: u8) -> (u8, u8, u8) {
+    const BASIC: [(u8, u8, u8); 16] = [
+        (0, 0, 0), (128, 0, 0), (0, 128, 0), (128, 128, 0), (0, 0, 128), (128, 0, 128), (0, 128, 128), (192, 192, 192),
+        (128, 128, 128), (255, 0, 0), (0, 255, 0), (255, 255, 0), (0, 0, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+    ];
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match i {
+        0 ..= 15 => BASIC[i as usize],
+        16 ..= 231 => {
+            let i = i - 16;
+            (LEVELS[(i / 36) as usize], LEVELS[(i / 6 % 6) as usize], LEVELS[(i % 6) as usize])
+        },
+        _ => {
+            let grey = 8 + 10 * (i - 232);
+            (grey, grey, grey)
+        },
+    }
+}
+
+// convert the ansi escape codes used for colours into css
+fn ansi_to_css(value: &[u8]) -> String {
+    let mut css = String::new();
+    for captures in SGR_REGEX.captures_iter(value) {
+        let params: Vec<u8> = captures[1].split_str(";").filter_map(crate::utils::try_parse).collect();
+        let mut params = params.into_iter();
+        while let Some(p) = params.next() {
+            let (property, rgb) = match p {
+                1 => { css.push_str("font-weight: bold; "); continue },
+                3 => { css.push_str("font-style: italic; "); continue },
+                4 => { css.push_str("text-decoration: underline; "); continue },
+                30 ..= 37 => ("color", xterm_colour(p - 30)),
+                40 ..= 47 => ("background-color", xterm_colour(p - 40)),
+                90 ..= 97 => ("color", xterm_colour(p - 90 + 8)),
+                100 ..= 107 => ("background-color", xterm_colour(p - 100 + 8)),
+                38 | 48 => {
+                    let property = if p == 38 { "color" } else { "background-color" };
+                    match params.next() {
+                        Some(5) => if let Some(i) = params.next() {
+                            (property, xterm_colour(i))
+                        } else {
+                            break
+                        },
+                        Some(2) => if let (Some(r), Some(g), Some(b)) = (params.next(), params.next(), params.next()) {
+                            (property, (r, g, b))
+                        } else {
+                            break
+                        },
+                        _ => break,
+                    }
+                },
+                _ => continue,
+            };
+            css.push_str(&format!("{property}: rgb({}, {}, {}); ", rgb.0, rgb.1, rgb.2));
+        }
+    }
+    css.truncate(css.trim_end().len());
+    css
+}
+
+fn is_numeric(value: &BStr) -> bool {
+    let value = value.trim();
+    let value = value.strip_suffix(b"%").unwrap_or(value);
+    crate::utils::try_parse::<f64, _>(value).is_some()
+}
+
+pub struct HtmlWriter {
+    inner: BaseWriter,
+    opts: Opts,
+    started: bool,
+    in_table: bool,
+    in_body: bool,
+}
+
+impl HtmlWriter {
+    fn write_line(&mut self, state: &mut WriterState, opts: &base::BaseOptions, line: &str) -> Result<()> {
+        self.write_raw(state, line.into(), true, opts, false, false)
+    }
+
+    fn start_table(&mut self, state: &mut WriterState, opts: &base::BaseOptions) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            if self.opts.standalone {
+                self.write_line(state, opts, HTML_HEAD)?;
+            }
+        }
+        if !self.in_table {
+            self.in_table = true;
+            self.write_line(state, opts, "<table>")?;
+        }
+        Ok(())
+    }
+
+    fn end_table(&mut self, state: &mut WriterState, opts: &base::BaseOptions) -> Result<()> {
+        if self.in_body {
+            self.in_body = false;
+            self.write_line(state, opts, "</tbody>")?;
+        }
+        if self.in_table {
+            self.in_table = false;
+            self.write_line(state, opts, "</table>")?;
+        }
+        Ok(())
+    }
+
+    fn format_html_row(&self, row: Vec<BString>, is_header: bool, opts: &base::BaseOptions) -> BString {
+        let tag = if is_header { "th" } else { "td" };
+        let header_style = if is_header && self.opts.style {
+            let header_colour = opts.header_colour.as_deref().unwrap_or("\x1b[1;4m");
+            let header_bg_colour = opts.header_bg_colour.as_deref().unwrap_or("");
+            ansi_to_css(format!("{header_colour}{header_bg_colour}").as_bytes())
+        } else {
+            String::new()
+        };
+
+        // like the pretty output, rainbow columns by default only if there is colour
+        let rainbow = self.opts.style && !is_header && opts.inner.rainbow_columns.is_on_if(|| opts.inner.colour.is_on(opts.inner.is_stdout_tty));
+
+        let mut output = BString::new(b"<tr>".into());
+        for (i, col) in row.into_iter().enumerate() {
+            let mut style = header_style.clone();
+            if rainbow {
+                let (r, g, b) = get_rgb_values(i, None, None);
+                style.push_str(&format!("color: rgb({r}, {g}, {b});"));
+            }
+            if self.opts.align_numbers && !is_header && is_numeric(col.as_ref()) {
+                if !style.is_empty() {
+                    style.push(' ');
+                }
+                style.push_str("text-align: right;");
+            }
+
+            output.push(b'<');
+            output.push_str(tag);
+            if !style.is_empty() {
+                output.push_str(format!(" style=\"{style}\""));
+            }
+            output.push(b'>');
+            output.push_str(escape(col.as_ref()));
+            output.push_str("</");
+            output.push_str(tag);
+            output.push(b'>');
+        }
+        output.push_str("</tr>");
+        output
+    }
+}
+
+impl Writer for HtmlWriter {
+    fn new(opts: &base::BaseOptions) -> Self {
+        Self {
+            inner: BaseWriter::new(opts),
+            opts: Opts::default(),
+            started: false,
+            in_table: false,
+            in_body: false,
+        }
+    }
+
+    fn get_file(&mut self, opts: &base::BaseOptions, has_header: bool) -> Box<dyn Write> {
+        self.inner.get_file(opts, has_header)
+    }
+
+    fn format_columns(row: Vec<BString>, _ofs: &base::Ofs, _ors: &BStr, _opts: &base::BaseOptions) -> base::FormattedRow {
+        // escaping is done when writing out the row
+        base::FormattedRow(row)
+    }
+
+    fn write_output(
+        &mut self,
+        state: &mut WriterState,
+        row: Vec<BString>,
+        _padding: Option<&Vec<usize>>,
+        is_header: bool,
+        opts: &base::BaseOptions,
+        _ofs: &base::Ofs,
+    ) -> Result<()> {
+        self.start_table(state, opts)?;
+        if !is_header && !self.in_body {
+            self.in_body = true;
+            self.write_line(state, opts, "<tbody>")?;
+        }
+        let output = self.format_html_row(row, is_header, opts);
+        self.write_raw(state, output, true, opts, is_header, opts.inner.is_stdout_tty)
+    }
+
+    fn write_header(
+        &mut self,
+        state: &mut WriterState,
+        header: base::FormattedRow,
+        padding: Option<&Vec<usize>>,
+        opts: &base::BaseOptions,
+        ofs: &base::Ofs,
+    ) -> Result<()> {
+        if !opts.drop_header {
+            // a header in the middle of a table starts a new table
+            if self.in_body {
+                self.end_table(state, opts)?;
+            }
+            self.start_table(state, opts)?;
+            self.write_line(state, opts, "<thead>")?;
+            self.write_output(state, header.0, padding, true, opts, ofs)?;
+            self.write_line(state, opts, "</thead>")?;
+        }
+        Ok(())
+    }
+
+    fn write_separator(
+        &mut self,
+        state: &mut WriterState,
+        _padding: Option<&Vec<usize>>,
+        opts: &base::BaseOptions,
+    ) -> Result<()> {
+        self.end_table(state, opts)
+    }
+
+    fn write_eof(
+        &mut self,
+        state: &mut WriterState,
+        opts: &base::BaseOptions,
+    ) -> Result<()> {
+        self.end_table(state, opts)?;
+        if self.opts.standalone {
+            if !self.started {
+                self.write_line(state, opts, HTML_HEAD)?;
+            }
+            self.write_line(state, opts, "</body>\n</html>")?;
+        }
+        Ok(())
+    }
+
+}
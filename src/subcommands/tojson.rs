@@ -2,7 +2,7 @@ use anyhow::Result;
 use crate::base;
 use crate::writer::{Writer, BaseWriter, WriterState};
 use std::io::Write;
-use bstr::{BString};
+use bstr::{BStr, BString};
 use clap::Parser;
 use serde_json;
 
@@ -42,6 +42,11 @@ impl Writer for JsonWriter {
         self.inner.get_file(opts, has_header)
     }
 
+    // no quoting or --null-string, that is for delimited output
+    fn format_columns(row: Vec<BString>, _ofs: &base::Ofs, _ors: &BStr, _opts: &base::BaseOptions) -> base::FormattedRow {
+        base::FormattedRow(row)
+    }
+
     fn write_output(
         &mut self,
         state: &mut WriterState,
@@ -53,7 +58,11 @@ impl Writer for JsonWriter {
     ) -> Result<()> {
         // default to numbered keys if header names run out
         let keys = self.header.iter().cloned().chain((self.header.len()..).map(|i| i.to_string()));
-        let values = row.iter().map(|r| r.to_string().into());
+        let values = row.iter().map(|r| if r.is_empty() && opts.null_string.is_some() {
+            serde_json::Value::Null
+        } else {
+            r.to_string().into()
+        });
 
         let output = keys.zip(values).collect();
         let output = serde_json::Value::Object(output);
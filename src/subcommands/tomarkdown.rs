@@ -1,64 +1,217 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use crate::base;
 use crate::writer::{Writer, BaseWriter, WriterState};
 use once_cell::sync::Lazy;
 use regex::bytes::Regex;
-use bstr::{BString, BStr, ByteSlice};
+use bstr::{BString, BStr, ByteSlice, ByteVec};
+use crate::column_slicer::ColumnSlicer;
+use super::summary::is_numeric;
 use std::process::{Command};
 use std::io::Write;
 use clap::Parser;
 
 static NEEDS_ESCAPE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[`|\\]").unwrap());
 
+#[derive(Copy, Clone, PartialEq, Debug, clap::ValueEnum)]
+enum Align {
+    Auto,
+    Left,
+    Right,
+    Center,
+}
+
+impl Align {
+    fn parse(value: &str) -> Result<Self> {
+        <Self as clap::ValueEnum>::from_str(value, true).map_err(|_| anyhow::anyhow!("invalid alignment: {value}"))
+    }
+}
+
 #[derive(Parser)]
 #[command(about = "convert to markdown table")]
 pub struct Opts {
+    #[arg(short = 'a', long, value_delimiter = ',', value_name = "auto|left|right|center|FIELD:ALIGN", help = "align columns, auto right aligns numbers, sizes and percentages")]
+    align: Vec<String>,
+    #[arg(short = 'c', long, help = "do not pad columns to the same width")]
+    compact: bool,
+    #[arg(short = 'w', long, help = "truncate cells longer than this with an ellipsis")]
+    max_col_width: Option<usize>,
 }
 
 pub struct Handler {
     got_header: bool,
     drop_header: bool,
+    compact: bool,
+    max_col_width: Option<usize>,
+    default_align: Option<Align>,
+    field_align: Vec<(String, Align)>,
+    // alignment from upstream e.g. frommarkdown
+    alignment: Vec<Option<base::Alignment>>,
+    column_slicer: ColumnSlicer,
+    // whether each column has only numeric values so far
+    numeric: Vec<Option<bool>>,
 }
 
 impl Handler {
-    pub fn new(_opts: Opts, base: &mut base::Base) -> Result<Self> {
+    pub fn new(opts: Opts, base: &mut base::Base) -> Result<Self> {
         base.opts.header_colour.get_or_insert_with(|| "\x1b[1m".into());
         base.opts.inner.trailer = base::AutoChoices::Never;
         base.opts.inner.numbered_columns = base::AutoChoices::Never;
         let drop_header = base.opts.drop_header;
         base.opts.drop_header = false;
-        base.opts.pretty = true;
+        if opts.compact {
+            // write rows as they come in
+            base.opts.pretty = false;
+            base.opts.ofs = Some("|".into());
+        } else {
+            base.opts.pretty = true;
+        }
+
+        let mut default_align = None;
+        let mut field_align = vec![];
+        for spec in &opts.align {
+            if let Some((field, align)) = spec.rsplit_once(':') {
+                field_align.push((field.to_owned(), Align::parse(align)?));
+            } else {
+                default_align = Some(Align::parse(spec)?);
+            }
+        }
 
         Ok(Self {
             drop_header,
             got_header: false,
+            compact: opts.compact,
+            max_col_width: opts.max_col_width,
+            default_align,
+            field_align,
+            alignment: vec![],
+            column_slicer: ColumnSlicer::new(&[], false)?,
+            numeric: vec![],
         })
     }
+
+    fn truncate(&self, row: &mut [BString]) {
+        if let Some(width) = self.max_col_width {
+            for col in row {
+                if let Some((i, _, _)) = col.char_indices().nth(width) {
+                    // make room for the ellipsis
+                    let end = col.char_indices().nth(width.saturating_sub(1)).map_or(i, |c| c.0);
+                    col.truncate(end);
+                    col.push_str("…");
+                }
+            }
+        }
+    }
+
+    fn resolve_alignment(&self, width: usize) -> Vec<Option<base::Alignment>> {
+        let to_alignment = |align: Align, i: usize| match align {
+            Align::Left => Some(base::Alignment::Left),
+            Align::Right => Some(base::Alignment::Right),
+            Align::Center => Some(base::Alignment::Center),
+            Align::Auto => if self.numeric.get(i).copied().flatten().unwrap_or(false) {
+                Some(base::Alignment::Right)
+            } else {
+                self.alignment.get(i).copied().flatten()
+            },
+        };
+
+        let mut alignment: Vec<_> = (0 .. width)
+            .map(|i| self.default_align.map_or_else(|| self.alignment.get(i).copied().flatten(), |a| to_alignment(a, i)))
+            .collect();
+        for (field, align) in &self.field_align {
+            if let Some(i) = self.column_slicer.get_single_field_index(field) && i < width {
+                alignment[i] = to_alignment(*align, i);
+            }
+        }
+        alignment
+    }
 }
 
 impl base::Processor<MarkdownWriter> for Handler {
 
     fn on_header(&mut self, base: &mut base::Base, mut header: Vec<BString>) -> Result<()> {
         self.got_header = true;
+        self.column_slicer.make_header_map(&header, base.opts.lenient_fields)?;
+        if !base.opts.lenient_fields {
+            for (field, _) in &self.field_align {
+                if self.column_slicer.get_single_field_index(field).is_none() {
+                    bail!("{}", crate::column_slicer::unknown_field(&header, field.as_bytes()));
+                }
+            }
+        }
         if self.drop_header {
             for h in &mut header {
                 h.clear();
             }
         }
+        self.truncate(&mut header);
+        if self.numeric.len() < header.len() {
+            self.numeric.resize(header.len(), None);
+        }
+        // compact output writes the header straight away, so we cannot know which columns are numeric
+        base.on_alignment(self.resolve_alignment(header.len()))?;
         base.on_header(header)
     }
 
-    fn on_row(&mut self, base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+    fn on_row(&mut self, base: &mut base::Base, mut row: Vec<BString>) -> Result<()> {
         if !self.got_header {
             self.on_header(base, (0..row.len()).map(|_| b"".into()).collect())?;
         }
+        if self.numeric.len() < row.len() {
+            self.numeric.resize(row.len(), None);
+        }
+        for (numeric, col) in self.numeric.iter_mut().zip(&row) {
+            if !col.is_empty() {
+                *numeric = Some(numeric.unwrap_or(true) && is_numeric(col.as_ref()));
+            }
+        }
+        self.truncate(&mut row);
         base.on_row(row)
     }
+
+    fn on_alignment(&mut self, _base: &mut base::Base, alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        self.alignment = alignment;
+        Ok(())
+    }
+
+    fn on_eof(self, base: &mut base::Base) -> Result<bool> {
+        if !self.compact {
+            // the header is not written until the end, so it can still use the final alignment
+            base.on_alignment(self.resolve_alignment(self.numeric.len()))?;
+        }
+        base.on_eof()
+    }
 }
 
 pub struct MarkdownWriter {
     inner: BaseWriter,
     ofs: base::Ofs,
+    alignment: Vec<Option<base::Alignment>>,
+}
+
+// the separator under the header with the colons for the alignment
+fn make_separator(width: usize, alignment: Option<base::Alignment>) -> BString {
+    let left = matches!(alignment, Some(base::Alignment::Left | base::Alignment::Center));
+    let right = matches!(alignment, Some(base::Alignment::Right | base::Alignment::Center));
+    let colons = left as usize + right as usize;
+    // keep a space either side if there is room for at least one dash
+    let spaced = width >= colons + 3;
+    let width = width.max(colons + 1);
+
+    let mut sep: BString = b"-".repeatn(width).into();
+    let (start, end) = if spaced {
+        sep[0] = b' ';
+        sep[width - 1] = b' ';
+        (1, width - 2)
+    } else {
+        (0, width - 1)
+    };
+    if left {
+        sep[start] = b':';
+    }
+    if right {
+        sep[end] = b':';
+    }
+    sep
 }
 
 impl Writer for MarkdownWriter {
@@ -66,9 +219,14 @@ impl Writer for MarkdownWriter {
         Self {
             inner: BaseWriter::new(opts),
             ofs: base::Ofs::Plain(b"|".into()),
+            alignment: vec![],
         }
     }
 
+    fn set_alignment(&mut self, alignment: Vec<Option<base::Alignment>>) {
+        self.alignment = alignment;
+    }
+
     fn get_file(&mut self, opts: &base::BaseOptions, has_header: bool) -> Box<dyn Write> {
         if opts.page {
             let mut command = Command::new("less");
@@ -79,8 +237,8 @@ impl Writer for MarkdownWriter {
         }
     }
 
-    fn format_columns(mut row: Vec<BString>, _ofs: &base::Ofs, _ors: &BStr, quote_output: bool) -> base::FormattedRow {
-        if quote_output {
+    fn format_columns(mut row: Vec<BString>, _ofs: &base::Ofs, _ors: &BStr, opts: &base::BaseOptions) -> base::FormattedRow {
+        if opts.quote_output {
             for col in &mut row {
                 // TODO what about newlines
                 if let std::borrow::Cow::Owned(new) = NEEDS_ESCAPE.replace_all(col, b"\\$0") {
@@ -123,25 +281,25 @@ impl Writer for MarkdownWriter {
         ofs: &base::Ofs,
     ) -> Result<()> {
         // write the separator
+        // the header has an empty column on either side for the fences
+        let alignment = |i: usize| i.checked_sub(1).and_then(|i| self.alignment.get(i).copied().flatten());
+        let last = header.0.len().saturating_sub(1);
         let sep: Vec<_> = if let Some(padding) = padding {
             padding.iter().chain(std::iter::repeat(&0))
                 .zip(&header.0)
-                .map(|(p, h)| {
-                    let mut sep: BString = b"-".repeatn(p + base::no_ansi_colour_len(h.as_ref())).into();
-                    if let Some(c) = sep.get_mut(0) {
-                        *c = b' ';
-                    }
-                    if let Some(c) = sep.last_mut() {
-                        *c = b' ';
+                .enumerate()
+                .map(|(i, (p, h))| {
+                    let width = p + base::no_ansi_colour_len(h.as_ref());
+                    if i == 0 || i == last {
+                        b" ".repeatn(width).into()
+                    } else {
+                        make_separator(width, alignment(i))
                     }
-                    sep
                 })
                 .collect()
         } else {
-            [b"" as &[u8]].into_iter()
-                .chain((0..header.0.len()).skip(2).map(|_| b"---" as _))
-                .chain([b"" as _])
-                .map(|x| x.into())
+            (0..header.0.len())
+                .map(|i| if i == 0 || i == last { b"".into() } else { make_separator(3, alignment(i)) })
                 .collect()
         };
         self.write_output(state, header.0, padding, true, opts, ofs)?;
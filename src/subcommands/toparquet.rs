@@ -0,0 +1,248 @@
+use anyhow::{Result, Context};
+use crate::base;
+use std::fs::File;
+use std::sync::Arc;
+use bstr::{BString, ByteSlice};
+use clap::Parser;
+use arrow::array::{ArrayRef, Int64Array, Float64Array, BooleanArray, StringArray, TimestampMicrosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+
+const TIMESTAMP_FORMATS: [&str; 4] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.fZ", "%Y-%m-%d %H:%M"];
+
+#[derive(Parser)]
+#[command(about = "convert to parquet")]
+pub struct Opts {
+    #[arg(help = "parquet file to write")]
+    file: String,
+    #[arg(long, default_value_t = 1024, help = "number of rows to infer the schema from, also used as the batch size")]
+    sample: usize,
+}
+
+pub struct Handler {
+    path: String,
+    file: Option<File>,
+    writer: Option<ArrowWriter<File>>,
+    batcher: Batcher,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
+        let file = File::create(&opts.file).with_context(|| format!("failed to create {}", opts.file))?;
+        Ok(Self {
+            path: opts.file,
+            file: Some(file),
+            writer: None,
+            batcher: Batcher::new(opts.sample),
+        })
+    }
+
+    fn write(&mut self, batch: RecordBatch) -> Result<()> {
+        if self.writer.is_none() {
+            let file = self.file.take().unwrap();
+            self.writer = Some(ArrowWriter::try_new(file, batch.schema(), None)?);
+        }
+        self.writer.as_mut().unwrap().write(&batch)?;
+        Ok(())
+    }
+
+    // a parquet file without its footer can't be read, so don't leave one behind
+    fn remove_on_error<T>(&mut self, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.writer = None;
+            self.file = None;
+            let _ = std::fs::remove_file(&self.path);
+        }
+        result
+    }
+}
+
+impl base::Processor for Handler {
+    fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+        self.batcher.header = Some(header);
+        Ok(())
+    }
+
+    fn on_row(&mut self, _base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+        let result = self.batcher.push(row).and_then(|batch| match batch {
+            Some(batch) => self.write(batch),
+            None => Ok(()),
+        });
+        self.remove_on_error(result)
+    }
+
+    fn on_eof(mut self, base: &mut base::Base) -> Result<bool> {
+        let result = self.batcher.finish().and_then(|batch| {
+            if let Some(batch) = batch {
+                self.write(batch)?;
+            }
+            if let Some(writer) = self.writer.take() {
+                writer.close()?;
+            }
+            Ok(())
+        });
+        self.remove_on_error(result)?;
+        base.on_eof()
+    }
+}
+
+// keep things like zip codes as strings
+fn has_leading_zero(value: &[u8]) -> bool {
+    let digits = value.strip_prefix(b"-").unwrap_or(value);
+    digits.len() > 1 && digits[0] == b'0' && digits[1] != b'.'
+}
+
+fn parse_int(value: &[u8]) -> Option<i64> {
+    if has_leading_zero(value) {
+        return None
+    }
+    crate::utils::try_parse(value)
+}
+
+fn parse_float(value: &[u8]) -> Option<f64> {
+    if has_leading_zero(value) || !value.first().is_some_and(|c| matches!(c, b'-' | b'.' | b'0' ..= b'9')) {
+        return None
+    }
+    crate::utils::try_parse(value)
+}
+
+fn parse_bool(value: &[u8]) -> Option<bool> {
+    if value.eq_ignore_ascii_case(b"true") {
+        Some(true)
+    } else if value.eq_ignore_ascii_case(b"false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn parse_timestamp(value: &[u8]) -> Option<i64> {
+    let value = value.to_str().ok()?;
+    let datetime = TIMESTAMP_FORMATS.iter()
+        .find_map(|f| chrono::NaiveDateTime::parse_from_str(value, f).ok())
+        .or_else(|| chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))?;
+    Some(datetime.and_utc().timestamp_micros())
+}
+
+fn infer_type(values: &[Option<&BString>]) -> DataType {
+    let mut values = values.iter().flatten().peekable();
+    if values.peek().is_none() {
+        return DataType::Utf8
+    }
+    let candidates = [
+        DataType::Int64,
+        DataType::Float64,
+        DataType::Timestamp(TimeUnit::Microsecond, None),
+        DataType::Boolean,
+    ];
+    candidates.into_iter()
+        .find(|t| values.clone().all(|v| parse_value(t, v)))
+        .unwrap_or(DataType::Utf8)
+}
+
+fn parse_value(typ: &DataType, value: &[u8]) -> bool {
+    match typ {
+        DataType::Int64 => parse_int(value).is_some(),
+        DataType::Float64 => parse_float(value).is_some(),
+        DataType::Timestamp(..) => parse_timestamp(value).is_some(),
+        DataType::Boolean => parse_bool(value).is_some(),
+        _ => true,
+    }
+}
+
+// gathers rows into record batches, inferring the schema from the first batch
+pub struct Batcher {
+    pub header: Option<Vec<BString>>,
+    batch_size: usize,
+    rows: Vec<Vec<BString>>,
+    schema: Option<SchemaRef>,
+    row_num: usize,
+}
+
+impl Batcher {
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            header: None,
+            batch_size: batch_size.max(1),
+            rows: vec![],
+            schema: None,
+            row_num: 0,
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<BString>) -> Result<Option<RecordBatch>> {
+        self.rows.push(row);
+        if self.rows.len() >= self.batch_size {
+            self.make_batch().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    // there is always at least one batch, even for empty input, so the schema gets written
+    pub fn finish(&mut self) -> Result<Option<RecordBatch>> {
+        if self.rows.is_empty() && self.schema.is_some() {
+            return Ok(None)
+        }
+        self.make_batch().map(Some)
+    }
+
+    fn infer_schema(&self) -> SchemaRef {
+        let width = self.rows.iter().map(|r| r.len()).max().unwrap_or(0).max(self.header.as_ref().map_or(0, |h| h.len()));
+        let fields: Vec<_> = (0 .. width).map(|i| {
+            let name = self.header.as_ref()
+                .and_then(|h| h.get(i))
+                .map_or_else(|| format!("col{i}"), |h| h.to_str_lossy().into_owned());
+            let values: Vec<_> = self.rows.iter().map(|r| r.get(i).filter(|v| !v.is_empty())).collect();
+            Field::new(name, infer_type(&values), true)
+        }).collect();
+        Arc::new(Schema::new(fields))
+    }
+
+    fn make_batch(&mut self) -> Result<RecordBatch> {
+        if self.schema.is_none() {
+            self.schema = Some(self.infer_schema());
+        }
+        let schema = self.schema.clone().unwrap();
+        let rows = std::mem::take(&mut self.rows);
+        if rows.is_empty() {
+            return Ok(RecordBatch::new_empty(schema))
+        }
+        let width = schema.fields().len();
+        if let Some(i) = rows.iter().position(|r| r.len() > width) {
+            anyhow::bail!(
+                "row {}: {} fields but the schema has {width} (try a larger --sample)",
+                self.row_num + i + 1, rows[i].len(),
+            );
+        }
+        let columns = schema.fields().iter().enumerate().map(|(i, field)| {
+            let values: Vec<_> = rows.iter().map(|r| r.get(i).filter(|v| !v.is_empty())).collect();
+            self.make_array(field, &values)
+        }).collect::<Result<Vec<_>>>()?;
+        self.row_num += rows.len();
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+
+    fn make_array(&self, field: &Field, values: &[Option<&BString>]) -> Result<ArrayRef> {
+        // the schema is fixed after the first batch, so later values may not fit
+        for (i, value) in values.iter().enumerate() {
+            if let Some(value) = value && !parse_value(field.data_type(), value) {
+                anyhow::bail!(
+                    "row {}, column {}: cannot convert {:?} to {} (try a larger --sample)",
+                    self.row_num + i + 1, field.name(), value, field.data_type(),
+                );
+            }
+        }
+
+        let values = values.iter().map(|v| v.map(|v| v.as_slice()));
+        let array: ArrayRef = match field.data_type() {
+            DataType::Int64 => Arc::new(values.map(|v| v.and_then(parse_int)).collect::<Int64Array>()),
+            DataType::Float64 => Arc::new(values.map(|v| v.and_then(parse_float)).collect::<Float64Array>()),
+            DataType::Timestamp(..) => Arc::new(values.map(|v| v.and_then(parse_timestamp)).collect::<TimestampMicrosecondArray>()),
+            DataType::Boolean => Arc::new(values.map(|v| v.and_then(parse_bool)).collect::<BooleanArray>()),
+            _ => Arc::new(values.map(|v| v.map(|v| v.to_str_lossy().into_owned())).collect::<StringArray>()),
+        };
+        Ok(array)
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::{Result, Context, bail};
+use crate::base;
+use super::toxml::escape;
+use super::fromxlsx::column_name;
+use std::fs::File;
+use std::io::{Write, BufWriter};
+use bstr::{BString, ByteVec};
+use clap::Parser;
+use zip::{ZipWriter, CompressionMethod, write::SimpleFileOptions};
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#;
+const RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#;
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts><fills count="1"><fill><patternFill patternType="none"/></fill></fills><borders count="1"><border/></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="2"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/></cellXfs></styleSheet>"#;
+const SHEET_HEAD: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#;
+const SHEET_TAIL: &str = "</sheetData></worksheet>";
+
+#[derive(Parser)]
+#[command(about = "convert to xlsx spreadsheet")]
+pub struct Opts {
+    #[arg(help = "xlsx file to write")]
+    file: String,
+    #[arg(short = 's', long, default_value = "Sheet1", help = "name of the sheet")]
+    sheet: String,
+}
+
+pub struct Handler {
+    sheet: String,
+    // boxed as the zip writer is quite large
+    zip: Box<ZipWriter<BufWriter<File>>>,
+    row_num: usize,
+}
+
+// excel refuses to open the file otherwise
+fn check_sheet_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.chars().count() > 31 {
+        bail!("sheet name must be 1 to 31 characters: {name:?}");
+    }
+    if let Some(c) = name.chars().find(|c| matches!(c, '[' | ']' | ':' | '*' | '?' | '/' | '\\')) {
+        bail!("sheet name can't contain {c:?}: {name:?}");
+    }
+    if name.starts_with('\'') || name.ends_with('\'') {
+        bail!("sheet name can't start or end with a quote: {name:?}");
+    }
+    Ok(())
+}
+
+impl Handler {
+    pub fn new(opts: Opts, _base: &mut base::Base) -> Result<Self> {
+        check_sheet_name(&opts.sheet)?;
+        let file = File::create(&opts.file).with_context(|| format!("failed to create {}", opts.file))?;
+        let mut zip = Box::new(ZipWriter::new(BufWriter::new(file)));
+        zip.start_file("xl/worksheets/sheet1.xml", Self::file_options())?;
+        zip.write_all(SHEET_HEAD.as_bytes())?;
+        Ok(Self {
+            sheet: opts.sheet,
+            zip,
+            row_num: 0,
+        })
+    }
+
+    fn file_options() -> SimpleFileOptions {
+        SimpleFileOptions::default().compression_method(CompressionMethod::Deflated)
+    }
+
+    fn is_number(value: &[u8]) -> bool {
+        // keep things like zip codes and phone numbers as text
+        let digits = value.strip_prefix(b"-").unwrap_or(value);
+        if digits.len() > 1 && digits[0] == b'0' && digits[1] != b'.' {
+            return false
+        }
+        value.first().is_some_and(|c| matches!(c, b'-' | b'.' | b'0' ..= b'9'))
+            && crate::utils::try_parse::<f64, _>(value).is_some_and(|x| x.is_finite())
+    }
+
+    fn write_row(&mut self, row: Vec<BString>, is_header: bool) -> Result<()> {
+        self.row_num += 1;
+        let style = if is_header { r#" s="1""# } else { "" };
+        let mut output = BString::new(vec![]);
+        output.push_str(format!(r#"<row r="{}">"#, self.row_num));
+        for (i, value) in row.into_iter().enumerate() {
+            if value.is_empty() {
+                continue
+            }
+            let cell = format!("{}{}", column_name(i), self.row_num);
+            if !is_header && Self::is_number(&value) {
+                output.push_str(format!(r#"<c r="{cell}"{style}><v>{value}</v></c>"#));
+            } else {
+                // drop control characters that are not allowed in xml
+                let value: BString = value.iter().copied().filter(|&c| c >= b' ' || matches!(c, b'\t' | b'\n' | b'\r')).collect::<Vec<_>>().into();
+                output.push_str(format!(r#"<c r="{cell}" t="inlineStr"{style}><is><t xml:space="preserve">"#));
+                output.push_str(escape(value.as_ref()));
+                output.push_str("</t></is></c>");
+            }
+        }
+        output.push_str("</row>");
+        self.zip.write_all(&output)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.zip.write_all(SHEET_TAIL.as_bytes())?;
+
+        let sheet = escape(self.sheet.as_bytes().into());
+        let workbook = format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="{sheet}" sheetId="1" r:id="rId1"/></sheets></workbook>"#
+        );
+        for (name, contents) in [
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("_rels/.rels", RELS),
+            ("xl/workbook.xml", &workbook),
+            ("xl/_rels/workbook.xml.rels", WORKBOOK_RELS),
+            ("xl/styles.xml", STYLES),
+        ] {
+            self.zip.start_file(name, Self::file_options())?;
+            self.zip.write_all(contents.as_bytes())?;
+        }
+        self.zip.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+impl base::Processor for Handler {
+    fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+        self.write_row(header, true)
+    }
+
+    fn on_row(&mut self, _base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+        self.write_row(row, false)
+    }
+
+    fn on_eof(self, base: &mut base::Base) -> Result<bool> {
+        self.finish()?;
+        base.on_eof()
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::Result;
+use crate::base;
+use crate::writer::{Writer, BaseWriter, WriterState};
+use std::io::Write;
+use std::collections::HashMap;
+use bstr::{BString, BStr, ByteVec};
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(about = "convert to xml")]
+pub struct Opts {
+    #[arg(long, default_value = "rows", help = "name of the root element")]
+    root: String,
+    #[arg(long, default_value = "row", help = "name of the element for each row")]
+    record: String,
+}
+
+pub struct Handler {
+    root: BString,
+    record: BString,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, base: &mut base::Base) -> Result<Self> {
+        base.opts.inner.trailer = base::AutoChoices::Never;
+        base.opts.inner.numbered_columns = base::AutoChoices::Never;
+        base.opts.drop_header = false;
+        Ok(Self {
+            root: sanitise_name(opts.root.as_bytes().into()),
+            record: sanitise_name(opts.record.as_bytes().into()),
+        })
+    }
+}
+
+impl base::Processor<XmlWriter> for Handler {
+    fn make_writer(&self, opts: base::BaseOptions) -> base::Output<XmlWriter> {
+        let mut writer = XmlWriter::new(&opts);
+        writer.root = self.root.clone();
+        writer.record = self.record.clone();
+        base::Output::with_writer(opts, writer)
+    }
+}
+
+pub fn escape(value: &BStr) -> BString {
+    let mut escaped = BString::new(Vec::with_capacity(value.len()));
+    for &c in value.iter() {
+        match c {
+            b'&' => escaped.push_str("&amp;"),
+            b'<' => escaped.push_str("&lt;"),
+            b'>' => escaped.push_str("&gt;"),
+            b'"' => escaped.push_str("&quot;"),
+            b'\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn sanitise_name(name: &BStr) -> BString {
+    let mut name: BString = name.iter()
+        .map(|&c| if matches!(c, b'_' | b'-' | b'.' | b'a' ..= b'z' | b'A' ..= b'Z' | b'0' ..= b'9' | 0x80 ..) { c } else { b'_' })
+        .collect::<Vec<_>>()
+        .into();
+    // names must start with a letter or underscore and must not start with xml
+    if !matches!(name.first(), Some(b'_' | b'a' ..= b'z' | b'A' ..= b'Z' | 0x80 ..)) || name.get(..3).is_some_and(|x| x.eq_ignore_ascii_case(b"xml")) {
+        name.insert(0, b'_');
+    }
+    name
+}
+
+// names that only clash once sanitised, like `a b` and `a_b`, get a suffix to tell them apart
+fn sanitise_header(header: &[BString]) -> Vec<BString> {
+    let mut seen: HashMap<BString, &BString> = HashMap::new();
+    header.iter().map(|original| {
+        let name = sanitise_name(original.as_ref());
+        let mut unique = name.clone();
+        let mut i = 1;
+        while seen.get(&unique).is_some_and(|&o| o != original) {
+            i += 1;
+            unique = format!("{name}_{i}").into();
+        }
+        seen.insert(unique.clone(), original);
+        unique
+    }).collect()
+}
+
+pub struct XmlWriter {
+    inner: BaseWriter,
+    root: BString,
+    record: BString,
+    header: Vec<BString>,
+    started: bool,
+}
+
+impl XmlWriter {
+    fn start(&mut self, state: &mut WriterState, opts: &base::BaseOptions) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            self.write_raw(state, br#"<?xml version="1.0" encoding="UTF-8"?>"#.into(), true, opts, false, false)?;
+            let root = bstr::concat([b"<", self.root.as_slice(), b">"]).into();
+            self.write_raw(state, root, true, opts, false, false)?;
+        }
+        Ok(())
+    }
+}
+
+impl Writer for XmlWriter {
+    fn new(opts: &base::BaseOptions) -> Self {
+        Self {
+            inner: BaseWriter::new(opts),
+            root: b"rows".into(),
+            record: b"row".into(),
+            header: vec![],
+            started: false,
+        }
+    }
+
+    fn get_file(&mut self, opts: &base::BaseOptions, has_header: bool) -> Box<dyn Write> {
+        self.inner.get_file(opts, has_header)
+    }
+
+    fn format_columns(row: Vec<BString>, _ofs: &base::Ofs, _ors: &BStr, _opts: &base::BaseOptions) -> base::FormattedRow {
+        // escaping is done when writing out the row
+        base::FormattedRow(row)
+    }
+
+    fn write_output(
+        &mut self,
+        state: &mut WriterState,
+        row: Vec<BString>,
+        _padding: Option<&Vec<usize>>,
+        _is_header: bool,
+        opts: &base::BaseOptions,
+        _ofs: &base::Ofs,
+    ) -> Result<()> {
+        self.start(state, opts)?;
+
+        // default to numbered names if header names run out
+        let names = self.header.iter().cloned().chain((self.header.len()..).map(|i| format!("col{i}").into()));
+        let mut output = BString::new(vec![]);
+        output.push_str("  <");
+        output.push_str(&self.record);
+        output.push(b'>');
+        for (name, value) in names.zip(row) {
+            output.push(b'<');
+            output.push_str(&name);
+            output.push(b'>');
+            output.push_str(escape(value.as_ref()));
+            output.push_str("</");
+            output.push_str(&name);
+            output.push(b'>');
+        }
+        output.push_str("</");
+        output.push_str(&self.record);
+        output.push(b'>');
+        self.write_raw(state, output, true, opts, false, opts.inner.is_stdout_tty)
+    }
+
+    fn write_header(
+        &mut self,
+        _state: &mut WriterState,
+        header: base::FormattedRow,
+        _padding: Option<&Vec<usize>>,
+        _opts: &base::BaseOptions,
+        _ofs: &base::Ofs,
+    ) -> Result<()> {
+        self.header = sanitise_header(&header.0);
+        Ok(())
+    }
+
+    fn write_separator(
+        &mut self,
+        _state: &mut WriterState,
+        _padding: Option<&Vec<usize>>,
+        _opts: &base::BaseOptions,
+    ) -> Result<()> {
+        // there can only be one root element
+        Ok(())
+    }
+
+    fn write_eof(
+        &mut self,
+        state: &mut WriterState,
+        opts: &base::BaseOptions,
+    ) -> Result<()> {
+        self.start(state, opts)?;
+        let root = bstr::concat([b"</", self.root.as_slice(), b">"]).into();
+        self.write_raw(state, root, true, opts, false, false)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitised_names_stay_unique() {
+        let header: Vec<BString> = ["a b", "a_b", "a-b", "a b", "1", "xml"].into_iter().map(BString::from).collect();
+        assert_eq!(sanitise_header(&header), ["a_b", "a_b_2", "a-b", "a_b", "_1", "_xml"]);
+    }
+}
@@ -6,9 +6,9 @@ use crate::column_slicer::ColumnSlicer;
 use clap::{Parser};
 
 #[derive(Parser)]
-#[command(about = "omit repeated lines")]
+#[command(about = "omit repeated lines", after_help = crate::column_slicer::FIELDS_HELP)]
 pub struct Opts {
-    #[arg(help = "sort based only on these fields")]
+    #[arg(allow_negative_numbers = true, help = "sort based only on these fields")]
     fields: Vec<String>,
     #[arg(short = 'x', long, help = "exclude, rather than include, field names")]
     complement: bool,
@@ -48,15 +48,22 @@ impl Handler {
             gather,
             repeated: opts.repeated || opts.repeated_all,
             print_early: !gather && opts.count_column.is_none(),
-            column_slicer: ColumnSlicer::new(&opts.fields, opts.regex),
+            column_slicer: ColumnSlicer::new(&opts.fields, opts.regex)?,
             opts,
         })
     }
 }
 
 impl base::Processor for Handler {
+    fn on_alignment(&mut self, base: &mut base::Base, mut alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        if self.opts.count_column.is_some() {
+            alignment.insert(0, Some(base::Alignment::Right));
+        }
+        Ok(base.on_alignment(alignment)?)
+    }
+
     fn on_header(&mut self, base: &mut base::Base, mut header: Vec<BString>) -> Result<()> {
-        self.column_slicer.make_header_map(&header);
+        self.column_slicer.make_header_map(&header, base.opts.lenient_fields)?;
         if let Some(count_column) = &self.opts.count_column {
             header.insert(0, count_column.as_bytes().into());
         }
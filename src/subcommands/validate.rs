@@ -0,0 +1,362 @@
+use anyhow::{Result, Context, bail};
+use crate::base::{self, Processor, Callbacks};
+use regex::bytes::Regex;
+use bstr::{BString, BStr, ByteSlice};
+use clap::{Parser};
+use std::collections::{HashMap, HashSet};
+use serde_json::Value;
+
+pub const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%d/%m/%Y", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"];
+const SCHEMA_KEYS: &[&str] = &["name", "type", "format", "values", "regex", "nullable", "unique", "min", "max"];
+
+#[derive(Parser)]
+#[command(about = "check the rows against a schema")]
+pub struct Opts {
+    #[arg(help = "json schema, or a table with columns name, type, format, values, regex, nullable, unique, min, max")]
+    schema: String,
+    #[arg(long, help = "allow the columns in any order and allow other columns")]
+    any_order: bool,
+}
+
+// the types summary recognises, plus int, float and string
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Number,
+    Date,
+    Enum,
+    Percent,
+    Size,
+    String,
+}
+
+impl Type {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "int" | "integer" => Self::Int,
+            "float" => Self::Float,
+            "number" | "numeric" => Self::Number,
+            "date" => Self::Date,
+            "enum" => Self::Enum,
+            "percent" => Self::Percent,
+            "size" => Self::Size,
+            "string" | "text" | "" => Self::String,
+            _ => bail!("unknown type {name:?}, expected one of int, float, number, date, enum, percent, size, string"),
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Number => "number",
+            Self::Date => "date",
+            Self::Enum => "enum",
+            Self::Percent => "percent",
+            Self::Size => "size",
+            Self::String => "string",
+        }
+    }
+}
+
+struct Column {
+    name: BString,
+    typ: Type,
+    format: Option<String>,
+    values: Option<HashSet<BString>>,
+    regex: Option<Regex>,
+    nullable: bool,
+    unique: bool,
+    min: Option<(f64, String)>,
+    max: Option<(f64, String)>,
+}
+
+impl Column {
+    fn new(fields: HashMap<String, String>, values: Option<Vec<String>>) -> Result<Self> {
+        if let Some(key) = fields.keys().find(|k| !SCHEMA_KEYS.contains(&k.as_str())) {
+            bail!("unknown schema key {key:?}, expected one of {}", SCHEMA_KEYS.join(", "));
+        }
+        let Some(name) = fields.get("name") else { bail!("schema column has no name") };
+
+        let mut column = Self {
+            name: name.as_str().into(),
+            typ: Type::parse(fields.get("type").map_or("", |t| t.as_str())).with_context(|| format!("in schema for {name:?}"))?,
+            format: fields.get("format").filter(|f| !f.is_empty()).cloned(),
+            values: values.map(|v| v.into_iter().map(BString::from).collect()),
+            regex: fields.get("regex").filter(|r| !r.is_empty()).map(|r| Regex::new(r)).transpose()?,
+            // an empty cell in a table schema is the same as leaving it out
+            nullable: fields.get("nullable").filter(|v| !v.is_empty()).map_or(Ok(true), |v| parse_bool(v))?,
+            unique: fields.get("unique").map_or(Ok(false), |v| parse_bool(v))?,
+            min: None,
+            max: None,
+        };
+        if column.typ == Type::Enum && column.values.is_none() {
+            bail!("schema for {name:?} is an enum but has no values");
+        }
+
+        // the bounds are in the same units as the values
+        let [min, max] = ["min", "max"].map(|key| {
+            let Some(value) = fields.get(key).filter(|v| !v.is_empty()) else { return Ok(None) };
+            let parsed = if matches!(column.typ, Type::String | Type::Enum) {
+                value.parse().ok()
+            } else {
+                column.parse(value.as_bytes().into())
+            };
+            let Some(parsed) = parsed else { bail!("invalid {key} {value:?} in schema for {name:?}") };
+            Ok(Some((parsed, value.clone())))
+        });
+        column.min = min?;
+        column.max = max?;
+        Ok(column)
+    }
+
+    fn parse(&self, value: &BStr) -> Option<f64> {
+        parse_value(self.typ, self.format.as_deref(), value)
+    }
+
+    fn type_name(&self) -> String {
+        match (self.typ, &self.format) {
+            (Type::Date, Some(format)) => format!("date in the format {format}"),
+            (Type::Int, _) => "integer".into(),
+            (typ, _) => typ.name().into(),
+        }
+    }
+}
+
+// the value as a number to compare against min and max, or None if it is not the right type
+pub fn parse_value(typ: Type, format: Option<&str>, value: &BStr) -> Option<f64> {
+    match typ {
+        Type::Int => crate::utils::try_parse::<i64, _>(value.trim()).map(|x| x as f64),
+        Type::Float | Type::Number => crate::utils::try_parse(value.trim()),
+        Type::Percent => crate::utils::try_parse(value.trim().strip_suffix(b"%")?),
+        Type::Size => super::summary::parse_size(value),
+        Type::Date => {
+            let value = value.to_str().ok()?.trim();
+            let parse = |format: &str| chrono::NaiveDateTime::parse_from_str(value, format)
+                .or_else(|_| chrono::NaiveDate::parse_from_str(value, format).map(|d| d.and_hms_opt(0, 0, 0).unwrap()))
+                .ok()
+                .map(|d| d.and_utc().timestamp() as f64);
+            if let Some(format) = format {
+                parse(format)
+            } else {
+                // summary only knows about date times
+                super::summary::parse_date(value).or_else(|| DATE_FORMATS.iter().find_map(|f| parse(f)))
+            }
+        },
+        // strings are compared by length
+        Type::String | Type::Enum => Some(value.chars().count() as f64),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "y" | "1" => Ok(true),
+        "false" | "no" | "n" | "0" | "" => Ok(false),
+        _ => bail!("expected true or false, not {value:?}"),
+    }
+}
+
+fn json_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn parse_json_schema(schema: &str) -> Result<(Vec<Column>, bool)> {
+    let schema: Value = serde_json::from_str(schema)?;
+    let (columns, any_order) = match &schema {
+        Value::Array(columns) => (columns, false),
+        Value::Object(obj) => (
+            obj.get("columns").and_then(|c| c.as_array()).context("schema has no columns")?,
+            obj.get("any_order").and_then(|x| x.as_bool()).unwrap_or(false),
+        ),
+        _ => bail!("schema must be a list of columns or an object with columns"),
+    };
+
+    let columns = columns.iter().map(|column| {
+        let Value::Object(column) = column else { bail!("schema columns must be objects") };
+        let fields = column.iter()
+            .filter(|(k, _)| *k != "values")
+            .map(|(k, v)| (k.clone(), json_to_string(v)))
+            .collect();
+        let values = column.get("values")
+            .map(|v| v.as_array().context("values must be a list").map(|v| v.iter().map(json_to_string).collect()))
+            .transpose()?;
+        Column::new(fields, values)
+    }).collect::<Result<_>>()?;
+    Ok((columns, any_order))
+}
+
+// reads a schema that is itself a table
+#[derive(Default)]
+struct SchemaTable {
+    header: Vec<BString>,
+    rows: Vec<Vec<BString>>,
+}
+
+impl Processor for &mut SchemaTable {
+    fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+        self.header = header;
+        Ok(())
+    }
+
+    fn on_row(&mut self, _base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+        self.rows.push(row);
+        Ok(())
+    }
+}
+
+fn parse_table_schema(path: &str, base: &base::Base) -> Result<Vec<Column>> {
+    let mut base = base.clone();
+    base.opts.header = Some(true);
+    base.opts.ifs = None;
+    let mut table = SchemaTable::default();
+    let file = crate::io::open_file(path, base.opts.encoding)?;
+    (&mut table).process_file(file, &mut base, Callbacks::ON_HEADER | Callbacks::ON_ROW)?;
+
+    table.rows.into_iter().map(|row| {
+        let mut fields: HashMap<_, _> = table.header.iter()
+            .zip(row)
+            .map(|(k, v)| (k.to_str_lossy().into_owned(), v.to_str_lossy().into_owned()))
+            .collect();
+        let values = fields.remove("values")
+            .filter(|v| !v.is_empty())
+            .map(|v| v.split('|').map(|v| v.to_owned()).collect());
+        Column::new(fields, values)
+    }).collect()
+}
+
+pub struct Handler {
+    columns: Vec<Column>,
+    any_order: bool,
+    // where each schema column is in the input
+    indices: Option<Vec<Option<usize>>>,
+    seen: Vec<HashMap<BString, usize>>,
+    row_count: usize,
+    violations: usize,
+}
+
+impl Handler {
+    pub fn new(opts: Opts, base: &mut base::Base) -> Result<Self> {
+        let contents = std::fs::read_to_string(&opts.schema).with_context(|| format!("failed to open {}", opts.schema))?;
+        let (columns, any_order) = if opts.schema.ends_with(".json") || contents.trim_start().starts_with(['{', '[']) {
+            parse_json_schema(&contents).with_context(|| format!("failed to read schema {}", opts.schema))?
+        } else {
+            (parse_table_schema(&opts.schema, base).with_context(|| format!("failed to read schema {}", opts.schema))?, false)
+        };
+
+        Ok(Self {
+            seen: columns.iter().map(|_| HashMap::new()).collect(),
+            columns,
+            any_order: any_order || opts.any_order,
+            indices: None,
+            row_count: 0,
+            violations: 0,
+        })
+    }
+
+    fn report(&mut self, base: &mut base::Base, row: BString, column: &BStr, value: &BStr, rule: &str, message: String) -> Result<()> {
+        if self.violations == 0 {
+            base.on_header(vec![b"row".into(), b"column".into(), b"value".into(), b"rule".into(), b"message".into()])?;
+        }
+        self.violations += 1;
+        base.on_row(vec![row, column.into(), value.into(), rule.into(), message.into()])
+    }
+
+    fn check_header(&mut self, base: &mut base::Base, header: &[BString]) -> Result<()> {
+        let row: BString = b"header".into();
+        if self.any_order {
+            for i in 0 .. self.columns.len() {
+                if !header.contains(&self.columns[i].name) {
+                    let name = self.columns[i].name.clone();
+                    self.report(base, row.clone(), name.as_ref(), b"".into(), "header", "missing column".into())?;
+                }
+            }
+        } else {
+            let len = header.len().max(self.columns.len());
+            for i in 0 .. len {
+                let expected = self.columns.get(i).map(|c| c.name.clone());
+                let actual = header.get(i);
+                if expected.as_ref() != actual {
+                    let message = match &expected {
+                        Some(expected) => format!("expected column {} to be {expected:?}", i + 1),
+                        None => format!("unexpected column {}", i + 1),
+                    };
+                    let actual = actual.cloned().unwrap_or_default();
+                    let column = expected.unwrap_or_else(|| actual.clone());
+                    self.report(base, row.clone(), column.as_ref(), actual.as_ref(), "header", message)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl base::Processor for Handler {
+    fn on_header(&mut self, base: &mut base::Base, header: Vec<BString>) -> Result<()> {
+        self.check_header(base, &header)?;
+        self.indices = Some(self.columns.iter().map(|c| header.iter().position(|h| *h == c.name)).collect());
+        Ok(())
+    }
+
+    fn on_row(&mut self, base: &mut base::Base, row: Vec<BString>) -> Result<()> {
+        self.row_count += 1;
+        // no header, so go by position
+        let indices = self.indices.get_or_insert_with(|| (0 .. self.columns.len()).map(Some).collect()).clone();
+        let row_number: BString = format!("{}", self.row_count).into();
+
+        for (c, index) in indices.into_iter().enumerate() {
+            let Some(index) = index else { continue };
+            let value = row.get(index).cloned().unwrap_or_default();
+            let column = &self.columns[c];
+            let name = column.name.clone();
+
+            let mut problems = vec![];
+            if value.trim().is_empty() {
+                if !column.nullable {
+                    problems.push(("null", "value is empty".to_owned()));
+                }
+            } else {
+                let parsed = column.parse(value.as_ref());
+                if parsed.is_none() {
+                    problems.push(("type", format!("not a valid {}", column.type_name())));
+                }
+                if let Some(values) = &column.values && !values.contains(&value) {
+                    let mut values: Vec<_> = values.iter().map(|v| v.to_str_lossy()).collect();
+                    values.sort();
+                    problems.push(("enum", format!("not one of {}", values.join(", "))));
+                }
+                if let Some(regex) = &column.regex && !regex.is_match(&value) {
+                    problems.push(("regex", format!("does not match {}", regex.as_str())));
+                }
+                let is_length = matches!(column.typ, Type::String | Type::Enum);
+                if let Some(parsed) = parsed && let Some((min, raw)) = &column.min && parsed < *min {
+                    problems.push(("min", if is_length { format!("shorter than {raw} characters") } else { format!("less than {raw}") }));
+                }
+                if let Some(parsed) = parsed && let Some((max, raw)) = &column.max && parsed > *max {
+                    problems.push(("max", if is_length { format!("longer than {raw} characters") } else { format!("more than {raw}") }));
+                }
+                if column.unique {
+                    if let Some(first) = self.seen[c].get(&value) {
+                        problems.push(("unique", format!("duplicate of row {first}")));
+                    } else {
+                        self.seen[c].insert(value.clone(), self.row_count);
+                    }
+                }
+            }
+
+            for (rule, message) in problems {
+                self.report(base, row_number.clone(), name.as_ref(), value.as_ref(), rule, message)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_eof(self, base: &mut base::Base) -> Result<bool> {
+        base.on_eof()?;
+        Ok(self.violations == 0)
+    }
+}
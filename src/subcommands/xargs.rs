@@ -2,10 +2,10 @@ use once_cell::sync::Lazy;
 use std::process::ExitCode;
 use crate::utils::{Break, MaybeBreak};
 use crate::writer::{get_rgb};
-use crate::column_slicer::{make_header_map};
+use crate::column_slicer::{make_header_map, unknown_field};
 use regex::bytes::{Regex, Captures};
 use std::path::Path;
-use anyhow::{Result};
+use anyhow::{Result, bail};
 use std::sync::{mpsc};
 use crate::base::{self, AutoChoices};
 use std::fmt::{Write as FmtWrite};
@@ -17,7 +17,6 @@ use std::process::{Child, Command, Stdio, ChildStdin, ChildStdout, ChildStderr};
 use std::io::{Read, Write};
 use bstr::{BString, BStr, ByteSlice, ByteVec};
 use clap::{Parser, CommandFactory, ArgAction, error::{ErrorKind, ContextKind, ContextValue}};
-use nix::sys::stat::{fstat, SFlag};
 use nix::sys::signal::{kill, SIGTERM};
 use std::borrow::Cow;
 use std::time::{Instant, Duration};
@@ -733,6 +732,24 @@ impl ProcStore {
         self.inner.is_empty()
     }
 
+    // so that a misspelt {name} fails up front like an unknown field does elsewhere
+    fn check_placeholders(&self, header: &[BString], keys: &HashMap<BString, usize>) -> Result<()> {
+        for arg in self.opts.command.iter().chain([&self.opts.stdin]) {
+            for c in self.placeholder_regex.captures_iter(arg.as_bytes()) {
+                if c.get(1).or(c.get(2)).is_some() {
+                    continue
+                }
+                let text = c.get(0).unwrap().as_bytes();
+                if Proc::format_arg(&self.placeholder_regex, text.into(), Some(keys), header).is_err() {
+                    let inner = &text[1..text.len()-1];
+                    let name = FormatSpec::parse(inner).map_or(inner, |(_, name)| name);
+                    bail!("{}", unknown_field(header, name));
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn is_full(&self) -> bool {
         self.job_limit.is_some_and(|limit| limit.get() <= self.inner.len())
     }
@@ -932,7 +949,11 @@ fn proc_loop(
                     loop {
                         match receiver.try_recv() {
                             Ok(Message::Header(mut h)) => {
-                                proc_store.keys = Some(make_header_map(&h));
+                                let keys = make_header_map(&h);
+                                if !base.opts.lenient_fields {
+                                    proc_store.check_placeholders(&h, &keys)?;
+                                }
+                                proc_store.keys = Some(keys);
                                 if !proc_store.opts.tag {
                                     h.clear();
                                 }
@@ -979,9 +1000,7 @@ impl Handler {
         opts.inner.progress_bar = AutoChoices::from_option_auto(opts.progress_bar).resolve_with(|| {
             base.opts.inner.is_stderr_tty && (
                 base.opts.inner.is_stdout_tty
-                || fstat(std::io::stdout().as_fd())
-                    .map(|s| !SFlag::S_IFIFO.intersects(SFlag::from_bits_truncate(s.st_mode)))
-                    .unwrap_or(false)
+                || !base.opts.inner.redirect.is_stdout_fifo()
             )
         });
 
@@ -1060,6 +1079,11 @@ impl Handler {
 }
 
 impl base::Processor for Handler {
+    // the commands decide what comes out, so upstream alignment doesn't apply
+    fn on_alignment(&mut self, _base: &mut base::Base, _alignment: Vec<Option<base::Alignment>>) -> Result<()> {
+        Ok(())
+    }
+
     fn on_header(&mut self, _base: &mut base::Base, header: Vec<BString>) -> Result<()> {
         Break::when(self.sender.send(Message::Header(header)).is_err())
     }
@@ -1069,12 +1093,13 @@ impl base::Processor for Handler {
     }
 
     fn on_ofs(&mut self, base: &mut base::Base, ofs: base::Ofs) -> MaybeBreak {
-        self.sender.send(Message::Ofs(ofs.clone())).unwrap();
+        // the loop may have stopped already, it reports why at eof
+        let _ = self.sender.send(Message::Ofs(ofs.clone()));
         base.on_ofs(ofs)
     }
 
     fn on_eof_detailed(self, _base: &mut base::Base) -> Result<ExitCode> {
-        self.sender.send(Message::Eof).unwrap();
+        let _ = self.sender.send(Message::Eof);
         self.err_receiver.recv().unwrap()
     }
 
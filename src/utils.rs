@@ -29,6 +29,15 @@ pub fn unescape_str(val: &'_ str) -> Cow<'_, BStr> {
     }
 }
 
+// a single byte, escapes like \t are allowed
+pub fn parse_char(val: &str) -> Result<u8, String> {
+    let val: &[u8] = &unescape_str(val);
+    match val {
+        [c] => Ok(*c),
+        _ => Err("must be a single byte character".into()),
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Break;
 
@@ -83,3 +92,18 @@ pub fn percent_format(format: &BStr, value: &BStr) -> BString {
         }
     }).into_owned().into()
 }
+
+// levenshtein distance, by character
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0 ..= b.len()).collect();
+    for (i, x) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, y) in b.iter().enumerate() {
+            let cost = usize::from(x != *y);
+            current.push((prev[j] + cost).min(prev[j + 1] + 1).min(current[j] + 1));
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
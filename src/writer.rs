@@ -9,7 +9,7 @@ use colorutils_rs::Hsv;
 const TERM_CLEAR: &[u8] = b"\x1b[K";
 
 const STEP: f32 = 0.647;
-pub fn get_rgb(i: usize, step: Option<f32>, saturation: Option<f32>) -> BString {
+pub fn get_rgb_values(i: usize, step: Option<f32>, saturation: Option<f32>) -> (u8, u8, u8) {
     let hue = (step.unwrap_or(STEP) * i as f32) % 1.0;
     let hsv = Hsv{
         h: hue * 360.0,
@@ -17,10 +17,38 @@ pub fn get_rgb(i: usize, step: Option<f32>, saturation: Option<f32>) -> BString
         v: 1.0,
     };
     let rgb = hsv.to_rgb8();
-    format!("\x1b[38;2;{};{};{}m", rgb.r, rgb.g, rgb.b).as_bytes().into()
+    (rgb.r, rgb.g, rgb.b)
+}
+
+pub fn get_rgb(i: usize, step: Option<f32>, saturation: Option<f32>) -> BString {
+    let (r, g, b) = get_rgb_values(i, step, saturation);
+    format!("\x1b[38;2;{r};{g};{b}m").as_bytes().into()
+}
+
+pub struct Quoting<'a> {
+    pub quote: u8,
+    pub escape: Option<u8>,
+    pub null_string: Option<&'a [u8]>,
+}
+
+impl Default for Quoting<'_> {
+    fn default() -> Self {
+        Self{ quote: b'"', escape: None, null_string: None }
+    }
+}
+
+impl<'a> Quoting<'a> {
+    pub fn new(opts: &'a BaseOptions) -> Self {
+        Self {
+            quote: opts.quote_char(),
+            escape: opts.escape_char,
+            null_string: opts.null_string.as_deref().map(|s| s.as_bytes()),
+        }
+    }
 }
 
-pub fn format_columns<S: AsRef<BStr>>(mut row: Vec<BString>, ofs: &Ofs<S>, ors: &BStr, quote_output: bool) -> FormattedRow {
+pub fn format_columns<S: AsRef<BStr>>(mut row: Vec<BString>, ofs: &Ofs<S>, ors: &BStr, quote_output: bool, quoting: &Quoting) -> FormattedRow {
+    let quote = quoting.quote;
     if quote_output {
         let ofs = ofs.as_bstr();
         // if ofs is space, make sure to quote empty columns otherwise they will just look like
@@ -28,24 +56,79 @@ pub fn format_columns<S: AsRef<BStr>>(mut row: Vec<BString>, ofs: &Ofs<S>, ors:
         let ofs_is_space = ofs.iter().all(|c| c.is_ascii_whitespace());
 
         for col in &mut row {
-            if (ofs_is_space && col.is_empty()) || needs_quoting(col, ofs, ors) {
+            if ofs_is_space && col.is_empty() && quoting.null_string.is_none() {
+                *col = vec![quote, quote].into();
+            } else if let Some(escape) = quoting.escape {
+                if needs_escaping(col, ofs, ors, quote, escape) {
+                    *col = escape_column(col, ofs, ors, quote, escape);
+                }
+            } else if needs_quoting(col, ofs, ors, quote) {
                 let mut quoted_col = vec![];
-                quoted_col.push(b'"');
-                for (i, part) in col.split_str(b"\"").enumerate() {
+                quoted_col.push(quote);
+                for (i, part) in col.split_str(&[quote]).enumerate() {
                     if i != 0 {
-                        quoted_col.extend_from_slice(b"\"\"");
+                        quoted_col.extend_from_slice(&[quote, quote]);
                     }
                     quoted_col.extend_from_slice(part);
                 }
-                quoted_col.push(b'"');
+                quoted_col.push(quote);
                 *col = quoted_col.into();
             }
         }
     }
 
+    if let Some(null) = quoting.null_string {
+        for col in &mut row {
+            if col.is_empty() {
+                *col = null.into();
+            }
+        }
+    }
+
     FormattedRow(row)
 }
 
+fn escape_column(value: &[u8], ofs: &[u8], ors: &[u8], quote: u8, escape: u8) -> BString {
+    let mut escaped = BString::new(vec![]);
+    for (i, &c) in value.iter().enumerate() {
+        let rest = &value[i..];
+        match c {
+            b'\n' => escaped.extend_from_slice(&[escape, b'n']),
+            b'\r' => escaped.extend_from_slice(&[escape, b'r']),
+            b'\0' => escaped.extend_from_slice(&[escape, b'0']),
+            _ if c == escape || c == quote
+                || (!ofs.is_empty() && rest.starts_with(ofs))
+                || (!ors.is_empty() && rest.starts_with(ors))
+                => escaped.extend_from_slice(&[escape, c]),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Lines up the columns of rows like the pretty output, e.g. `dsv pretty`.
+///
+/// Widths ignore ANSI colours and count wide characters as two columns. The last column is not padded.
+///
+/// ```
+/// let rows = [vec!["name".into(), "size".into()], vec!["日本".into(), "1".into()]];
+/// assert_eq!(dsv::justify(&rows, "  "), ["name  size", "日本  1"]);
+/// ```
+pub fn justify(rows: &[Vec<BString>], ofs: &str) -> Vec<BString> {
+    let padding = crate::base::padding(rows.iter().map(|row| &row[..]));
+    rows.iter().zip(padding).map(|(row, padding)| {
+        let mut line = BString::new(vec![]);
+        for (i, col) in row.iter().enumerate() {
+            if i > 0 {
+                line.push_str(ofs);
+            }
+            line.push_str(col);
+            line.extend(std::iter::repeat_n(b' ', padding.get(i).copied().unwrap_or(0)));
+        }
+        line
+    }).collect()
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn format_row<'a, I: Iterator<Item=&'a BStr>>(
     row: Vec<BString>,
@@ -118,18 +201,59 @@ pub fn format_row<'a, I: Iterator<Item=&'a BStr>>(
     parts
 }
 
-fn needs_quoting(value: &[u8], ofs: &[u8], ors: &[u8]) -> bool {
-    value.contains(&b'"') || value.windows(ofs.len()).any(|window| window == ofs) || value.windows(ors.len()).any(|window| window == ors)
+fn needs_quoting(value: &[u8], ofs: &[u8], ors: &[u8], quote: u8) -> bool {
+    value.contains(&quote) || value.windows(ofs.len()).any(|window| window == ofs) || value.windows(ors.len()).any(|window| window == ors)
+}
+
+fn needs_escaping(value: &[u8], ofs: &[u8], ors: &[u8], quote: u8, escape: u8) -> bool {
+    value.iter().any(|&c| matches!(c, b'\n' | b'\r' | b'\0') || c == escape) || needs_quoting(value, ofs, ors, quote)
 }
 
 #[derive(Default)]
 pub struct WriterState {
     pub file: Option<Box<dyn Write>>,
+    pub finish: Option<crate::io::Finish>,
     pub rgb_map: Vec<BString>,
     pub ors: BString,
     pub hyperlinks: Option<(u32, Vec<BString>)>,
 }
 
+impl WriterState {
+    // flush and end any compression, so errors are reported rather than lost on drop
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        if let Some(finish) = self.finish.take() {
+            finish.finish()?;
+        }
+        Ok(())
+    }
+}
+
+// the file from get_file, compressed and encoded as asked
+fn open_file<'a, W: Writer + ?Sized>(
+    writer: &mut W,
+    file: &'a mut Option<Box<dyn Write>>,
+    finish: &mut Option<crate::io::Finish>,
+    opts: &BaseOptions,
+    is_header: bool,
+) -> Result<&'a mut Box<dyn Write>> {
+    if file.is_none() {
+        let mut new_file = writer.get_file(opts, is_header);
+        if let Some(compression) = opts.compress && !opts.page {
+            let (compressed, compressor) = crate::io::compress(new_file, compression)?;
+            new_file = compressed;
+            *finish = Some(compressor);
+        }
+        if let Some(encoding) = opts.output_encoding && !opts.page {
+            new_file = crate::encoding::encode(new_file, encoding);
+        }
+        *file = Some(new_file);
+    }
+    Ok(file.as_mut().unwrap())
+}
+
 pub struct BaseWriter {
     proc: Option<std::process::Child>,
 }
@@ -150,8 +274,8 @@ pub trait Writer {
         state.rgb_map.iter().map(|x| x.as_bstr()).chain(repeat(b"".into()))
     }
 
-    fn format_columns(row: Vec<BString>, ofs: &Ofs, ors: &BStr, quote_output: bool) -> FormattedRow {
-        format_columns(row, ofs, ors, quote_output)
+    fn format_columns(row: Vec<BString>, ofs: &Ofs, ors: &BStr, opts: &BaseOptions) -> FormattedRow {
+        format_columns(row, ofs, ors, opts.quote_output, &Quoting::new(opts))
     }
 
     fn write_header(
@@ -203,6 +327,17 @@ pub trait Writer {
         self.write_raw(state, sep.into(), true, opts, false, true)
     }
 
+    fn set_alignment(&mut self, _alignment: Vec<Option<Alignment>>) {
+    }
+
+    fn write_eof(
+        &mut self,
+        _state: &mut WriterState,
+        _opts: &BaseOptions,
+    ) -> Result<()> {
+        Ok(())
+    }
+
     fn write_raw(
         &mut self,
         state: &mut WriterState,
@@ -212,7 +347,7 @@ pub trait Writer {
         is_header: bool,
         clear: bool,
     ) -> Result<()> {
-        let file = state.file.get_or_insert_with(|| self.get_file(opts, is_header));
+        let file = open_file(self, &mut state.file, &mut state.finish, opts, is_header)?;
         self.write_to_file(file, if ors { Some(state.ors.as_ref()) } else { None }, clear, string)
     }
 
@@ -224,7 +359,7 @@ pub trait Writer {
         clear: bool,
         func: F,
     ) -> Result<()> {
-        let file = state.file.get_or_insert_with(|| self.get_file(opts, is_header));
+        let file = open_file(self, &mut state.file, &mut state.finish, opts, is_header)?;
         self.write_to_file_with(file, state.ors.as_ref(), clear, func)
     }
 
@@ -350,7 +485,7 @@ impl Writer for BaseWriter {
             }
             self.pipe_to(command)
         } else {
-            Box::new(std::io::stdout().lock())
+            opts.inner.redirect.stdout()
         }
     }
 }
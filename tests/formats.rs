@@ -0,0 +1,365 @@
+use std::path::PathBuf;
+use dsv::Pipeline;
+
+const INPUT: &str = "name,size,note\nalpha,1,\"x, y\"\nbeta,2.5,\n";
+
+fn run(pipeline: Pipeline, input: &'static str) -> String {
+    let (_, output) = pipeline.output(input.as_bytes()).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+// a file in the temp dir that is removed when the test is done with it
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("dsv-test-{}-{name}", std::process::id())))
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// a directory of files in the temp dir, removed when the test is done with it
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("dsv-test-{}-{name}", std::process::id()));
+        for (path, contents) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        Self(dir)
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn xlsx_round_trip() {
+    let file = TempFile::new("round-trip.xlsx");
+    run(Pipeline::new().option("--csv").stage("toxlsx", ["--sheet", "data", file.path()]), INPUT);
+    let output = run(Pipeline::new().option("--csv").stage("fromxlsx", ["--sheet", "data", file.path()]), "");
+    assert_eq!(output, INPUT);
+
+    let output = run(Pipeline::new().option("--csv").stage("fromxlsx", ["--range", "B1:C3", file.path()]), "");
+    assert_eq!(output, "size,note\n1,\"x, y\"\n2.5,\n");
+}
+
+#[test]
+fn parquet_round_trip() {
+    let file = TempFile::new("round-trip.parquet");
+    run(Pipeline::new().option("--csv").stage("toparquet", [file.path()]), INPUT);
+    let output = run(Pipeline::new().option("--csv").stage("fromparquet", [file.path()]), "");
+    assert_eq!(output, "name,size,note\nalpha,1.0,\"x, y\"\nbeta,2.5,\n");
+
+    let output = run(Pipeline::new().option("--csv").stage("fromparquet", ["--columns", "note,name", file.path()]), "");
+    // columns come out in the order of the file
+    assert_eq!(output, "name,note\nalpha,\"x, y\"\nbeta,\n");
+}
+
+#[test]
+fn parquet_empty_input() {
+    let file = TempFile::new("empty.parquet");
+    run(Pipeline::new().option("--csv").stage("toparquet", [file.path()]), "name,size\n");
+    let output = run(Pipeline::new().option("--csv").stage("fromparquet", [file.path()]), "");
+    assert_eq!(output, "name,size\n");
+}
+
+#[test]
+fn parquet_failure_removes_the_file() {
+    let file = TempFile::new("failed.parquet");
+    // the first row says size is an int
+    let result = Pipeline::new().option("--csv").stage("toparquet", ["--sample", "1", file.path()]).output(INPUT.as_bytes());
+    assert!(matches!(result, Err(dsv::Error::Failed(e)) if e.to_string().contains("try a larger --sample")));
+    assert!(!file.0.exists());
+}
+
+#[test]
+fn arrow_round_trip() {
+    let (_, arrow) = Pipeline::new().option("--csv").stage("toarrow", std::iter::empty::<&str>()).output(INPUT.as_bytes()).unwrap();
+    let (_, output) = Pipeline::new().option("--csv").stage("fromarrow", std::iter::empty::<&str>()).output(std::io::Cursor::new(arrow)).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "name,size,note\nalpha,1.0,\"x, y\"\nbeta,2.5,\n");
+
+    let file = TempFile::new("round-trip.arrow");
+    run(Pipeline::new().option("--csv").stage("toarrow", [file.path()]), "a,n\nx,1\ny,2\n");
+    let output = run(Pipeline::new().option("--csv").stage("fromarrow", ["--columns", "n", file.path()]), "");
+    assert_eq!(output, "n\n1\n2\n");
+}
+
+#[test]
+fn xml_round_trip() {
+    let (_, xml) = Pipeline::new().option("--csv").stage("toxml", std::iter::empty::<&str>()).output(INPUT.as_bytes()).unwrap();
+    let (_, output) = Pipeline::new().option("--csv").stage("fromxml", std::iter::empty::<&str>()).output(std::io::Cursor::new(xml)).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), INPUT);
+
+    // names are made into valid elements, values are escaped and empty elements are kept
+    let pipeline = Pipeline::new().option("--csv")
+        .stage("toxml", ["--root", "items", "--record", "item"]);
+    let (_, xml) = pipeline.output(&b"my name,a&b,x\n<tag>,\"1 & 2\",\nz,y,w\n"[..]).unwrap();
+    let (_, output) = Pipeline::new().option("--csv").stage("fromxml", ["--record", "/items/item"]).output(std::io::Cursor::new(xml)).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "my_name,a_b,x\n<tag>,1 & 2,\nz,y,w\n");
+}
+
+#[test]
+fn parquet_rows_wider_than_the_schema() {
+    let file = TempFile::new("wide.parquet");
+    let input = "a,b\n1,2\n3,4,5\n";
+    let result = Pipeline::new().option("--csv").stage("toparquet", ["--sample", "1", file.path()]).output(input.as_bytes());
+    assert!(matches!(result, Err(dsv::Error::Failed(e)) if e.to_string().contains("row 2: 3 fields but the schema has 2")));
+    assert!(!file.0.exists());
+
+    // a sample that sees the wide row makes room for it
+    run(Pipeline::new().option("--csv").stage("toparquet", [file.path()]), input);
+    let output = run(Pipeline::new().option("--csv").stage("fromparquet", [file.path()]), "");
+    assert_eq!(output, "a,b,col2\n1,2,\n3,4,5\n");
+}
+
+#[test]
+fn fromregex_named_groups() {
+    let input = "x=1 y=a\nbad line\nx=2 y=b\n  more\n";
+    let pattern = r"^x=(?<x>\d+) y=(?<y>\w+)";
+    let output = run(Pipeline::new().option("--ofs").option(",").stage("fromregex", [pattern]), input);
+    assert_eq!(output, "x,y\n1,a\n2,b\n");
+
+    // lines that don't match carry on the last column
+    let output = run(Pipeline::new().option("--ofs").option(",").stage("fromregex", ["--continuation", pattern]), input);
+    assert_eq!(output, "x,y\n1,\"a\nbad line\"\n2,\"b\n  more\"\n");
+
+    // groups that don't take part are empty
+    let output = run(Pipeline::new().option("--ofs").option(",").stage("fromregex", [r"^x=(?<x>\d+)(?: y=(?<y>\w+))?$"]), "x=1\nx=2 y=b\n");
+    assert_eq!(output, "x,y\n1,\n2,b\n");
+}
+
+#[test]
+fn fromregex_preset() {
+    let input = "127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] \"GET /a.gif HTTP/1.0\" 200 2326 \"http://x/\" \"Mozilla/4.08\"\n";
+    let output = run(Pipeline::new().option("--ofs").option(",").stage("fromregex", ["--preset", "nginx-combined"]), input);
+    assert_eq!(output, concat!(
+        "remote_addr,remote_user,time_local,request,status,body_bytes_sent,http_referer,http_user_agent\n",
+        "127.0.0.1,frank,10/Oct/2000:13:55:36 -0700,GET /a.gif HTTP/1.0,200,2326,http://x/,Mozilla/4.08\n",
+    ));
+}
+
+const HTML: &str = r#"<html><body>
+<table id="first"><tr><th>a</th><th>b</th></tr><tr><td>1</td><td>2</td></tr></table>
+<div id="main">
+<table class="data"><caption>Sizes</caption><tr><th>name</th><th>size</th></tr><tr><td>x</td><td><b>3</b><table><tr><td>in</td></tr></table></td></tr></table>
+</div>
+<table class="data other"><tr><th>k</th></tr><tr><td>v</td></tr></table>
+</body></html>"#;
+
+fn fromhtml(args: &[&str], input: &'static str) -> String {
+    run(Pipeline::new().option("--ofs").option(",").stage("fromhtml", args.iter().copied()), input)
+}
+
+#[test]
+fn fromhtml_table_selection() {
+    assert_eq!(fromhtml(&["--table", "2"], HTML), "name,size\nx,3  in\n");
+    assert_eq!(fromhtml(&["--id", "first"], HTML), "a,b\n1,2\n");
+    assert_eq!(fromhtml(&["--class", "data"], HTML), "name,size\nx,3  in\n");
+    assert_eq!(fromhtml(&["--class", "data", "--table", "2"], HTML), "k\nv\n");
+    assert_eq!(fromhtml(&["--selector", "div#main table.data"], HTML), "name,size\nx,3  in\n");
+    assert_eq!(fromhtml(&["--id", "nope"], HTML), "");
+    // tables are counted in document order, nested ones too, like querySelectorAll
+    assert_eq!(fromhtml(&["--table", "3"], HTML), "in\n");
+    assert_eq!(fromhtml(&["--table", "4"], HTML), "k\nv\n");
+}
+
+#[test]
+fn fromhtml_all_tables() {
+    let pipeline = Pipeline::new().stage("fromhtml", ["--all"]).stage("tojson", std::iter::empty::<&str>());
+    assert_eq!(run(pipeline, HTML), "{\"a\":\"1\",\"b\":\"2\"}\n{\"name\":\"x\",\"size\":\"3  in\"}\n{\"k\":\"v\"}\n");
+}
+
+#[test]
+fn fromhtml_nested_captions_and_headers() {
+    assert_eq!(fromhtml(&["--table", "2", "--nested", "skip"], HTML), "name,size\nx,3\n");
+    assert_eq!(fromhtml(&["--table", "2", "--caption"], HTML), "caption,name,size\nSizes,x,3  in\n");
+    assert_eq!(fromhtml(&["--table", "2", "--inner-html"], HTML), "name,size\nx,<b>3</b><table><tr><td>in</td></tr></table>\n");
+
+    // thead makes a header even when it looks like data
+    let tojson = |input| run(Pipeline::new().stage("fromhtml", std::iter::empty::<&str>()).stage("tojson", std::iter::empty::<&str>()), input);
+    assert_eq!(tojson("<table><thead><tr><td>2023</td><td>2024</td></tr></thead><tr><td>1</td><td>2</td></tr></table>"), "{\"2023\":\"1\",\"2024\":\"2\"}\n");
+    assert_eq!(tojson("<table><tr><td>2023</td><td>2024</td></tr><tr><td>1</td><td>2</td></tr></table>"), "{\"0\":\"2023\",\"1\":\"2024\"}\n{\"0\":\"1\",\"1\":\"2\"}\n");
+
+    let rowspan = r#"<table><tr><th>k</th><th>v</th></tr><tr><td rowspan="2">a</td><td>1</td></tr><tr><td>2</td></tr></table>"#;
+    assert_eq!(fromhtml(&[], rowspan), "k,v\na,1\na,2\n");
+}
+
+#[test]
+fn cat_filename_and_line_columns() {
+    let dir = TempDir::new("cat-columns", &[("one.csv", "a,b\n1,2\n\"x\ny\",3\n"), ("sub/two.csv", "a,b\n4,5\n")]);
+    let (one, two) = (dir.path("one.csv"), dir.path("sub/two.csv"));
+
+    let cat = |args: &[&str], input| run(Pipeline::new().option("--csv").stage("cat", args.iter().copied()), input);
+    // the line is where the record starts
+    assert_eq!(
+        cat(&["--filename-column", "file", "--line-column", "line", &one, &two], ""),
+        format!("file,line,a,b\n{one},2,1,2\n{one},3,\"x\ny\",3\n{two},2,4,5\n"),
+    );
+    assert_eq!(cat(&["--filename-column", "file", "--basename", &one, &two], ""), "file,a,b\none.csv,1,2\none.csv,\"x\ny\",3\ntwo.csv,4,5\n");
+    // stdin is -
+    assert_eq!(cat(&["--filename-column", "file", "--basename", &two], "a,b\ns,t\n"), "file,a,b\n-,s,t\ntwo.csv,4,5\n");
+    assert_eq!(cat(&["-n", "--basename", "--filename-column", "file", &two], "a,b\ns,t\n"), "n,file,a,b\n1,-,s,t\n2,two.csv,4,5\n");
+}
+
+#[test]
+fn cat_directories_and_globs() {
+    let dir = TempDir::new("cat-expand", &[
+        ("one.csv", "a\n1\n"),
+        ("sub/two.csv", "a\n2\n"),
+        ("v10.csv", "a\n10\n"),
+        ("v2.csv", "a\n9\n"),
+        ("notes.txt", "a\nnote\n"),
+    ]);
+    let cat = |args: &[&str]| run(Pipeline::new().option("--csv").stage("cat", args.iter().copied()), "");
+    let root = dir.path("");
+    let root = root.trim_end_matches('/');
+
+    // a directory needs -r
+    assert_eq!(cat(&[root]), "");
+    assert_eq!(cat(&["-r", root]), "a\nnote\n1\n2\n10\n9\n");
+    assert_eq!(cat(&["-r", "--include", "*.csv", "--exclude", "v*", root]), "a\n1\n2\n");
+    // globs are expanded in order, naturally with -V
+    let glob = dir.path("v*.csv");
+    assert_eq!(cat(&[&glob]), "a\n10\n9\n");
+    assert_eq!(cat(&["-V", &glob]), "a\n9\n10\n");
+    assert_eq!(cat(&["--filename-column", "file", "--basename", "-V", &dir.path("*")]), "file,a\nnotes.txt,note\none.csv,1\nv2.csv,9\nv10.csv,10\n");
+}
+
+// cat with global options, as the ragged handling is done when reading any input
+fn cat_with(options: &[&str], input: &'static str) -> Result<(std::process::ExitCode, Vec<u8>), dsv::Error> {
+    let pipeline = options.iter().fold(Pipeline::new().option("--csv"), |pipeline, option| pipeline.option(*option));
+    pipeline.stage("cat", [] as [&str; 0]).output(input.as_bytes())
+}
+
+#[test]
+fn ragged_rows() {
+    let input = "a,b,c\n1,2,3\n4,5\n6,7,8,9\n";
+    let output = |options: &[&str]| String::from_utf8(cat_with(options, input).unwrap().1).unwrap();
+
+    assert_eq!(output(&[]), input);
+    // pad only fills short rows and truncate only cuts long ones
+    assert_eq!(output(&["--on-ragged", "pad"]), "a,b,c\n1,2,3\n4,5,\n6,7,8,9\n");
+    assert_eq!(output(&["--on-ragged", "truncate"]), "a,b,c\n1,2,3\n4,5\n6,7,8\n");
+    assert_eq!(output(&["--on-ragged", "skip"]), "a,b,c\n1,2,3\n");
+    assert_eq!(output(&["--on-ragged", "stderr"]), "a,b,c\n1,2,3\n");
+    for options in [&["--on-ragged", "error"][..], &["--strict"]] {
+        let result = cat_with(options, input);
+        assert!(matches!(result, Err(dsv::Error::Failed(e)) if e.to_string() == "<stdin>:3: expected 3 fields but found 2: 4,5"));
+    }
+    // --on-ragged takes over from --strict
+    assert_eq!(output(&["--strict", "--on-ragged", "pad"]), "a,b,c\n1,2,3\n4,5,\n6,7,8,9\n");
+
+    // without a header the first row sets the width
+    let (_, output) = cat_with(&["-N", "--on-ragged", "pad"], "1,2\n3\n4,5,6\n").unwrap();
+    assert_eq!(output, b"1,2\n3,\n4,5,6\n");
+}
+
+#[test]
+fn unterminated_quotes() {
+    let input = "a,b\n1,\"x\n2,3\n";
+    let (_, output) = cat_with(&[], input).unwrap();
+    assert_eq!(output, b"a,b\n1,\"x\n2,3\"\n");
+
+    for options in [&["--strict"][..], &["--strict", "--on-ragged", "pad"]] {
+        let result = cat_with(options, input);
+        assert!(matches!(result, Err(dsv::Error::Failed(e)) if e.to_string().starts_with("<stdin>:2: unterminated quote")));
+    }
+}
+
+fn validate(schema: &TempFile, options: &[&str], input: &'static str) -> (bool, String) {
+    let pipeline = Pipeline::new().option("--csv").stage("validate", options.iter().copied().chain([schema.path()]));
+    let (code, output) = pipeline.output(input.as_bytes()).unwrap();
+    (code == std::process::ExitCode::SUCCESS, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn validate_table_schema() {
+    let schema = TempFile::new("schema.csv");
+    std::fs::write(&schema.0, concat!(
+        "name,type,format,values,regex,nullable,unique,min,max\n",
+        "id,int,,,,no,yes,1,100\n",
+        "kind,enum,,a|b,,,,,\n",
+        "code,string,,,^[A-Z]+$,,,2,3\n",
+        "when,date,%d/%m/%Y,,,,,,\n",
+    )).unwrap();
+
+    assert_eq!(validate(&schema, &[], "id,kind,code,when\n1,a,AB,01/02/2024\n2,b,ABC,\n"), (true, "".into()));
+
+    let input = "id,kind,code,when\n1,a,AB,01/02/2024\n1,c,abcd,2024-02-01\n,b,A,\n200,a,ABC,31/12/2024\n";
+    assert_eq!(validate(&schema, &[], input), (false, concat!(
+        "row,column,value,rule,message\n",
+        "2,id,1,unique,duplicate of row 1\n",
+        "2,kind,c,enum,\"not one of a, b\"\n",
+        "2,code,abcd,regex,does not match ^[A-Z]+$\n",
+        "2,code,abcd,max,longer than 3 characters\n",
+        "2,when,2024-02-01,type,not a valid date in the format %d/%m/%Y\n",
+        "3,id,,null,value is empty\n",
+        "3,code,A,min,shorter than 2 characters\n",
+        "4,id,200,max,more than 100\n",
+    ).into()));
+
+    assert_eq!(validate(&schema, &[], "kind,id,code\n"), (false, concat!(
+        "row,column,value,rule,message\n",
+        "header,id,kind,header,\"expected column 1 to be \"\"id\"\"\"\n",
+        "header,kind,id,header,\"expected column 2 to be \"\"kind\"\"\"\n",
+        "header,when,,header,\"expected column 4 to be \"\"when\"\"\"\n",
+    ).into()));
+    assert_eq!(validate(&schema, &["--any-order"], "kind,extra,id,code\n"), (false, concat!(
+        "row,column,value,rule,message\n",
+        "header,when,,header,missing column\n",
+    ).into()));
+}
+
+#[test]
+fn validate_json_schema() {
+    let schema = TempFile::new("schema.json");
+    std::fs::write(&schema.0, r#"{"columns": [{"name": "n", "type": "percent", "max": "50%", "nullable": false}], "any_order": true}"#).unwrap();
+    assert_eq!(validate(&schema, &[], "x,n\n1,20%\n2,60%\n3,abc\n4,\n"), (false, concat!(
+        "row,column,value,rule,message\n",
+        "2,n,60%,max,more than 50%\n",
+        "3,n,abc,type,not a valid percent\n",
+        "4,n,,null,value is empty\n",
+    ).into()));
+
+    // without a header the columns go by position
+    std::fs::write(&schema.0, r#"[{"name": "n", "type": "int"}, {"name": "m", "type": "size", "max": "1K"}]"#).unwrap();
+    assert_eq!(validate(&schema, &["-N"], "1,2K\nx,10\n"), (false, concat!(
+        "row,column,value,rule,message\n",
+        "1,m,2K,max,more than 1K\n",
+        "2,n,x,type,not a valid integer\n",
+    ).into()));
+
+    for (contents, error) in [
+        (r#"[{"name": "n", "type": "bogus"}]"#, "unknown type \"bogus\""),
+        (r#"[{"name": "n", "type": "enum"}]"#, "schema for \"n\" is an enum but has no values"),
+        (r#"[{"name": "n", "type": "int", "min": "x"}]"#, "invalid min \"x\" in schema for \"n\""),
+        (r#"[{"name": "n", "colour": "red"}]"#, "unknown schema key \"colour\""),
+    ] {
+        std::fs::write(&schema.0, contents).unwrap();
+        let result = Pipeline::new().option("--csv").stage("validate", [schema.path()]).output(&b"n\n1\n"[..]);
+        let Err(e) = result else { panic!("{contents} should fail") };
+        // the reason is under the "failed to read schema" context
+        let chain: Vec<_> = std::iter::successors(Some(&e as &dyn std::error::Error), |e| e.source()).map(|e| e.to_string()).collect();
+        assert!(chain.iter().any(|e| e.contains(error)), "{chain:?}");
+    }
+}
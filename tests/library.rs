@@ -0,0 +1,44 @@
+use bstr::BString;
+use dsv::{ColumnSlicer, Error, Ifs, LineParser};
+
+fn row(fields: &[&str]) -> Vec<BString> {
+    fields.iter().map(|&f| f.into()).collect()
+}
+
+#[test]
+fn parse_quotes_and_escapes() {
+    let mut parser = LineParser::new(Ifs::Plain(",".into()));
+    assert_eq!(parser.parse_line(br#"a,"say ""hi""",,"#), Some(row(&["a", r#"say "hi""#, "", ""])));
+
+    let mut parser = LineParser::new(Ifs::Plain(";".into())).quote_char(b'\'').escape_char(b'\\');
+    assert_eq!(parser.parse_line(br"'a;b';c\;d"), Some(row(&["a;b", "c;d"])));
+
+    let mut parser = LineParser::new(Ifs::Plain(",".into())).no_quoting();
+    assert_eq!(parser.parse_line(br#""a,b""#), Some(row(&["\"a", "b\""])));
+
+    let mut parser = LineParser::new(Ifs::Regex(regex::bytes::Regex::new(r"\s+").unwrap()));
+    assert_eq!(parser.parse_line(b"a  b\tc"), Some(row(&["a", "b", "c"])));
+}
+
+#[test]
+fn parse_unterminated_quote() {
+    let mut parser = LineParser::new(Ifs::Plain(",".into()));
+    assert_eq!(parser.parse_line(br#"a,"b"#), None);
+    assert!(matches!(parser.finish(), Err(Error::Failed(e)) if e.to_string().contains("unterminated quote")));
+}
+
+#[test]
+fn justify_ignores_colours() {
+    let rows = [row(&["\x1b[31mred\x1b[0m", "x"]), row(&["longer", "y"]), row(&["z"])];
+    assert_eq!(dsv::justify(&rows, " "), ["\x1b[31mred\x1b[0m    x", "longer y", "z"]);
+}
+
+#[test]
+fn column_slicer_errors() {
+    assert!(matches!(ColumnSlicer::new(&["(".to_owned()], true), Err(Error::Failed(_))));
+
+    let mut slicer = ColumnSlicer::new(&["nmae".to_owned()], false).unwrap();
+    let result = slicer.make_header_map(&row(&["name", "size"]), false);
+    assert!(matches!(result, Err(Error::Failed(e)) if e.to_string().contains("did you mean \"name\"")));
+    assert!(slicer.make_header_map(&row(&["name", "size"]), true).is_ok());
+}
@@ -0,0 +1,110 @@
+use std::process::ExitCode;
+use dsv::{Pipeline, Error};
+
+fn run(pipeline: Pipeline, input: &'static str) -> (ExitCode, String) {
+    let (code, output) = pipeline.output(input.as_bytes()).unwrap();
+    (code, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn stages() {
+    let pipeline = Pipeline::new().option("--csv").grep("a").sort(["size"]).cut(["name"]);
+    let (code, output) = run(pipeline, "name,size\nbanana,3\ncarrot,1\napple,2\n");
+    assert_eq!(code, ExitCode::SUCCESS);
+    assert_eq!(output, "name\ncarrot\napple\nbanana\n");
+}
+
+#[test]
+fn head_and_tail() {
+    let input = "n\n1\n2\n3\n4\n";
+    assert_eq!(run(Pipeline::new().option("--csv").head(2), input).1, "n\n1\n2\n");
+    assert_eq!(run(Pipeline::new().option("--csv").tail(1), input).1, "n\n4\n");
+}
+
+#[test]
+fn no_match_is_a_failure_code() {
+    let (code, output) = run(Pipeline::new().option("--csv").grep("zzz"), "a\nx\ny\n");
+    assert_eq!(code, ExitCode::FAILURE);
+    assert_eq!(output, "a\n");
+}
+
+#[test]
+fn usage_error() {
+    let result = Pipeline::new().stage("sort", ["--no-such-option"]).output(&b""[..]);
+    assert!(matches!(result, Err(Error::Usage(e)) if e.contains("--no-such-option")));
+}
+
+#[test]
+fn failed_error() {
+    let result = Pipeline::new().option("--csv").cut(["nmae"]).output(&b"name,size\nx,1\n"[..]);
+    let Err(Error::Failed(e)) = result else { panic!("expected a failure, got {result:?}") };
+    assert!(e.to_string().contains("did you mean \"name\""), "{e}");
+}
+
+#[test]
+fn alignment_follows_the_columns() {
+    let input = "| name | size | pct |\n|:--|--:|:-:|\n| a | 1 | 2 |\n";
+    let pipeline = Pipeline::new().stage("frommarkdown", std::iter::empty::<&str>()).cut(["pct,size"]).stage("tomarkdown", std::iter::empty::<&str>());
+    let (_, output) = run(pipeline, input);
+    assert_eq!(output.lines().nth(1), Some("| :-: | ---: |"));
+}
+
+#[test]
+fn alignment_follows_added_columns() {
+    let input = "| k | v |\n|:--|--:|\n| a | 10 |\n";
+    let markdown = |pipeline: Pipeline| pipeline.stage("tomarkdown", std::iter::empty::<&str>());
+    let pipeline = Pipeline::new().stage("frommarkdown", std::iter::empty::<&str>()).stage("grep", ["-n", "a"]);
+    let (_, output) = run(markdown(pipeline), input);
+    assert_eq!(output.lines().nth(1), Some("| - |:--| -: |"));
+    let pipeline = Pipeline::new().stage("frommarkdown", std::iter::empty::<&str>()).stage("cat", ["-n", "--filename-column", "file"]);
+    let (_, output) = run(markdown(pipeline), input);
+    assert_eq!(output.lines().nth(1), Some("| - | ---- |:--| -: |"));
+}
+
+#[test]
+fn xargs_unknown_placeholder() {
+    let result = Pipeline::new().option("--csv").stage("xargs", ["echo", "{nmae}"]).output(&b"name,size\nx,1\n"[..]);
+    let Err(Error::Failed(e)) = result else { panic!("expected a failure, got {result:?}") };
+    assert!(e.to_string().contains("did you mean \"name\""), "{e}");
+}
+
+#[test]
+fn infer_schema_constraints_are_opt_in() {
+    let input = "id,name\n1,a\n2,b\n";
+    let (_, output) = run(Pipeline::new().option("--csv").stage("infer-schema", ["--as", "schema"]), input);
+    assert!(!output.contains("unique") && !output.contains("min"), "{output}");
+    let (_, output) = run(Pipeline::new().option("--csv").stage("infer-schema", ["--as", "schema", "--unique", "--range"]), input);
+    assert!(output.contains("\"unique\": true") && output.contains("\"max\": 2"), "{output}");
+}
+
+#[test]
+fn wide_characters_take_two_columns() {
+    let pipeline = Pipeline::new().option("--csv").stage("tomarkdown", std::iter::empty::<&str>());
+    let (_, output) = run(pipeline, "a,b\n日本語,x\nabc,y\n");
+    assert_eq!(output.lines().nth(2), Some("| 日本語 | x |"));
+    assert_eq!(output.lines().nth(3), Some("| abc    | y |"));
+}
+
+#[test]
+fn null_string_is_for_delimited_output() {
+    let input = "a,b\nx,\n";
+    let (_, output) = run(Pipeline::new().option("--csv").option("--null-string").option("NULL").stage("cat", std::iter::empty::<&str>()), input);
+    assert_eq!(output, "a,b\nx,NULL\n");
+    let (_, output) = run(Pipeline::new().option("--csv").option("--null-string").option("NULL").stage("tojson", std::iter::empty::<&str>()), input);
+    assert_eq!(output, "{\"a\":\"x\",\"b\":null}\n");
+    let (_, output) = run(Pipeline::new().option("--csv").option("--null-string").option("NULL").stage("tomarkdown", std::iter::empty::<&str>()), input);
+    assert_eq!(output.lines().nth(2), Some("| x |   |"));
+}
+
+#[test]
+fn markdown_align_unknown_field() {
+    let input = "name,size\na,1\n";
+    let result = Pipeline::new().option("--csv").stage("tomarkdown", ["--align", "nmae:center"]).output(input.as_bytes());
+    let Err(Error::Failed(e)) = result else { panic!("expected a failure, got {result:?}") };
+    assert!(e.to_string().contains("did you mean \"name\""), "{e}");
+
+    let (_, output) = run(Pipeline::new().option("--csv").option("--lenient-fields").stage("tomarkdown", ["--align", "nmae:center"]), input);
+    assert_eq!(output.lines().nth(1), Some("| ---- | ---- |"));
+    let (_, output) = run(Pipeline::new().option("--csv").stage("tomarkdown", ["--align", "name:center,2:right"]), input);
+    assert_eq!(output.lines().nth(1), Some("| :--: | ---: |"));
+}
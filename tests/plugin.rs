@@ -0,0 +1,43 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+#[test]
+fn plugin_sees_rows_before_eof() {
+    let dir = std::env::temp_dir().join(format!("dsv-plugin-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // echoes every message back
+    let plugin = dir.join("dsv-echo");
+    std::fs::write(&plugin, "#!/bin/sh\nwhile IFS= read -r line; do printf '%s\\n' \"$line\"; done\n").unwrap();
+    std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let path = std::env::join_paths(std::iter::once(dir.clone()).chain(std::env::split_paths(&std::env::var_os("PATH").unwrap()))).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_dsv"))
+        .args(["--csv", "-H", "echo"])
+        .env("PATH", path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // keep stdin open, like tail -f
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"a,b\n1,2\n").unwrap();
+    stdin.flush().unwrap();
+
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in stdout.lines() {
+            let _ = sender.send(line.unwrap());
+        }
+    });
+    let lines: Vec<_> = (0..2).map(|_| receiver.recv_timeout(Duration::from_secs(10))).collect();
+
+    drop(stdin);
+    let status = child.wait().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(lines.into_iter().collect::<Result<Vec<_>, _>>().unwrap(), ["a,b", "1,2"]);
+    assert!(status.success());
+}